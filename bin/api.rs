@@ -0,0 +1,413 @@
+//! To build the binary:
+//!
+//!     `cargo build --release --bin api`
+//!
+//! Serves the VectorX integration API (dataRoot proofs, range and health) from the data indexed by
+//! the events service.
+//!
+//!
+//!
+
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use ethers::contract::abigen;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, H256};
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use vectorx::input::types::StoredDataRootProof;
+use vectorx::input::{RedisClient, RpcDataFetcher};
+
+// Note: Update ABI when updating contract.
+abigen!(VectorX, "./abi/VectorX.abi.json",);
+
+// Port the API listens on, if not configured.
+const DEFAULT_API_PORT: u16 = 3000;
+
+struct ApiState {
+    redis_client: RedisClient,
+    // Avail data fetchers, keyed by lowercase chain name (e.g. goldberg).
+    data_fetchers: HashMap<String, RpcDataFetcher>,
+    // Ethereum providers, keyed by chain id.
+    providers: HashMap<u64, Arc<Provider<Http>>>,
+}
+
+type SharedState = Arc<ApiState>;
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn bad_request(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message)
+}
+
+fn not_found(message: String) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, message)
+}
+
+fn bad_gateway(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_GATEWAY, message)
+}
+
+fn internal_error(message: String) -> ApiError {
+    ApiError(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+// Maps a failure of the Redis store the API serves from to a 500.
+fn redis_error(e: anyhow::Error) -> ApiError {
+    internal_error(format!("Failed to access the indexed data: {}", e))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProofQuery {
+    chain_name: String,
+    contract_chain_id: u64,
+    contract_address: String,
+    block_number: Option<u32>,
+    block_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RangeQuery {
+    contract_chain_id: u64,
+    contract_address: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HealthQuery {
+    chain_name: String,
+    contract_chain_id: u64,
+    contract_address: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimelineQuery {
+    contract_chain_id: u64,
+    contract_address: String,
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+    // Only return entries with this name (e.g. AuthoritySetStored).
+    name: Option<String>,
+}
+
+impl ApiState {
+    fn data_fetcher(&self, chain_name: &str) -> Result<RpcDataFetcher, ApiError> {
+        self.data_fetchers
+            .get(&chain_name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| bad_request(format!("Unsupported chain {}", chain_name)))
+    }
+
+    fn contract(
+        &self,
+        chain_id: u64,
+        address: Address,
+    ) -> Result<VectorX<Provider<Http>>, ApiError> {
+        let provider = self
+            .providers
+            .get(&chain_id)
+            .ok_or_else(|| bad_request(format!("Unsupported contract chain id {}", chain_id)))?;
+        Ok(VectorX::new(address, provider.clone()))
+    }
+}
+
+fn parse_address(contract_address: &str) -> Result<Address, ApiError> {
+    contract_address
+        .parse::<Address>()
+        .map_err(|_| bad_request(format!("Invalid contract address {}", contract_address)))
+}
+
+// Builds and stores the proofs of the indexed range containing the block, for ranges indexed before
+// the events service stored proofs. The commitment recomputed from the Avail headers must match the
+// stored commitment, since proofs against any other commitment would not verify on-chain.
+async fn compute_proof(
+    redis_client: &mut RedisClient,
+    data_fetcher: &mut RpcDataFetcher,
+    chain_id: u64,
+    address: Address,
+    block_number: u32,
+) -> Result<StoredDataRootProof, ApiError> {
+    let range = redis_client
+        .get_data_commitment_range_for_block(chain_id, address.0.to_vec(), block_number)
+        .await
+        .map_err(redis_error)?
+        .ok_or_else(|| not_found(format!("Block {} is not in a stored range", block_number)))?;
+
+    let headers = data_fetcher
+        .get_block_headers_range(range.start + 1, range.end)
+        .await
+        .map_err(|e| bad_gateway(format!("Failed to get the Avail headers: {}", e)))?;
+    let (_, data_commitment) = RpcDataFetcher::compute_merkle_root_commitments(&headers);
+    if data_commitment != range.data_commitment {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!(
+                "The data commitment of range {}-{} does not match the Avail headers",
+                range.start, range.end
+            ),
+        ));
+    }
+
+    let proofs = RpcDataFetcher::compute_data_root_proofs(range.start, range.end, &headers);
+    let proof = proofs
+        .iter()
+        .find(|proof| proof.block_number == block_number)
+        .cloned()
+        .ok_or_else(|| {
+            not_found(format!(
+                "Block {} is not in range {}-{}",
+                block_number, range.start, range.end
+            ))
+        })?;
+    redis_client
+        .add_data_root_proofs(chain_id, address.0.to_vec(), proofs)
+        .await
+        .map_err(redis_error)?;
+
+    Ok(proof)
+}
+
+async fn get_proof(
+    State(state): State<SharedState>,
+    Query(query): Query<ProofQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let address = parse_address(&query.contract_address)?;
+    let mut data_fetcher = state.data_fetcher(&query.chain_name)?;
+    let mut redis_client = state.redis_client.clone();
+
+    match (query.block_number, query.block_hash) {
+        (Some(block_number), _) => {
+            let proof = match redis_client
+                .get_data_root_proof(query.contract_chain_id, address.0.to_vec(), block_number)
+                .await
+                .map_err(redis_error)?
+            {
+                Some(proof) => proof,
+                None => {
+                    compute_proof(
+                        &mut redis_client,
+                        &mut data_fetcher,
+                        query.contract_chain_id,
+                        address,
+                        block_number,
+                    )
+                    .await?
+                }
+            };
+            Ok(Json(json!({ "data": proof })))
+        }
+        (None, Some(block_hash)) => {
+            let block_hash = block_hash
+                .parse::<H256>()
+                .map_err(|_| bad_request(format!("Invalid block hash {}", block_hash)))?;
+            let proof = match redis_client
+                .get_data_root_proof_by_hash(
+                    query.contract_chain_id,
+                    address.0.to_vec(),
+                    block_hash,
+                )
+                .await
+                .map_err(redis_error)?
+            {
+                Some(proof) => proof,
+                None => {
+                    let header = data_fetcher
+                        .get_header_by_hash(block_hash)
+                        .await
+                        .map_err(|e| bad_gateway(format!("Failed to get the Avail header: {}", e)))?
+                        .ok_or_else(|| not_found(format!("Unknown block hash {:?}", block_hash)))?;
+                    let proof = compute_proof(
+                        &mut redis_client,
+                        &mut data_fetcher,
+                        query.contract_chain_id,
+                        address,
+                        header.number,
+                    )
+                    .await?;
+                    // The proof is built for the canonical block at the height of the header, which
+                    // is another block if the hash is of a fork.
+                    if proof.block_hash != block_hash {
+                        return Err(not_found(format!(
+                            "Block {:?} is not a canonical block in a stored range",
+                            block_hash
+                        )));
+                    }
+                    proof
+                }
+            };
+
+            // Responses to block hash queries do not include the block number.
+            let mut proof = serde_json::to_value(proof).unwrap();
+            proof.as_object_mut().unwrap().remove("blockNumber");
+            Ok(Json(json!({ "data": proof })))
+        }
+        (None, None) => Err(bad_request(
+            "One of blockNumber or blockHash must be set".to_string(),
+        )),
+    }
+}
+
+async fn get_range(
+    State(state): State<SharedState>,
+    Query(query): Query<RangeQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let address = parse_address(&query.contract_address)?;
+    let mut redis_client = state.redis_client.clone();
+
+    let ranges = redis_client
+        .get_data_commitment_ranges(query.contract_chain_id, address.0.to_vec())
+        .await
+        .map_err(redis_error)?;
+    let (Some(first), Some(last)) = (ranges.first(), ranges.last()) else {
+        return Err(not_found(format!(
+            "No ranges stored for {:?} on chain {}",
+            address, query.contract_chain_id
+        )));
+    };
+
+    Ok(Json(json!({
+        "data": { "rangeStart": first.start, "rangeEnd": last.end }
+    })))
+}
+
+async fn get_timeline(
+    State(state): State<SharedState>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let address = parse_address(&query.contract_address)?;
+    let mut redis_client = state.redis_client.clone();
+
+    let mut events = redis_client
+        .get_contract_events(
+            query.contract_chain_id,
+            address.0.to_vec(),
+            query.from_block.unwrap_or(0),
+            query.to_block.unwrap_or(u64::MAX),
+        )
+        .await
+        .map_err(redis_error)?;
+    if let Some(name) = query.name {
+        events.retain(|event| event.name == name);
+    }
+
+    Ok(Json(json!({ "data": events })))
+}
+
+async fn get_health(
+    State(state): State<SharedState>,
+    Query(query): Query<HealthQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let address = parse_address(&query.contract_address)?;
+    let mut data_fetcher = state.data_fetcher(&query.chain_name)?;
+    let contract = state.contract(query.contract_chain_id, address)?;
+    let mut redis_client = state.redis_client.clone();
+
+    let last_log_block = redis_client
+        .get_last_log_block(query.contract_chain_id, address.0.to_vec())
+        .await
+        .map_err(redis_error)?
+        .ok_or_else(|| {
+            not_found(format!(
+                "No events indexed for {:?} on chain {}",
+                address, query.contract_chain_id
+            ))
+        })?;
+    let eth_head = contract
+        .client()
+        .get_block_number()
+        .await
+        .map_err(|e| bad_gateway(format!("Failed to get the Ethereum head: {}", e)))?
+        .as_u64();
+
+    let latest_block = contract
+        .latest_block()
+        .await
+        .map_err(|e| bad_gateway(format!("Failed to get the contract's latest block: {}", e)))?;
+    let avail_head = data_fetcher
+        .get_head()
+        .await
+        .map_err(|e| bad_gateway(format!("Failed to get the Avail head: {}", e)))?
+        .number;
+
+    Ok(Json(json!({
+        "data": {
+            "ethBlockSinceLastLog": eth_head.saturating_sub(last_log_block),
+            "blocksBehindHead": avail_head.saturating_sub(latest_block),
+        }
+    })))
+}
+
+#[tokio::main]
+async fn main() {
+    env::set_var("RUST_LOG", "info");
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    // Routing is configured per chain with the following environment variables:
+    //  {chainName}_AVAIL_URL: The Avail RPC of the Avail chain `chainName` (e.g. GOLDBERG_AVAIL_URL).
+    //  ETHEREUM_RPC_URL_{chainId}: An Ethereum RPC for the chain with id `chainId` that VectorX
+    //      contracts are deployed on (e.g. ETHEREUM_RPC_URL_11155111).
+    // Contracts are routed by contractChainId and contractAddress to the ranges indexed for them by
+    // the events service.
+    let mut data_fetchers = HashMap::new();
+    let mut providers = HashMap::new();
+    for (key, value) in env::vars() {
+        if let Some(chain_name) = key.strip_suffix("_AVAIL_URL") {
+            let chain_name = chain_name.to_lowercase();
+            info!("Serving Avail chain {}.", chain_name);
+            let data_fetcher = RpcDataFetcher::with_avail(&value, &chain_name).await;
+            data_fetchers.insert(chain_name, data_fetcher);
+        } else if let Some(chain_id) = key.strip_prefix("ETHEREUM_RPC_URL_") {
+            let chain_id = chain_id
+                .parse::<u64>()
+                .unwrap_or_else(|_| panic!("invalid {}", key));
+            info!("Serving contracts on chain {}.", chain_id);
+            let provider = Provider::<Http>::try_from(value.as_str())
+                .unwrap_or_else(|_| panic!("invalid {}", key));
+            providers.insert(chain_id, Arc::new(provider));
+        }
+    }
+
+    let mut port = DEFAULT_API_PORT;
+    if let Ok(port_env) = env::var("API_PORT") {
+        port = port_env.parse::<u16>().expect("invalid API_PORT");
+    }
+
+    let state = Arc::new(ApiState {
+        redis_client: RedisClient::new().await,
+        data_fetchers,
+        providers,
+    });
+
+    let app = Router::new()
+        .route("/api/integrations/vectorx", get(get_proof))
+        .route("/api/integrations/vectorx/range", get(get_range))
+        .route("/api/integrations/vectorx/health", get(get_health))
+        .route("/api/integrations/vectorx/timeline", get(get_timeline))
+        .with_state(state);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    info!("Listening on {}.", addr);
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .expect("API server failed");
+}
@@ -1,67 +1,1132 @@
-use std::env;
+use std::cmp::min;
+use std::collections::HashSet;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fs};
 
 use alloy_sol_types::{sol, SolType};
-use ethers::contract::abigen;
-use ethers::core::types::{Address, Filter};
-use ethers::providers::{Middleware, Provider, StreamExt, Ws};
-use log::info;
-use vectorx::input::{DataCommitmentRange, RpcDataFetcher};
+use anyhow::Result;
+use avail_subxt::primitives::Header;
+use ethers::abi::{AbiDecode, RawLog};
+use ethers::contract::{abigen, EthEvent, EthLogDecode};
+use ethers::core::types::{Address, Filter, Log, H256, U256};
+use ethers::providers::{Http, JsonRpcClient, Middleware, Provider, StreamExt, Ws};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use vectorx::alert::Alerter;
+use vectorx::consts::MAX_NUM_HEADERS;
+use vectorx::input::types::{StoredCommitmentMismatch, StoredContractEvent, StoredContractState};
+use vectorx::input::{compute_range_hash, DataCommitmentRange, RpcDataFetcher};
 
 // Note: Update ABI when updating contract.
 abigen!(VectorX, "./abi/VectorX.abi.json",);
 
+// The calls a Safe makes to the contract on behalf of the guardian.
+abigen!(
+    Safe,
+    r#"[
+        function execTransaction(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, bytes signatures) returns (bool)
+    ]"#,
+);
+abigen!(
+    MultiSend,
+    r#"[
+        function multiSend(bytes transactions)
+    ]"#,
+);
+
 type HeaderRangeCommitmentStoredTuple = sol! { tuple(uint32, uint32, bytes32, bytes32) };
 
-sol! { struct RangeHashInput {
-    uint32 trusted_block;
-    uint32 end_block;
-} }
+// Number of Ethereum blocks queried per eth_getLogs request when backfilling.
+const BACKFILL_PAGE_SIZE: u64 = 10_000;
 
-async fn listen_for_events(ethereum_ws: &str, contract_address: &str) {
-    let address = contract_address
-        .parse::<Address>()
-        .expect("invalid address");
+// Number of confirmations before an event is persisted, if not configured.
+const DEFAULT_CONFIRMATIONS: u64 = 12;
 
-    let client = Provider::<Ws>::connect(ethereum_ws)
-        .await
-        .expect("could not connect to client");
+// Number of confirmed Ethereum blocks between reconcile passes.
+const RECONCILE_INTERVAL_BLOCKS: u64 = 300;
+
+// Number of Ethereum blocks before the previous reconcile point whose ranges are reconciled again,
+// as a reorg deeper than the confirmations may still reach them.
+const RECONCILE_REORG_DEPTH: u64 = 128;
+
+// Path of the deployments config, if EVENTS_CONFIG is not set.
+const DEFAULT_CONFIG_PATH: &str = "events.toml";
+
+// Delay before restarting a deployment's indexer. Doubles on each consecutive failure, up to
+// MAX_RESTART_DELAY, and resets once the indexer has run for MAX_RESTART_DELAY.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(5);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+
+// Interval between attempts to subscribe through the WS again while polling over HTTP.
+const WS_RETRY_INTERVAL: Duration = Duration::from_secs(600);
+
+// Number of Ethereum blocks between samples of the contract state when detecting guardian
+// interventions that emit no events.
+const STATE_SAMPLE_INTERVAL: u64 = 100;
 
-    let chain_id = client.get_chainid().await.unwrap();
+// Interval between eth_blockNumber polls when polling over HTTP, if not configured.
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 12;
+
+// WS subscriptions can silently stop delivering blocks. If no block or log arrives within this
+// duration, the subscription is considered dead.
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A VectorX deployment to index.
+#[derive(Deserialize, Debug, Clone)]
+struct Deployment {
+    /// Name of the Avail chain the contract is a light client of (e.g. goldberg). Also used as the
+    /// Avail chain id in Redis keys.
+    chain_name: String,
+    /// Ethereum WS for the chain the contract is deployed on. Events are streamed through WS
+    /// subscriptions when set.
+    ethereum_ws: Option<String>,
+    /// Ethereum HTTP RPC for the chain the contract is deployed on. Events are polled with
+    /// eth_getLogs when the WS is not set, cannot be connected to or its subscriptions die.
+    ethereum_rpc_url: Option<String>,
+    /// Interval between polls for new blocks over HTTP.
+    #[serde(default = "default_poll_interval_secs")]
+    poll_interval_secs: u64,
+    contract_address: String,
+    /// The Ethereum block to backfill events from. Defaults to the block the contract was deployed
+    /// in.
+    start_block: Option<u64>,
+    /// The number of confirmations before an event is persisted.
+    #[serde(default = "default_confirmations")]
+    confirmations: u64,
+    /// Avail RPC used to verify the commitments and build the data root proofs.
+    avail_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct EventsConfig {
+    deployments: Vec<Deployment>,
+}
+
+fn default_confirmations() -> u64 {
+    DEFAULT_CONFIRMATIONS
+}
 
-    info!(
-        "Listening for VectorX events on chain {} at address: {}",
-        chain_id, contract_address
-    );
+fn default_poll_interval_secs() -> u64 {
+    DEFAULT_POLL_INTERVAL_SECS
+}
 
-    let mut data_fetcher = RpcDataFetcher::new().await;
+// Reads the deployments config. JSON is used for paths ending in .json, TOML otherwise.
+fn read_config(path: &str) -> EventsConfig {
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+    if path.ends_with(".json") {
+        serde_json::from_str(&contents).unwrap_or_else(|e| panic!("invalid {}: {}", path, e))
+    } else {
+        toml::from_str(&contents).unwrap_or_else(|e| panic!("invalid {}: {}", path, e))
+    }
+}
 
-    let client = Arc::new(client);
+// Indexes the events of a deployment over either transport. Range processing is the same for both,
+// only how new blocks and removed logs are observed differs.
+struct EventIndexer<P: JsonRpcClient> {
+    client: Arc<Provider<P>>,
+    contract: VectorX<Provider<P>>,
+    data_fetcher: RpcDataFetcher,
+    alerter: Alerter,
+    chain_id: u64,
+    address: Address,
+    filter: Filter,
+    confirmations: u64,
+    // The last Ethereum block whose events have been fully processed.
+    checkpoint: u64,
+    // The checkpoint at the last reconcile pass.
+    last_reconciled_block: u64,
+}
 
-    let header_range_filter = Filter::new()
-        .address(address)
-        .event("HeaderRangeCommitmentStored(uint32,uint32,bytes32,bytes32)");
+// The stored ranges with a HeaderRangeCommitmentStored event among the events.
+fn logged_ranges(
+    ranges: Vec<DataCommitmentRange>,
+    events: &[StoredContractEvent],
+) -> Vec<DataCommitmentRange> {
+    let logged: HashSet<(u64, u64)> = events
+        .iter()
+        .filter(|event| event.name == "HeaderRangeCommitmentStored")
+        .filter_map(|event| {
+            Some((
+                event.data["startBlock"].as_u64()?,
+                event.data["endBlock"].as_u64()?,
+            ))
+        })
+        .collect();
+    ranges
+        .into_iter()
+        .filter(|range| logged.contains(&(range.start as u64, range.end as u64)))
+        .collect()
+}
 
-    let mut stream = client.subscribe_logs(&header_range_filter).await.unwrap();
-    while let Some(log) = stream.next().await {
-        let log_bytes = log.data;
-        let decoded = HeaderRangeCommitmentStoredTuple::abi_decode(&log_bytes.0, true).unwrap();
+// Decodes the range and the state commitment from a HeaderRangeCommitmentStored log.
+fn decode_range(log: &Log) -> (DataCommitmentRange, Vec<u8>) {
+    let decoded = HeaderRangeCommitmentStoredTuple::abi_decode(&log.data.0, true).unwrap();
 
-        let trusted_block = decoded.0;
-        let end_block = decoded.1;
-        let expected_data_commitment: Vec<u8> = decoded.2.to_vec();
-        let expected_data_commitment: [u8; 32] = expected_data_commitment.try_into().unwrap();
+    let trusted_block = decoded.0;
+    let end_block = decoded.1;
+    let expected_data_commitment: Vec<u8> = decoded.2.to_vec();
+    let expected_state_commitment: Vec<u8> = decoded.3.to_vec();
 
-        let data_commitment_range = DataCommitmentRange {
+    (
+        DataCommitmentRange {
             start: trusted_block,
             end: end_block,
-            data_commitment: expected_data_commitment.to_vec(),
+            data_commitment: expected_data_commitment,
+        },
+        expected_state_commitment,
+    )
+}
+
+// Decodes a log of the contract into its event name and fields, for the contract's timeline.
+fn decode_event(log: &Log) -> Option<(String, Value)> {
+    let event = VectorXEvents::decode_log(&RawLog::from(log.clone())).ok()?;
+    let decoded = match event {
+        VectorXEvents::AdminChangedFilter(e) => (
+            "AdminChanged",
+            json!({ "previousAdmin": e.previous_admin, "newAdmin": e.new_admin }),
+        ),
+        VectorXEvents::AuthoritySetStoredFilter(e) => (
+            "AuthoritySetStored",
+            json!({
+                "authoritySetId": e.authority_set_id,
+                "authoritySetHash": H256::from(e.authority_set_hash),
+            }),
+        ),
+        VectorXEvents::BeaconUpgradedFilter(e) => ("BeaconUpgraded", json!({ "beacon": e.beacon })),
+        VectorXEvents::HeadUpdateFilter(e) => (
+            "HeadUpdate",
+            json!({
+                "blockNumber": e.block_number,
+                "headerHash": H256::from(e.header_hash),
+            }),
+        ),
+        VectorXEvents::HeaderRangeCommitmentStoredFilter(e) => (
+            "HeaderRangeCommitmentStored",
+            json!({
+                "startBlock": e.start_block,
+                "endBlock": e.end_block,
+                "dataCommitment": H256::from(e.data_commitment),
+                "stateCommitment": H256::from(e.state_commitment),
+            }),
+        ),
+        VectorXEvents::HeaderRangeRequestedFilter(e) => (
+            "HeaderRangeRequested",
+            json!({
+                "trustedBlock": e.trusted_block,
+                "trustedHeader": H256::from(e.trusted_header),
+                "authoritySetId": e.authority_set_id,
+                "authoritySetHash": H256::from(e.authority_set_hash),
+                "targetBlock": e.target_block,
+            }),
+        ),
+        VectorXEvents::InitializedFilter(e) => ("Initialized", json!({ "version": e.version })),
+        VectorXEvents::RoleAdminChangedFilter(e) => (
+            "RoleAdminChanged",
+            json!({
+                "role": H256::from(e.role),
+                "previousAdminRole": H256::from(e.previous_admin_role),
+                "newAdminRole": H256::from(e.new_admin_role),
+            }),
+        ),
+        VectorXEvents::RoleGrantedFilter(e) => (
+            "RoleGranted",
+            json!({ "role": H256::from(e.role), "account": e.account, "sender": e.sender }),
+        ),
+        VectorXEvents::RoleRevokedFilter(e) => (
+            "RoleRevoked",
+            json!({ "role": H256::from(e.role), "account": e.account, "sender": e.sender }),
+        ),
+        VectorXEvents::RotateRequestedFilter(e) => (
+            "RotateRequested",
+            json!({
+                "currentAuthoritySetId": e.current_authority_set_id,
+                "currentAuthoritySetHash": H256::from(e.current_authority_set_hash),
+            }),
+        ),
+        VectorXEvents::UpgradedFilter(e) => {
+            ("Upgraded", json!({ "implementation": e.implementation }))
+        }
+    };
+    Some((decoded.0.to_string(), decoded.1))
+}
+
+fn is_range_log(log: &Log) -> bool {
+    log.topics.first() == Some(&HeaderRangeCommitmentStoredFilter::signature())
+}
+
+// Returns the interventions that changed the contract state from the state at start_block to the
+// state at end_block.
+fn detect_interventions(
+    start_state: &StoredContractState,
+    end_state: &StoredContractState,
+    start_block: u64,
+    end_block: u64,
+    head_updates: &[(u64, u32)],
+) -> Vec<&'static str> {
+    let mut interventions = Vec::new();
+    if start_state.frozen != end_state.frozen {
+        interventions.push("FreezeUpdated");
+    }
+    let head_updated = head_updates.iter().any(|(log_block, block_number)| {
+        *log_block > start_block
+            && *log_block <= end_block
+            && *block_number == end_state.latest_block
+    });
+    if start_state.latest_block != end_state.latest_block && !head_updated {
+        interventions.push("GenesisStateUpdated");
+    }
+    interventions
+}
+
+// Narrows down the changes of the contract state within (start, end] to the blocks they happened in
+// by bisection, with state_at returning the contract state at a block. Returns each block with a
+// change, in order, with the interventions at the block and the state after them.
+async fn bisect_interventions<F, Fut>(
+    start: u64,
+    start_state: StoredContractState,
+    end: u64,
+    end_state: StoredContractState,
+    head_updates: &[(u64, u32)],
+    mut state_at: F,
+) -> Result<Vec<(u64, Vec<&'static str>, StoredContractState)>>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<StoredContractState>>,
+{
+    let mut interventions = Vec::new();
+
+    // Segments are bisected depth first, left half first, so changes are found in order.
+    let mut segments = vec![(start, start_state, end, end_state)];
+    while let Some((start, start_state, end, end_state)) = segments.pop() {
+        let changes = detect_interventions(&start_state, &end_state, start, end, head_updates);
+        if changes.is_empty() {
+            continue;
+        }
+        if end == start + 1 {
+            interventions.push((end, changes, end_state));
+            continue;
+        }
+        let mid = start + (end - start) / 2;
+        let mid_state = state_at(mid).await?;
+        segments.push((mid, mid_state.clone(), end, end_state));
+        segments.push((start, start_state, mid, mid_state));
+    }
+    Ok(interventions)
+}
+
+// Decodes the transactions packed in the data of a MultiSend call. Each is encoded as operation (1
+// byte), to (20 bytes), value (32 bytes), data length (32 bytes) and data. Returns the target and
+// data of each transaction.
+fn decode_multi_send(transactions: &[u8]) -> Vec<(Address, Vec<u8>)> {
+    let mut calls = Vec::new();
+    let mut offset = 0;
+    while offset + 85 <= transactions.len() {
+        let to = Address::from_slice(&transactions[offset + 1..offset + 21]);
+        let data_length = U256::from_big_endian(&transactions[offset + 53..offset + 85]);
+        let data_start = offset + 85;
+        let Some(data_end) = usize::try_from(data_length)
+            .ok()
+            .and_then(|data_length| data_start.checked_add(data_length))
+        else {
+            break;
+        };
+        let Some(data) = transactions.get(data_start..data_end) else {
+            break;
+        };
+        calls.push((to, data.to_vec()));
+        offset = data_end;
+    }
+    calls
+}
+
+// Returns the data of the calls to the contract made by a transaction to `to`, with the Safe they
+// were made through. The transaction calls the contract directly, or through a Safe's
+// execTransaction, either as its call or as one of the calls of a MultiSend batch such as those of
+// the Safe Transaction Builder.
+fn contract_calls(
+    contract_address: Address,
+    to: Address,
+    input: &[u8],
+) -> Vec<(Vec<u8>, Option<Address>)> {
+    if to == contract_address {
+        return vec![(input.to_vec(), None)];
+    }
+
+    let Ok(exec) = ExecTransactionCall::decode(input) else {
+        return Vec::new();
+    };
+    if exec.to == contract_address {
+        return vec![(exec.data.to_vec(), Some(to))];
+    }
+    match MultiSendCall::decode(&exec.data) {
+        Ok(multi_send) => decode_multi_send(&multi_send.transactions)
+            .into_iter()
+            .filter(|(call_to, _)| *call_to == contract_address)
+            .map(|(_, data)| (data, Some(to)))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Binary search for the first block at which the contract has code.
+async fn find_deploy_block<P: JsonRpcClient>(client: &Provider<P>, address: Address) -> u64 {
+    let mut low = 0;
+    let mut high = client.get_block_number().await.unwrap().as_u64();
+    while low < high {
+        let mid = (low + high) / 2;
+        let code = client.get_code(address, Some(mid.into())).await.unwrap();
+        if code.is_empty() {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+impl<P: JsonRpcClient + 'static> EventIndexer<P> {
+    async fn new(client: Provider<P>, deployment: &Deployment) -> Self {
+        let address = deployment
+            .contract_address
+            .parse::<Address>()
+            .expect("invalid address");
+
+        let chain_id = client.get_chainid().await.unwrap().as_u64();
+
+        let mut data_fetcher = RpcDataFetcher::with_avail(
+            &deployment.avail_url,
+            &deployment.chain_name.to_lowercase(),
+        )
+        .await;
+
+        let client = Arc::new(client);
+        let contract = VectorX::new(address, client.clone());
+
+        // All events of the contract are indexed into its timeline.
+        let filter = Filter::new().address(address);
+
+        // Resume from the checkpoint if there is one. Otherwise, start from the configured start
+        // block or the block the contract was deployed in.
+        let checkpoint = data_fetcher
+            .redis_client
+            .get_events_checkpoint(chain_id, address.0.to_vec())
+            .await;
+        let checkpoint = match (checkpoint, deployment.start_block) {
+            (Some(checkpoint), _) => checkpoint,
+            (None, Some(start_block)) => start_block.saturating_sub(1),
+            (None, None) => find_deploy_block(&client, address).await.saturating_sub(1),
+        };
+
+        Self {
+            client,
+            contract,
+            data_fetcher,
+            alerter: Alerter::new(),
+            chain_id,
+            address,
+            filter,
+            confirmations: deployment.confirmations,
+            checkpoint,
+            last_reconciled_block: checkpoint,
+        }
+    }
+
+    // Recomputes the data and state commitments of the range from the Avail headers and compares
+    // them with the commitments emitted on-chain. Mismatches are alerted and marked in the store.
+    // Returns whether the commitments match.
+    async fn verify_range(
+        &mut self,
+        range: &DataCommitmentRange,
+        state_commitment: Vec<u8>,
+        headers: &[Header],
+    ) -> bool {
+        let (expected_state_commitment, expected_data_commitment) =
+            RpcDataFetcher::compute_merkle_root_commitments(headers);
+        if expected_data_commitment == range.data_commitment
+            && expected_state_commitment == state_commitment
+        {
+            return true;
+        }
+
+        self.alerter
+            .alert(&format!(
+                "Commitment mismatch for range {}-{} on chain {} at {:?}: data commitment 0x{} (expected 0x{}), state commitment 0x{} (expected 0x{}).",
+                range.start,
+                range.end,
+                self.chain_id,
+                self.address,
+                hex::encode(&range.data_commitment),
+                hex::encode(&expected_data_commitment),
+                hex::encode(&state_commitment),
+                hex::encode(&expected_state_commitment)
+            ))
+            .await;
+        self.data_fetcher
+            .redis_client
+            .add_commitment_mismatch(
+                self.chain_id,
+                self.address.0.to_vec(),
+                StoredCommitmentMismatch {
+                    start_block: range.start,
+                    end_block: range.end,
+                    data_commitment: range.data_commitment.clone(),
+                    expected_data_commitment,
+                    state_commitment,
+                    expected_state_commitment,
+                },
+            )
+            .await;
+        false
+    }
+
+    async fn process_log(&mut self, log: Log) {
+        let (data_commitment_range, state_commitment) = decode_range(&log);
+        let log_block = log.block_number.unwrap().as_u64();
+
+        let num_blocks = data_commitment_range
+            .end
+            .checked_sub(data_commitment_range.start);
+        // Ranges that cannot be verified are not stored, so the API never serves them.
+        let verifiable = match num_blocks {
+            None => {
+                self.alerter
+                    .alert(&format!(
+                        "Range {}-{} on chain {} at {:?} ends before it starts and cannot be verified.",
+                        data_commitment_range.start,
+                        data_commitment_range.end,
+                        self.chain_id,
+                        self.address
+                    ))
+                    .await;
+                false
+            }
+            Some(num_blocks) if num_blocks as usize > MAX_NUM_HEADERS => {
+                self.alerter
+                    .alert(&format!(
+                        "Range {}-{} on chain {} at {:?} is larger than {} blocks and cannot be verified.",
+                        data_commitment_range.start,
+                        data_commitment_range.end,
+                        self.chain_id,
+                        self.address,
+                        MAX_NUM_HEADERS
+                    ))
+                    .await;
+                false
+            }
+            Some(_) => {
+                // Fetch the headers once to verify the commitments and build the data root proofs.
+                let headers = self
+                    .data_fetcher
+                    .get_block_headers_range(
+                        data_commitment_range.start + 1,
+                        data_commitment_range.end,
+                    )
+                    .await
+                    .expect("Failed to get headers from Avail.");
+
+                // Proofs against the recomputed commitment would not verify against the commitment
+                // stored on-chain, so none are stored for a mismatched range.
+                if self
+                    .verify_range(&data_commitment_range, state_commitment, &headers)
+                    .await
+                {
+                    let proofs = RpcDataFetcher::compute_data_root_proofs(
+                        data_commitment_range.start,
+                        data_commitment_range.end,
+                        &headers,
+                    );
+                    self.data_fetcher
+                        .redis_client
+                        .add_data_root_proofs(self.chain_id, self.address.0.to_vec(), proofs)
+                        .await
+                        .expect("Failed to add data root proofs");
+                }
+                true
+            }
+        };
+
+        if verifiable {
+            self.data_fetcher
+                .redis_client
+                .add_data_commitment_range(
+                    self.chain_id,
+                    self.address.0.to_vec(),
+                    data_commitment_range,
+                )
+                .await;
+        }
+        self.data_fetcher
+            .redis_client
+            .set_last_log_block(self.chain_id, self.address.0.to_vec(), log_block)
+            .await;
+    }
+
+    // Adds a log to the contract's timeline, and processes it if it stores a new range. Returns the
+    // Ethereum block and the Avail block number of HeadUpdate events.
+    async fn process_event_log(&mut self, log: Log) -> Option<(u64, u32)> {
+        let Some((name, mut data)) = decode_event(&log) else {
+            warn!(
+                "Unknown event in transaction {:?} on chain {}.",
+                log.transaction_hash, self.chain_id
+            );
+            return None;
         };
 
-        data_fetcher
+        let log_block = log.block_number.unwrap().as_u64();
+        let head_update = match name.as_str() {
+            "HeadUpdate" => Some((log_block, data["blockNumber"].as_u64().unwrap() as u32)),
+            "HeaderRangeCommitmentStored" => {
+                self.annotate_guardian_call(&log, &mut data).await;
+                None
+            }
+            _ => None,
+        };
+
+        let event = StoredContractEvent {
+            block_number: log_block,
+            log_index: log.log_index.map(|log_index| log_index.as_u64()),
+            entry_index: None,
+            transaction_hash: log.transaction_hash,
+            name,
+            data,
+        };
+        self.data_fetcher
+            .redis_client
+            .add_contract_event(self.chain_id, self.address.0.to_vec(), event)
+            .await;
+
+        if is_range_log(&log) {
+            self.process_log(log).await;
+        }
+
+        head_update
+    }
+
+    // Ranges stored by the guardian through updateBlockRangeData emit the same events as proven
+    // ranges. If the transaction called the contract directly or through a Safe, the call that
+    // stored the range is decoded to mark the range and record the authority set it stored, which
+    // emits no AuthoritySetStored event.
+    async fn annotate_guardian_call(&self, log: &Log, data: &mut Value) {
+        let transaction = self
+            .client
+            .get_transaction(log.transaction_hash.unwrap())
+            .await
+            .unwrap();
+        let Some(transaction) = transaction else {
+            return;
+        };
+        let Some(to) = transaction.to else {
+            return;
+        };
+
+        let start_block = data["startBlock"].as_u64().unwrap() as u32;
+        let end_block = data["endBlock"].as_u64().unwrap() as u32;
+        for (call_data, safe) in contract_calls(self.address, to, &transaction.input) {
+            let Ok(call) = UpdateBlockRangeDataCall::decode(&call_data) else {
+                continue;
+            };
+            // A Safe batch can store several batches of ranges, so the call must store this one.
+            let stores_range = call
+                .start_blocks
+                .iter()
+                .zip(call.end_blocks.iter())
+                .any(|(start, end)| *start == start_block && *end == end_block);
+            if !stores_range {
+                continue;
+            }
+
+            data["guardianCall"] = json!("updateBlockRangeData");
+            data["endAuthoritySetId"] = json!(call.end_authority_set_id);
+            data["endAuthoritySetHash"] = json!(H256::from(call.end_authority_set_hash));
+            if let Some(safe) = safe {
+                data["safe"] = json!(safe);
+            }
+            return;
+        }
+    }
+
+    // The contract state at the block. Before the contract is deployed, e.g. at the checkpoint of a
+    // fresh start, it has no code and the calls fail, so its state is the default one.
+    async fn get_contract_state(&self, block: u64) -> Result<StoredContractState> {
+        let frozen = self.contract.frozen().block(block).call().await;
+        let latest_block = self.contract.latest_block().block(block).call().await;
+        match (frozen, latest_block) {
+            (Ok(frozen), Ok(latest_block)) => Ok(StoredContractState {
+                frozen,
+                latest_block,
+            }),
+            (Err(e), _) | (_, Err(e)) => {
+                let code = self
+                    .client
+                    .get_code(self.address, Some(block.into()))
+                    .await?;
+                if code.is_empty() {
+                    Ok(StoredContractState::default())
+                } else {
+                    Err(e.into())
+                }
+            }
+        }
+    }
+
+    // updateFreeze and updateGenesisState emit no events, so they are detected by comparing the
+    // contract state at consecutive samples, STATE_SAMPLE_INTERVAL blocks apart within
+    // (from_block, to_block]. A change of latestBlock without a HeadUpdate to the new block is a
+    // genesis state update. Each change is narrowed down to the block it happened in by bisection.
+    // On the first run, the state at from_block is the baseline.
+    async fn check_contract_state(
+        &mut self,
+        from_block: u64,
+        to_block: u64,
+        head_updates: &[(u64, u32)],
+    ) -> Result<()> {
+        let previous_state = self
+            .data_fetcher
+            .redis_client
+            .get_contract_state(self.chain_id, self.address.0.to_vec())
+            .await;
+        let mut state = match previous_state {
+            Some(previous_state) => previous_state,
+            None => self.get_contract_state(from_block).await?,
+        };
+
+        let mut sample_start = from_block;
+        while sample_start < to_block {
+            let sample_end = min(sample_start + STATE_SAMPLE_INTERVAL, to_block);
+            let sample_end_state = self.get_contract_state(sample_end).await?;
+
+            let indexer = &*self;
+            let interventions = bisect_interventions(
+                sample_start,
+                state,
+                sample_end,
+                sample_end_state.clone(),
+                head_updates,
+                |block| indexer.get_contract_state(block),
+            )
+            .await?;
+            for (block, changes, block_state) in interventions {
+                self.record_interventions(block, changes, &block_state)
+                    .await;
+            }
+
+            state = sample_end_state;
+            sample_start = sample_end;
+        }
+
+        self.data_fetcher
             .redis_client
-            .add_data_commitment_range(chain_id.as_u64(), address.0.to_vec(), data_commitment_range)
+            .set_contract_state(self.chain_id, self.address.0.to_vec(), state)
             .await;
+        Ok(())
+    }
+
+    // Adds the interventions detected at the block to the timeline.
+    async fn record_interventions(
+        &mut self,
+        block: u64,
+        names: Vec<&str>,
+        state: &StoredContractState,
+    ) {
+        for (entry_index, name) in names.into_iter().enumerate() {
+            let data = match name {
+                "FreezeUpdated" => json!({ "frozen": state.frozen }),
+                _ => {
+                    let header_hash = self
+                        .contract
+                        .block_height_to_header_hash(state.latest_block)
+                        .block(block)
+                        .call()
+                        .await
+                        .unwrap();
+                    json!({
+                        "latestBlock": state.latest_block,
+                        "headerHash": H256::from(header_hash),
+                    })
+                }
+            };
+            warn!(
+                "Guardian intervention {} on chain {} at {:?} in block {}: {}",
+                name, self.chain_id, self.address, block, data
+            );
+            self.data_fetcher
+                .redis_client
+                .add_contract_event(
+                    self.chain_id,
+                    self.address.0.to_vec(),
+                    StoredContractEvent {
+                        block_number: block,
+                        log_index: None,
+                        entry_index: Some(entry_index as u64),
+                        transaction_hash: None,
+                        name: name.to_string(),
+                        data,
+                    },
+                )
+                .await;
+        }
+    }
+
+    // Processes all logs in (checkpoint, to_block], paging eth_getLogs and saving a checkpoint
+    // after each page.
+    async fn process_confirmed_logs(&mut self, to_block: u64) {
+        let mut page_start = self.checkpoint + 1;
+        while page_start <= to_block {
+            let page_end = min(page_start + BACKFILL_PAGE_SIZE - 1, to_block);
+            let page_filter = self
+                .filter
+                .clone()
+                .from_block(page_start)
+                .to_block(page_end);
+
+            let logs = self.client.get_logs(&page_filter).await.unwrap();
+            let mut head_updates = Vec::new();
+            for log in logs {
+                if let Some(head_update) = self.process_event_log(log).await {
+                    head_updates.push(head_update);
+                }
+            }
+            // The page is processed again on the next head, as the checkpoint is not moved past it.
+            if let Err(e) = self
+                .check_contract_state(page_start - 1, page_end, &head_updates)
+                .await
+            {
+                error!(
+                    "Failed to check the contract state on chain {} up to block {}: {}",
+                    self.chain_id, page_end, e
+                );
+                return;
+            }
+
+            self.data_fetcher
+                .redis_client
+                .set_events_checkpoint(self.chain_id, self.address.0.to_vec(), page_end)
+                .await;
+            self.checkpoint = page_end;
+            page_start = page_end + 1;
+        }
+    }
+
+    // Removes the timeline entry of a log that was reorged out, if it was already persisted. For
+    // range logs, the range is also removed if it is not committed in the contract on the new chain.
+    async fn handle_removed_log(&mut self, log: Log) {
+        let log_block = log.block_number.unwrap().as_u64();
+        if log_block > self.checkpoint {
+            // The log has not been persisted yet.
+            return;
+        }
+
+        self.data_fetcher
+            .redis_client
+            .remove_contract_event(
+                self.chain_id,
+                self.address.0.to_vec(),
+                log_block,
+                log.log_index.unwrap().as_u64(),
+            )
+            .await;
+        if !is_range_log(&log) {
+            return;
+        }
+
+        let (range, _) = decode_range(&log);
+        let range_hash = compute_range_hash(range.start, range.end);
+        let on_chain_commitment = self
+            .contract
+            .data_root_commitments(range_hash.0)
+            .call()
+            .await
+            .unwrap();
+        if on_chain_commitment.to_vec() == range.data_commitment {
+            // The same commitment was included in the new chain.
+            return;
+        }
+
+        warn!(
+            "Range {}-{} on chain {} was reorged out after {} confirmations.",
+            range.start, range.end, self.chain_id, self.confirmations
+        );
+        self.data_fetcher
+            .redis_client
+            .remove_data_root_proofs(
+                self.chain_id,
+                self.address.0.to_vec(),
+                range.start,
+                range.end,
+            )
+            .await;
+        self.data_fetcher
+            .redis_client
+            .remove_data_commitment_range(self.chain_id, self.address.0.to_vec(), range)
+            .await;
+    }
+
+    // Compares the stored ranges logged since the previous reconcile point, less
+    // RECONCILE_REORG_DEPTH blocks, against the contract's dataRootCommitments mapping at the
+    // checkpoint. Older ranges were reconciled by earlier passes. Ranges that are not committed
+    // on-chain are removed, and ranges with a different on-chain commitment are replaced.
+    async fn reconcile(&mut self) {
+        let from_block = self
+            .last_reconciled_block
+            .saturating_sub(RECONCILE_REORG_DEPTH);
+        let events = self
+            .data_fetcher
+            .redis_client
+            .get_contract_events(
+                self.chain_id,
+                self.address.0.to_vec(),
+                from_block,
+                self.checkpoint,
+            )
+            .await
+            .expect("Failed to get the contract events");
+        let ranges = self
+            .data_fetcher
+            .redis_client
+            .get_data_commitment_ranges(self.chain_id, self.address.0.to_vec())
+            .await
+            .expect("Failed to get keys");
+        let ranges = logged_ranges(ranges, &events);
+        info!(
+            "Reconciling {} ranges logged since block {} on chain {} at block {}.",
+            ranges.len(),
+            from_block,
+            self.chain_id,
+            self.checkpoint
+        );
+
+        for range in ranges {
+            let range_hash = compute_range_hash(range.start, range.end);
+            let on_chain_commitment = self
+                .contract
+                .data_root_commitments(range_hash.0)
+                .block(self.checkpoint)
+                .call()
+                .await
+                .unwrap();
+            if on_chain_commitment.to_vec() == range.data_commitment {
+                continue;
+            }
+
+            warn!(
+                "Stored range {}-{} on chain {} does not match the contract.",
+                range.start, range.end, self.chain_id
+            );
+            self.data_fetcher
+                .redis_client
+                .remove_data_commitment_range(self.chain_id, self.address.0.to_vec(), range.clone())
+                .await;
+            if on_chain_commitment == [0u8; 32] {
+                self.data_fetcher
+                    .redis_client
+                    .remove_data_root_proofs(
+                        self.chain_id,
+                        self.address.0.to_vec(),
+                        range.start,
+                        range.end,
+                    )
+                    .await;
+            } else {
+                self.data_fetcher
+                    .redis_client
+                    .add_data_commitment_range(
+                        self.chain_id,
+                        self.address.0.to_vec(),
+                        DataCommitmentRange {
+                            data_commitment: on_chain_commitment.to_vec(),
+                            ..range
+                        },
+                    )
+                    .await;
+            }
+        }
+    }
+
+    // Backfills all confirmed blocks since the checkpoint, then reconciles the stored ranges.
+    async fn backfill(&mut self) {
+        let head = self.client.get_block_number().await.unwrap().as_u64();
+        info!(
+            "Backfilling events on chain {} from block {} to block {}.",
+            self.chain_id,
+            self.checkpoint + 1,
+            head.saturating_sub(self.confirmations)
+        );
+        self.process_confirmed_logs(head.saturating_sub(self.confirmations))
+            .await;
+        self.reconcile().await;
+        self.last_reconciled_block = self.checkpoint;
+
+        info!("Backfill complete on chain {}.", self.chain_id);
+    }
+
+    // Persists the events in blocks that have enough confirmations at the new head, and reconciles
+    // the stored ranges every RECONCILE_INTERVAL_BLOCKS confirmed blocks.
+    async fn handle_new_head(&mut self, head: u64) {
+        let confirmed_block = head.saturating_sub(self.confirmations);
+        if confirmed_block > self.checkpoint {
+            self.process_confirmed_logs(confirmed_block).await;
+        }
+
+        if self.checkpoint >= self.last_reconciled_block + RECONCILE_INTERVAL_BLOCKS {
+            self.reconcile().await;
+            self.last_reconciled_block = self.checkpoint;
+        }
+    }
+
+    // Polls for new blocks over HTTP, using the checkpoint as the eth_getLogs cursor. Removed logs
+    // are not observed when polling, so reorgs deeper than the confirmations are only repaired by
+    // reconcile. Returns after max_duration if set.
+    async fn run_polling(&mut self, poll_interval: Duration, max_duration: Option<Duration>) {
+        info!(
+            "Polling for VectorX events on chain {} at address: {:?}",
+            self.chain_id, self.address
+        );
+
+        let started = Instant::now();
+        self.backfill().await;
+
+        loop {
+            if max_duration.is_some_and(|max_duration| started.elapsed() >= max_duration) {
+                return;
+            }
+            tokio::time::sleep(poll_interval).await;
+            let head = self.client.get_block_number().await.unwrap().as_u64();
+            self.handle_new_head(head).await;
+        }
+    }
+}
+
+impl EventIndexer<Ws> {
+    // Streams new blocks and removed logs through WS subscriptions. Returns when the subscriptions
+    // cannot be made, e.g. when the endpoint does not support eth_subscribe, or when they end or
+    // stop delivering for SUBSCRIPTION_TIMEOUT.
+    async fn run_subscription(&mut self) {
+        info!(
+            "Listening for VectorX events on chain {} at address: {:?}",
+            self.chain_id, self.address
+        );
+
+        // Subscribe before backfilling, so no block or removed log is missed in between.
+        let client = self.client.clone();
+        let mut blocks = match client.subscribe_blocks().await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                warn!(
+                    "Could not subscribe to new blocks on chain {}: {}",
+                    self.chain_id, e
+                );
+                return;
+            }
+        };
+        let mut logs = match client.subscribe_logs(&self.filter).await {
+            Ok(logs) => logs,
+            Err(e) => {
+                warn!(
+                    "Could not subscribe to the logs on chain {}: {}",
+                    self.chain_id, e
+                );
+                return;
+            }
+        };
+
+        self.backfill().await;
+
+        loop {
+            tokio::select! {
+                block = blocks.next() => {
+                    let Some(block) = block else { break };
+                    self.handle_new_head(block.number.unwrap().as_u64()).await;
+                }
+                log = logs.next() => {
+                    let Some(log) = log else { break };
+
+                    // New logs are persisted once confirmed, so only removed logs are handled here.
+                    if log.removed == Some(true) {
+                        self.handle_removed_log(log).await;
+                    }
+                }
+                _ = tokio::time::sleep(SUBSCRIPTION_TIMEOUT) => {
+                    warn!(
+                        "No new blocks on chain {} for {:?}.",
+                        self.chain_id, SUBSCRIPTION_TIMEOUT
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Indexes the deployment through WS subscriptions if possible. If the WS is not set, cannot be
+// connected to or its subscriptions end, falls back to polling over HTTP. When the WS is set, the
+// subscriptions are retried every WS_RETRY_INTERVAL while polling.
+async fn listen_for_events(deployment: Deployment) {
+    loop {
+        if let Some(ethereum_ws) = &deployment.ethereum_ws {
+            match Provider::<Ws>::connect(ethereum_ws).await {
+                Ok(client) => {
+                    let mut indexer = EventIndexer::new(client, &deployment).await;
+                    indexer.run_subscription().await;
+                    warn!(
+                        "WS subscriptions for {} at {} ended.",
+                        deployment.chain_name, deployment.contract_address
+                    );
+                }
+                Err(e) => warn!(
+                    "Could not connect to the WS for {} at {}: {}",
+                    deployment.chain_name, deployment.contract_address, e
+                ),
+            }
+        }
+
+        let Some(ethereum_rpc_url) = &deployment.ethereum_rpc_url else {
+            return;
+        };
+        let client = Provider::<Http>::try_from(ethereum_rpc_url.as_str())
+            .expect("invalid ethereum_rpc_url");
+        let mut indexer = EventIndexer::new(client, &deployment).await;
+        let max_duration = deployment.ethereum_ws.as_ref().map(|_| WS_RETRY_INTERVAL);
+        indexer
+            .run_polling(
+                Duration::from_secs(deployment.poll_interval_secs),
+                max_duration,
+            )
+            .await;
+    }
+}
+
+// Runs the indexer of a deployment in its own task, and restarts it with backoff whenever it
+// panics or its subscriptions end. Indexing resumes from the checkpoint in Redis.
+async fn supervise(deployment: Deployment) {
+    let alerter = Alerter::new();
+    let mut restart_delay = MIN_RESTART_DELAY;
+    loop {
+        let started = Instant::now();
+        let result = tokio::spawn(listen_for_events(deployment.clone())).await;
+        match result {
+            Ok(()) => warn!(
+                "Indexer for {} at {} stopped, restarting.",
+                deployment.chain_name, deployment.contract_address
+            ),
+            Err(e) => {
+                alerter
+                    .alert(&format!(
+                        "Indexer for {} at {} failed, restarting: {}",
+                        deployment.chain_name, deployment.contract_address, e
+                    ))
+                    .await
+            }
+        }
+
+        if started.elapsed() >= MAX_RESTART_DELAY {
+            restart_delay = MIN_RESTART_DELAY;
+        }
+        tokio::time::sleep(restart_delay).await;
+        restart_delay = min(restart_delay * 2, MAX_RESTART_DELAY);
     }
 }
 
@@ -71,44 +1136,299 @@ async fn main() {
     dotenv::dotenv().ok();
     env_logger::init();
 
-    // List of Avail chains to index.
-    let chains = ["Couscous", "Goldberg"];
+    // The deployments to index are listed in the config file at EVENTS_CONFIG (TOML, or JSON if the
+    // path ends in .json). See events.example.toml.
+    let config_path = env::var("EVENTS_CONFIG").unwrap_or(DEFAULT_CONFIG_PATH.to_string());
+    let config = read_config(&config_path);
+    if config.deployments.is_empty() {
+        error!("No deployments configured in {}.", config_path);
+        return;
+    }
+
+    let mut join_handles = Vec::new();
+    for deployment in config.deployments {
+        if deployment.ethereum_ws.is_none() && deployment.ethereum_rpc_url.is_none() {
+            panic!(
+                "One of ethereum_ws or ethereum_rpc_url must be set for {} at {}",
+                deployment.chain_name, deployment.contract_address
+            );
+        }
+        info!(
+            "Indexing {} at {} for events!",
+            deployment.chain_name, deployment.contract_address
+        );
+        join_handles.push(tokio::spawn(supervise(deployment)));
+    }
+
+    // Deployments are restarted by their supervisor, so this only returns if a supervisor fails.
+    for handle in join_handles {
+        handle.await.expect("Supervisor failed");
+    }
+}
 
-    // For each Avail chain `chainName` to index, set the following environment variables:
-    //  {chainName}_ETHEREUM_WS: An Ethereum WS for the chain the deployed VectorX contract is on.
-    //  {chainName}_CONTRACT_ADDRESS: The address of the deployed VectorX contract.
-    // Note: Not all chains need to be indexed.
-    let mut ethereum_ws_vec = Vec::new();
-    let mut contract_addresses = Vec::new();
-    for chain in &chains {
-        let ethereum_ws_var = format!("{}_ETHEREUM_WS", chain.to_uppercase());
-        let contract_address_var = format!("{}_CONTRACT_ADDRESS", chain.to_uppercase());
+#[cfg(test)]
+mod tests {
+    use ethers::abi::AbiEncode;
+    use ethers::types::Bytes;
 
-        let ethereum_ws = env::var(&ethereum_ws_var);
-        let contract_address = env::var(&contract_address_var);
+    use super::*;
 
-        if ethereum_ws.is_err() || contract_address.is_err() {
-            info!("Not indexing {} for events!", chain);
-            continue;
+    // Packs a transaction of a MultiSend batch as operation || to || value || data length || data.
+    fn pack_transaction(to: Address, data: &[u8]) -> Vec<u8> {
+        let mut packed = vec![0u8];
+        packed.extend_from_slice(to.as_bytes());
+        packed.extend_from_slice(&[0u8; 32]);
+        let mut data_length = [0u8; 32];
+        U256::from(data.len()).to_big_endian(&mut data_length);
+        packed.extend_from_slice(&data_length);
+        packed.extend_from_slice(data);
+        packed
+    }
+
+    fn exec_transaction(to: Address, data: Vec<u8>) -> Vec<u8> {
+        ExecTransactionCall {
+            to,
+            value: U256::zero(),
+            data: Bytes::from(data),
+            operation: 1,
+            safe_tx_gas: U256::zero(),
+            base_gas: U256::zero(),
+            gas_price: U256::zero(),
+            gas_token: Address::zero(),
+            refund_receiver: Address::zero(),
+            signatures: Bytes::from(vec![0u8; 65]),
         }
+        .encode()
+    }
 
-        ethereum_ws_vec.push(ethereum_ws.unwrap());
-        contract_addresses.push(contract_address.unwrap());
+    fn multi_send(transactions: Vec<u8>) -> Vec<u8> {
+        MultiSendCall {
+            transactions: Bytes::from(transactions),
+        }
+        .encode()
     }
 
-    let mut join_handles = Vec::new();
+    fn state(frozen: bool, latest_block: u32) -> StoredContractState {
+        StoredContractState {
+            frozen,
+            latest_block,
+        }
+    }
 
-    for (ethereum_ws, contract_address) in ethereum_ws_vec
-        .into_iter()
-        .zip(contract_addresses.into_iter())
-    {
-        let handle = tokio::spawn(async move {
-            listen_for_events(&ethereum_ws, &contract_address).await;
-        });
-        join_handles.push(handle);
+    #[test]
+    fn test_logged_ranges() {
+        let range = |start, end| DataCommitmentRange {
+            start,
+            end,
+            data_commitment: vec![0u8; 32],
+        };
+        let event = |name: &str, data: Value| StoredContractEvent {
+            block_number: 1000,
+            log_index: Some(0),
+            entry_index: None,
+            transaction_hash: None,
+            name: name.to_string(),
+            data,
+        };
+        let events = [
+            event(
+                "HeaderRangeCommitmentStored",
+                json!({ "startBlock": 1180, "endBlock": 1360 }),
+            ),
+            event("HeadUpdate", json!({ "blockNumber": 1000 })),
+            // Other events with the same blocks do not select a range.
+            event(
+                "HeaderRangeRequested",
+                json!({ "trustedBlock": 1000, "targetBlock": 1180 }),
+            ),
+        ];
+
+        let ranges = logged_ranges(
+            vec![range(1000, 1180), range(1180, 1360), range(1360, 1540)],
+            &events,
+        );
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| (range.start, range.end))
+                .collect::<Vec<_>>(),
+            vec![(1180, 1360)]
+        );
     }
 
-    for handle in join_handles {
-        handle.await.expect("Task panicked or failed");
+    #[test]
+    fn test_decode_multi_send() {
+        let calls = vec![
+            (Address::repeat_byte(1), vec![0xaa; 4]),
+            (Address::repeat_byte(2), Vec::new()),
+            (Address::repeat_byte(3), vec![0xbb; 100]),
+        ];
+        let transactions = calls
+            .iter()
+            .flat_map(|(to, data)| pack_transaction(*to, data))
+            .collect::<Vec<_>>();
+
+        assert_eq!(decode_multi_send(&transactions), calls);
+    }
+
+    #[test]
+    fn test_decode_malformed_multi_send() {
+        let first = pack_transaction(Address::repeat_byte(1), &[0xaa; 4]);
+        let second = pack_transaction(Address::repeat_byte(2), &[0xbb; 8]);
+        let complete = [first.clone(), second].concat();
+
+        // Transactions truncated in their header or their data are dropped, and the complete
+        // transactions before them are kept.
+        for length in [
+            0,
+            1,
+            50,
+            first.len() + 84,
+            first.len() + 85,
+            complete.len() - 1,
+        ] {
+            let calls = decode_multi_send(&complete[..length]);
+            let expected_calls = if length >= first.len() { 1 } else { 0 };
+            assert_eq!(calls.len(), expected_calls);
+        }
+
+        // A data length that overflows the offset of the data does not panic.
+        let mut overflowing = first.clone();
+        overflowing[53..85].copy_from_slice(&[0xff; 32]);
+        assert!(decode_multi_send(&overflowing).is_empty());
+        let mut overflowing = first;
+        overflowing[53..85].copy_from_slice(&[0u8; 32]);
+        overflowing[77..85].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(decode_multi_send(&overflowing).is_empty());
+    }
+
+    #[test]
+    fn test_contract_calls() {
+        let contract = Address::repeat_byte(0xc0);
+        let safe = Address::repeat_byte(0x5a);
+        let multi_send_address = Address::repeat_byte(0x35);
+        let call = vec![0x12; 36];
+
+        // A direct call to the contract.
+        assert_eq!(
+            contract_calls(contract, contract, &call),
+            vec![(call.clone(), None)]
+        );
+
+        // A call through a Safe's execTransaction.
+        assert_eq!(
+            contract_calls(contract, safe, &exec_transaction(contract, call.clone())),
+            vec![(call.clone(), Some(safe))]
+        );
+
+        // Calls to the contract in a MultiSend batch of a Safe, other calls of the batch are
+        // ignored.
+        let other_call = vec![0x34; 8];
+        let batch = [
+            pack_transaction(contract, &call),
+            pack_transaction(Address::repeat_byte(1), &other_call),
+            pack_transaction(contract, &other_call),
+        ]
+        .concat();
+        assert_eq!(
+            contract_calls(
+                contract,
+                safe,
+                &exec_transaction(multi_send_address, multi_send(batch))
+            ),
+            vec![(call.clone(), Some(safe)), (other_call, Some(safe))]
+        );
+
+        // A transaction to a caller that is not a Safe has no calls to the contract.
+        assert!(contract_calls(contract, Address::repeat_byte(1), &call).is_empty());
+        assert!(contract_calls(contract, safe, &[]).is_empty());
+
+        // Truncated Safe and MultiSend calldata has no calls to the contract.
+        let exec = exec_transaction(contract, call.clone());
+        assert!(contract_calls(contract, safe, &exec[..exec.len() / 2]).is_empty());
+        let mut truncated_batch = multi_send(pack_transaction(contract, &call));
+        truncated_batch.truncate(truncated_batch.len() - 40);
+        assert!(contract_calls(
+            contract,
+            safe,
+            &exec_transaction(multi_send_address, truncated_batch)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_detect_interventions() {
+        // A HeadUpdate to the new latest block in the range is not an intervention.
+        assert!(detect_interventions(
+            &state(false, 1000),
+            &state(false, 1256),
+            100,
+            200,
+            &[(150, 1256)]
+        )
+        .is_empty());
+        // A HeadUpdate to another block or outside of the range does not explain the change.
+        assert_eq!(
+            detect_interventions(
+                &state(false, 1000),
+                &state(false, 1256),
+                100,
+                200,
+                &[(150, 1100), (201, 1256)]
+            ),
+            vec!["GenesisStateUpdated"]
+        );
+        assert_eq!(
+            detect_interventions(&state(false, 1000), &state(true, 1000), 100, 200, &[]),
+            vec!["FreezeUpdated"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bisect_interventions() {
+        // The contract is frozen at block 137, and its latest block changes at block 150.
+        let state_at = |block: u64| async move {
+            Ok(state(block >= 137, if block >= 150 { 2000 } else { 1000 }))
+        };
+
+        // The change at block 150 is a HeadUpdate, so only the freeze is an intervention.
+        let interventions = bisect_interventions(
+            100,
+            state(false, 1000),
+            200,
+            state(true, 2000),
+            &[(150, 2000)],
+            state_at,
+        )
+        .await
+        .unwrap();
+        assert_eq!(interventions.len(), 1);
+        assert_eq!(interventions[0].0, 137);
+        assert_eq!(interventions[0].1, vec!["FreezeUpdated"]);
+        assert!(interventions[0].2.frozen);
+
+        // Without the HeadUpdate, the change at block 150 is a genesis state update, found after
+        // the freeze.
+        let interventions = bisect_interventions(
+            100,
+            state(false, 1000),
+            200,
+            state(true, 2000),
+            &[],
+            state_at,
+        )
+        .await
+        .unwrap();
+        let blocks = interventions
+            .iter()
+            .map(|(block, names, _)| (*block, names.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            vec![
+                (137, vec!["FreezeUpdated"]),
+                (150, vec!["GenesisStateUpdated"])
+            ]
+        );
     }
 }
@@ -1,86 +1,220 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
+
 use alloy_primitives::Address;
-use avail_subxt::config::Header;
 use clap::Parser;
-use ethers::abi::AbiEncode;
-use ethers::middleware::SignerMiddleware;
-use ethers::signers::{LocalWallet, Signer};
-use ethers::types::TransactionReceipt;
-use log::info;
-
-// Note: Update ABI when updating contract.
-abigen!(VectorX, "./abi/VectorX.abi.json",);
-
-use std::cmp::min;
-use std::env;
-use std::str::FromStr;
-use std::sync::Arc;
-
+use ethers::abi::{AbiEncode, Token};
 use ethers::contract::abigen;
 use ethers::providers::{Http, Provider};
+use ethers::types::{H160, H256};
+use ethers::utils::keccak256;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use vectorx::input::types::BlockRangeData;
 use vectorx::input::RpcDataFetcher;
+use vectorx::relay::Relayer;
+
+// Note: Update ABI when updating contract.
+abigen!(VectorX, "./abi/VectorX.abi.json",);
 
 // To get the calldata for filling the block range run the following script:
 // cargo run --bin fill_block_range -- --end-block <end_block>
 
-// Add --post flag to post the data on-chain.
+// Add --post flag to post the data on-chain, or --safe-batch <file> to write a Safe transaction
+// batch. Add --resume to continue a fill that was partly posted.
 
 #[derive(Parser, Debug, Clone)]
 #[command(
-    about = "Get the last block of the block range to fill and whether to post the data on-chain."
+    about = "Fill the block range from the contract's latest block to the end block as the guardian."
 )]
 pub struct FillBlockRangeArgs {
-    #[arg(long, required = true)]
-    pub end_block: u32,
+    /// The last block to fill. Not needed with --resume.
+    #[arg(long, required_unless_present = "resume")]
+    pub end_block: Option<u32>,
+    /// Post each batch on-chain with PRIVATE_KEY, which must be the guardian.
     #[arg(long, default_value = "false")]
     pub post: bool,
+    /// Write the batches to this file as a Safe Transaction Builder batch.
+    #[arg(long)]
+    pub safe_batch: Option<String>,
+    /// Where the fill plan is saved, to resume from.
+    #[arg(long, default_value = "fill_plan.json")]
+    pub plan_file: String,
+    /// Continue the fill saved in the plan file from the contract's latest block, instead of
+    /// planning a new one.
+    #[arg(long)]
+    pub resume: bool,
 }
 
-pub struct BlockRangeData {
-    pub start_blocks: Vec<u32>,
-    pub end_blocks: Vec<u32>,
-    pub header_hashes: Vec<[u8; 32]>,
-    pub data_root_commitments: Vec<[u8; 32]>,
-    pub state_root_commitments: Vec<[u8; 32]>,
-    pub end_authority_set_id: u64,
-    pub end_authority_set_hash: [u8; 32],
+// A range of at most MAX_NUM_HEADERS blocks, with the hash of its end block and the commitments of
+// the blocks (start_block, end_block].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct FillRange {
+    start_block: u32,
+    end_block: u32,
+    header_hash: H256,
+    data_root_commitment: H256,
+    state_root_commitment: H256,
 }
 
-async fn get_block_range_data(start_block: u32, end_block: u32) -> BlockRangeData {
-    let mut input_data_fetcher = RpcDataFetcher::new().await;
-
-    let mut start_blocks = Vec::new();
-    let mut end_blocks = Vec::new();
-    let mut header_hashes = Vec::new();
-    let mut data_root_commitments = Vec::new();
-    let mut state_root_commitments = Vec::new();
-
-    for i in (start_block..end_block).step_by(256) {
-        let block_range_end = min(i + 256, end_block);
-        let header = input_data_fetcher.get_header(block_range_end).await;
-        let (state_root_commitment, data_root_commitment) = input_data_fetcher
-            .get_merkle_root_commitments(i, block_range_end)
-            .await;
-        start_blocks.push(i);
-        end_blocks.push(block_range_end);
-        header_hashes.push(header.hash().0);
-        data_root_commitments.push(data_root_commitment.try_into().unwrap());
-        state_root_commitments.push(state_root_commitment.try_into().unwrap());
+// An updateBlockRangeData transaction, which fills the blocks of one authority set and stores the
+// hash of the authority set after its last block.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct FillBatch {
+    ranges: Vec<FillRange>,
+    end_authority_set_id: u64,
+    end_authority_set_hash: H256,
+}
+
+impl FillBatch {
+    fn start_block(&self) -> u32 {
+        self.ranges[0].start_block
+    }
+
+    fn end_block(&self) -> u32 {
+        self.ranges[self.ranges.len() - 1].end_block
     }
-    let end_authority_set_id = input_data_fetcher.get_authority_set_id(end_block).await;
-    let end_authority_set_hash = input_data_fetcher
-        .compute_authority_set_hash(end_block)
-        .await;
-    BlockRangeData {
-        start_blocks,
-        end_blocks,
-        header_hashes,
-        data_root_commitments,
-        state_root_commitments,
-        end_authority_set_id,
-        end_authority_set_hash: end_authority_set_hash.0,
+
+    fn calldata(&self) -> Vec<u8> {
+        vector_x::UpdateBlockRangeDataCall {
+            start_blocks: self.ranges.iter().map(|range| range.start_block).collect(),
+            end_blocks: self.ranges.iter().map(|range| range.end_block).collect(),
+            header_hashes: self
+                .ranges
+                .iter()
+                .map(|range| range.header_hash.0)
+                .collect(),
+            data_root_commitments: self
+                .ranges
+                .iter()
+                .map(|range| range.data_root_commitment.0)
+                .collect(),
+            state_root_commitments: self
+                .ranges
+                .iter()
+                .map(|range| range.state_root_commitment.0)
+                .collect(),
+            end_authority_set_id: self.end_authority_set_id,
+            end_authority_set_hash: self.end_authority_set_hash.0,
+        }
+        .encode()
+    }
+}
+
+impl From<BlockRangeData> for FillBatch {
+    fn from(batch: BlockRangeData) -> Self {
+        let ranges = (0..batch.start_blocks.len())
+            .map(|i| FillRange {
+                start_block: batch.start_blocks[i],
+                end_block: batch.end_blocks[i],
+                header_hash: H256(batch.header_hashes[i]),
+                data_root_commitment: H256(batch.data_root_commitments[i]),
+                state_root_commitment: H256(batch.state_root_commitments[i]),
+            })
+            .collect();
+        FillBatch {
+            ranges,
+            end_authority_set_id: batch.end_authority_set_id,
+            end_authority_set_hash: H256(batch.end_authority_set_hash),
+        }
     }
 }
 
+// The batches of a fill, saved so a partly posted fill can be resumed.
+#[derive(Serialize, Deserialize, Debug)]
+struct FillPlan {
+    chain_id: u64,
+    contract_address: H160,
+    batches: Vec<FillBatch>,
+}
+
+// Plans the fill of (start_block, end_block]. The fill is split into one batch per authority set,
+// so the hash of every authority set crossed is stored, and each batch into ranges of at most
+// MAX_NUM_HEADERS blocks, each checked to chain from its start block to its end block.
+async fn plan_fill(
+    fetcher: &mut RpcDataFetcher,
+    start_block: u32,
+    end_block: u32,
+) -> Vec<FillBatch> {
+    let mut batches = Vec::new();
+    let mut batch_start = start_block;
+    while batch_start < end_block {
+        let batch = fetcher
+            .get_block_range_batch(batch_start, end_block)
+            .await
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Failed to plan the batch from block {}: {:#}",
+                    batch_start, e
+                )
+            });
+        let batch = FillBatch::from(batch);
+        batch_start = batch.end_block();
+        batches.push(batch);
+    }
+    batches
+}
+
+// Checks the commitments the contract stores for the ranges of the batches against the plan.
+// Returns the number of ranges that do not match.
+async fn check_posted_batches(contract: &VectorX<Provider<Http>>, batches: &[FillBatch]) -> u32 {
+    let mut mismatches = 0;
+    for range in batches.iter().flat_map(|batch| batch.ranges.iter()) {
+        // The contract keys the commitments by keccak256(abi.encode(startBlock, endBlock)).
+        let key = keccak256(ethers::abi::encode(&[
+            Token::Uint(range.start_block.into()),
+            Token::Uint(range.end_block.into()),
+        ]));
+        let data_root_commitment = contract.data_root_commitments(key).await.unwrap();
+        if data_root_commitment != range.data_root_commitment.0 {
+            error!(
+                "The contract's data root commitment of range ({}, {}] is 0x{}, not the planned {:?}.",
+                range.start_block,
+                range.end_block,
+                hex::encode(data_root_commitment),
+                range.data_root_commitment
+            );
+            mismatches += 1;
+        }
+    }
+    mismatches
+}
+
+// Builds a batch for the Safe Transaction Builder, with one updateBlockRangeData transaction per
+// batch, executed in order.
+fn safe_batch_json(plan: &FillPlan, batches: &[FillBatch]) -> Value {
+    let transactions: Vec<Value> = batches
+        .iter()
+        .map(|batch| {
+            json!({
+                "to": format!("{:?}", plan.contract_address),
+                "value": "0",
+                "data": format!("0x{}", hex::encode(batch.calldata())),
+                "contractMethod": null,
+                "contractInputsValues": null,
+            })
+        })
+        .collect();
+    json!({
+        "version": "1.0",
+        "chainId": plan.chain_id.to_string(),
+        "createdAt": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+        "meta": {
+            "name": format!(
+                "VectorX fill from block {} to {}",
+                batches[0].start_block(),
+                batches[batches.len() - 1].end_block()
+            ),
+            "description": format!(
+                "{} updateBlockRangeData transactions, one per authority set.",
+                batches.len()
+            ),
+        },
+        "transactions": transactions,
+    })
+}
+
 #[tokio::main]
 async fn main() {
     env::set_var("RUST_LOG", "info");
@@ -88,8 +222,6 @@ async fn main() {
     env_logger::init();
     let args = FillBlockRangeArgs::parse();
 
-    let end_block = args.end_block;
-
     let contract_address = env::var("CONTRACT_ADDRESS").expect("CONTRACT_ADDRESS must be set");
 
     let address = contract_address
@@ -98,59 +230,197 @@ async fn main() {
 
     let ethereum_rpc_url = env::var("ETHEREUM_RPC_URL").expect("ETHEREUM_RPC_URL must be set");
 
-    let private_key =
-        env::var("PRIVATE_KEY").unwrap_or(String::from("0x00000000000000000000000000000000"));
-    let chain_id = env::var("CHAIN_ID").expect("CHAIN_ID must be set");
-    let wallet = LocalWallet::from_str(&private_key).expect("invalid private key");
-    let wallet = wallet.with_chain_id(chain_id.parse::<u64>().unwrap());
+    let chain_id = env::var("CHAIN_ID")
+        .expect("CHAIN_ID must be set")
+        .parse::<u64>()
+        .expect("invalid chain id");
 
     let provider =
-        Provider::<Http>::try_from(ethereum_rpc_url).expect("could not connect to client");
-    let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet.clone()));
+        Provider::<Http>::try_from(ethereum_rpc_url.as_str()).expect("could not connect to client");
 
-    let contract = VectorX::new(address.0 .0, client);
+    let contract = VectorX::new(address.0 .0, provider.into());
 
     let latest_block = contract.latest_block().await.unwrap();
 
-    let block_range_data = get_block_range_data(latest_block, end_block).await;
+    let plan = if args.resume {
+        let plan: FillPlan = serde_json::from_str(
+            &fs::read_to_string(&args.plan_file).expect("Failed to read the plan file"),
+        )
+        .expect("Failed to parse the plan file");
+        if plan.chain_id != chain_id || plan.contract_address != H160(address.0 .0) {
+            panic!("The plan in {} is for another contract", args.plan_file);
+        }
+        plan
+    } else {
+        let end_block = args.end_block.unwrap();
+        let mut input_data_fetcher = RpcDataFetcher::new().await;
+        let head_block = input_data_fetcher
+            .get_head()
+            .await
+            .expect("Failed to get the finalized head from Avail.")
+            .number;
+        if end_block > head_block {
+            panic!(
+                "The end block {} is not finalized, the finalized head is {}",
+                end_block, head_block
+            );
+        }
+        if end_block <= latest_block {
+            panic!(
+                "The end block {} is not after the contract's latest block {}",
+                end_block, latest_block
+            );
+        }
+
+        let plan = FillPlan {
+            chain_id,
+            contract_address: H160(address.0 .0),
+            batches: plan_fill(&mut input_data_fetcher, latest_block, end_block).await,
+        };
+        fs::write(
+            &args.plan_file,
+            serde_json::to_string_pretty(&plan).unwrap(),
+        )
+        .expect("Failed to write the plan file");
+        info!("Saved the plan to {}.", args.plan_file);
+        plan
+    };
+
+    // The batches that end at or before the latest block were posted, and the fill continues from
+    // the batch that starts there.
+    let (posted, remaining): (Vec<FillBatch>, Vec<FillBatch>) = plan
+        .batches
+        .iter()
+        .cloned()
+        .partition(|batch| batch.end_block() <= latest_block);
+    // A posted range that differs from the plan means the plan is stale, e.g. the contract was
+    // filled by other means, so continuing it could store wrong hashes.
+    if !posted.is_empty() {
+        let mismatches = check_posted_batches(&contract, &posted).await;
+        if mismatches > 0 {
+            panic!(
+                "{} ranges of the {} batches already posted do not match the plan in {}, not resuming",
+                mismatches,
+                posted.len(),
+                args.plan_file
+            );
+        }
+        info!(
+            "{} batches were already posted and match the plan.",
+            posted.len()
+        );
+    }
+    if remaining.is_empty() {
+        info!(
+            "The fill is complete, the contract is at block {}.",
+            latest_block
+        );
+        return;
+    }
+    if remaining[0].start_block() != latest_block {
+        panic!(
+            "The contract's latest block {} is not the start of a remaining batch, plan a new fill without --resume",
+            latest_block
+        );
+    }
+    for batch in remaining.iter() {
+        info!(
+            "Batch from block {} to {} in {} ranges, storing the hash of authority set {}.",
+            batch.start_block(),
+            batch.end_block(),
+            batch.ranges.len(),
+            batch.end_authority_set_id
+        );
+    }
+
+    if let Some(safe_batch) = &args.safe_batch {
+        fs::write(
+            safe_batch,
+            serde_json::to_string_pretty(&safe_batch_json(&plan, &remaining)).unwrap(),
+        )
+        .expect("Failed to write the Safe batch");
+        info!("Wrote the Safe transaction batch to {}.", safe_batch);
+    }
 
     if args.post {
-        let tx: Option<TransactionReceipt> = contract
-            .update_block_range_data(
-                block_range_data.start_blocks,
-                block_range_data.end_blocks,
-                block_range_data.header_hashes,
-                block_range_data.data_root_commitments,
-                block_range_data.state_root_commitments,
-                block_range_data.end_authority_set_id,
-                block_range_data.end_authority_set_hash,
-            )
-            .send()
-            .await
-            .unwrap()
-            .await
-            .unwrap();
-        if let Some(tx) = tx {
+        let private_key = env::var("PRIVATE_KEY").expect("PRIVATE_KEY must be set");
+        let mut relayer = Relayer::new(&ethereum_rpc_url, &private_key, chain_id);
+        for batch in remaining.iter() {
+            match relayer
+                .relay(address.0 .0.into(), batch.calldata().into(), None)
+                .await
+            {
+                Ok(receipt) => info!(
+                    "Filled blocks {} to {} in transaction {:?}.",
+                    batch.start_block(),
+                    batch.end_block(),
+                    receipt.transaction_hash
+                ),
+                Err(e) => panic!(
+                    "Failed to fill blocks {} to {}, run again with --resume to continue: {}",
+                    batch.start_block(),
+                    batch.end_block(),
+                    e
+                ),
+            }
+        }
+    } else if args.safe_batch.is_none() {
+        // If we don't want to post the data on-chain, we can just print the data that would be posted.
+        for batch in remaining.iter() {
             info!(
-                "Proof relayed successfully! Transaction Hash: {:?}",
-                tx.transaction_hash
+                "Calldata for update block range call from block {} to {}:\n {:?}",
+                batch.start_block(),
+                batch.end_block(),
+                hex::encode(batch.calldata())
             );
         }
-    } else {
-        // If we don't want to post the data on-chain, we can just print the data that would be posted.
-        let update_block_range_call = vector_x::UpdateBlockRangeDataCall {
-            start_blocks: block_range_data.start_blocks,
-            end_blocks: block_range_data.end_blocks,
-            header_hashes: block_range_data.header_hashes,
-            data_root_commitments: block_range_data.data_root_commitments,
-            state_root_commitments: block_range_data.state_root_commitments,
-            end_authority_set_id: block_range_data.end_authority_set_id,
-            end_authority_set_hash: block_range_data.end_authority_set_hash,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_batch_json() {
+        let batch = FillBatch {
+            ranges: vec![FillRange {
+                start_block: 1000,
+                end_block: 1256,
+                header_hash: H256::repeat_byte(1),
+                data_root_commitment: H256::repeat_byte(2),
+                state_root_commitment: H256::repeat_byte(3),
+            }],
+            end_authority_set_id: 12,
+            end_authority_set_hash: H256::repeat_byte(4),
         };
-        let calldata = update_block_range_call.encode();
-        info!(
-            "Calldata for update block range call:\n {:?}",
-            hex::encode(calldata)
+        let plan = FillPlan {
+            chain_id: 11155111,
+            contract_address: H160::repeat_byte(5),
+            batches: vec![batch.clone()],
+        };
+
+        let safe_batch = safe_batch_json(&plan, &plan.batches);
+        assert_eq!(safe_batch["version"], "1.0");
+        assert_eq!(safe_batch["chainId"], "11155111");
+        assert!(safe_batch["createdAt"].is_u64());
+        assert_eq!(
+            safe_batch["meta"]["name"],
+            "VectorX fill from block 1000 to 1256"
+        );
+
+        let transactions = safe_batch["transactions"].as_array().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0]["to"],
+            "0x0505050505050505050505050505050505050505"
+        );
+        assert_eq!(transactions[0]["value"], "0");
+        assert_eq!(
+            transactions[0]["data"],
+            format!("0x{}", hex::encode(batch.calldata()))
         );
+        assert!(transactions[0]["contractMethod"].is_null());
+        assert!(transactions[0]["contractInputsValues"].is_null());
     }
 }
@@ -32,9 +32,15 @@ pub async fn main() {
 
     let genesis_block = args.block;
 
-    let header = fetcher.get_header(genesis_block).await;
+    let header = fetcher
+        .get_header(genesis_block)
+        .await
+        .expect("Failed to get header from Avail.");
     let header_hash = header.hash();
-    let authority_set_id = fetcher.get_authority_set_id(genesis_block).await;
+    let authority_set_id = fetcher
+        .get_authority_set_id(genesis_block)
+        .await
+        .expect("Failed to get the authority set id from Avail.");
     let authority_set_hash = fetcher.compute_authority_set_hash(genesis_block).await;
     info!("Block {}'s header hash: {:?}", genesis_block, header_hash);
     info!(
@@ -9,19 +9,103 @@
 use std::collections::HashMap;
 use std::env;
 use std::ops::Deref;
+use std::time::Duration;
 
 use avail_subxt::config::Header as HeaderTrait;
 use avail_subxt::subxt_rpc::RpcParams;
 use avail_subxt::{api, build_client};
 use codec::Encode;
-use log::debug;
+use log::{debug, info};
 use plonky2x::frontend::ecc::curve25519::ed25519::eddsa::DUMMY_SIGNATURE;
 use sp_core::ed25519::{self};
 use sp_core::{blake2_256, Pair, H256};
-use vectorx::input::types::{GrandpaJustification, SignerMessage, StoredJustificationData};
+use vectorx::alert::Alerter;
+use vectorx::input::types::{
+    GrandpaJustification, SignerMessage, StoredJustificationData, StoredPrecommit,
+};
 use vectorx::input::RpcDataFetcher;
+use vectorx::monitor::GrandpaMonitor;
+
+// How often to check for a finality stall while no justifications are received.
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Checks every precommit in the justification for equivocations, regardless of whether the
+// justification is stored. Each precommit is verified against its own target, as precommits in a
+// justification can target descendants of the finalized block.
+async fn check_equivocations(
+    fetcher: &mut RpcDataFetcher,
+    monitor: &mut GrandpaMonitor,
+    alerter: &Alerter,
+    justification: &GrandpaJustification,
+    authority_set_id: u64,
+) {
+    for precommit in justification.commit.precommits.iter() {
+        let signed_message = Encode::encode(&(
+            &SignerMessage::PrecommitMessage(precommit.precommit.clone()),
+            &justification.round,
+            &authority_set_id,
+        ));
+        if !<ed25519::Pair as Pair>::verify(
+            &precommit.signature,
+            signed_message.as_slice(),
+            &precommit.id,
+        ) {
+            continue;
+        }
+
+        let stored_precommit = StoredPrecommit {
+            target_hash: precommit.precommit.target_hash.0.to_vec(),
+            target_number: precommit.precommit.target_number,
+            signed_message,
+            signature: precommit.signature.0.to_vec(),
+        };
+        if let Some(equivocation) = monitor.observe_precommit(
+            authority_set_id,
+            justification.round,
+            precommit.id.0.to_vec(),
+            stored_precommit,
+        ) {
+            alerter
+                .alert(&format!(
+                    "Equivocation on {}: authority 0x{} signed blocks {} and {} in round {} of authority set {}.",
+                    fetcher.avail_chain_id,
+                    hex::encode(&equivocation.pubkey),
+                    equivocation.first.target_number,
+                    equivocation.second.target_number,
+                    equivocation.round,
+                    equivocation.authority_set_id
+                ))
+                .await;
+            fetcher
+                .redis_client
+                .add_equivocation(&fetcher.avail_chain_id, equivocation)
+                .await;
+        }
+    }
+}
+
+// Alerts if finality has not moved for longer than the stall threshold.
+async fn check_finality_stall(
+    fetcher: &RpcDataFetcher,
+    monitor: &mut GrandpaMonitor,
+    alerter: &Alerter,
+) {
+    if let Some((last_finalized_block, elapsed)) = monitor.check_stall() {
+        alerter
+            .alert(&format!(
+                "Finality stall on {}: block {} was finalized {} seconds ago and finality has not moved since.",
+                fetcher.avail_chain_id,
+                last_finalized_block,
+                elapsed.as_secs()
+            ))
+            .await;
+    }
+}
+
+async fn listen_for_justifications(mut fetcher: RpcDataFetcher, stall_threshold: Duration) {
+    let alerter = Alerter::new();
+    let mut monitor = GrandpaMonitor::new(stall_threshold);
 
-async fn listen_for_justifications(mut fetcher: RpcDataFetcher) {
     let sub: Result<avail_subxt::subxt_rpc::Subscription<GrandpaJustification>, _> = fetcher
         .client
         .rpc()
@@ -34,13 +118,36 @@ async fn listen_for_justifications(mut fetcher: RpcDataFetcher) {
         .await;
     let mut sub = sub.unwrap();
 
-    // Wait for new justification.
-    while let Some(Ok(justification)) = sub.next().await {
+    loop {
+        // Wait for new justification, checking for a finality stall if none arrives.
+        let justification = match tokio::time::timeout(STALL_CHECK_INTERVAL, sub.next()).await {
+            Ok(Some(Ok(justification))) => justification,
+            Ok(_) => break,
+            Err(_) => {
+                // No justification was received, so check the finalized head directly in case
+                // only the subscription stalled.
+                let head = fetcher
+                    .get_head()
+                    .await
+                    .expect("Failed to get the finalized head from Avail.");
+                monitor.observe_finalized(head.number);
+                check_finality_stall(&fetcher, &mut monitor, &alerter).await;
+                continue;
+            }
+        };
         debug!(
             "New justification from block {}",
             justification.commit.target_number
         );
 
+        if monitor.observe_finalized(justification.commit.target_number) {
+            info!(
+                "Finality resumed on {} at block {}.",
+                fetcher.avail_chain_id, justification.commit.target_number
+            );
+        }
+        check_finality_stall(&fetcher, &mut monitor, &alerter).await;
+
         // Get the header corresponding to the new justification.
         let header = fetcher
             .client
@@ -70,6 +177,15 @@ async fn listen_for_justifications(mut fetcher: RpcDataFetcher) {
             .unwrap()
             .unwrap();
 
+        check_equivocations(
+            &mut fetcher,
+            &mut monitor,
+            &alerter,
+            &justification,
+            authority_set_id,
+        )
+        .await;
+
         // Form a message which is signed in the justification.
         let signed_message = Encode::encode(&(
             &SignerMessage::PrecommitMessage(justification.commit.precommits[0].clone().precommit),
@@ -162,6 +278,15 @@ pub async fn main() {
     let avail_url = env::var("AVAIL_URL").unwrap();
     let avail_chain_id = env::var("AVAIL_CHAIN_ID").unwrap();
 
+    let finality_stall_secs_env = env::var("FINALITY_STALL_SECS");
+    let mut finality_stall_secs = 600;
+    if finality_stall_secs_env.is_ok() {
+        finality_stall_secs = finality_stall_secs_env
+            .unwrap()
+            .parse::<u64>()
+            .expect("invalid FINALITY_STALL_SECS");
+    }
+
     let fetcher = RpcDataFetcher {
         client: build_client(avail_url.clone(), false).await.unwrap().0,
         redis_client: vectorx::input::RedisClient::new().await,
@@ -170,5 +295,5 @@ pub async fn main() {
         save: None,
     };
 
-    listen_for_justifications(fetcher).await;
+    listen_for_justifications(fetcher, Duration::from_secs(finality_stall_secs)).await;
 }
@@ -0,0 +1,89 @@
+//! To build the binary:
+//!
+//!     `cargo build --release --bin participation`
+//!
+//!
+//!
+//!
+//!
+
+use std::{env, fs};
+
+use clap::{Parser, ValueEnum};
+use log::info;
+use vectorx::analytics::{compute_participation, participation_to_csv, PARTICIPATION_BIAS_NOTE};
+use vectorx::input::RedisClient;
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "Report validator participation from the justifications indexed in a block range.",
+    long_about = format!(
+        "Report validator participation from the justifications indexed in a block range.\n\n{}",
+        PARTICIPATION_BIAS_NOTE
+    )
+)]
+pub struct ParticipationArgs {
+    #[arg(long, required = true)]
+    pub start_block: u32,
+    #[arg(long, required = true)]
+    pub end_block: u32,
+    /// Only report on this authority set.
+    #[arg(long)]
+    pub authority_set_id: Option<u64>,
+    /// Justifications with at most this many signatures above the 2/3 threshold are counted as
+    /// near the threshold.
+    #[arg(long, default_value = "2")]
+    pub near_threshold_margin: usize,
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: OutputFormat,
+    /// File to write the report to. Defaults to stdout.
+    #[arg(long)]
+    pub output: Option<String>,
+}
+
+#[tokio::main]
+pub async fn main() {
+    env::set_var("RUST_LOG", "info");
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let args = ParticipationArgs::parse();
+
+    let avail_chain_id = env::var("AVAIL_CHAIN_ID").expect("AVAIL_CHAIN_ID must be set");
+    let mut redis_client = RedisClient::new().await;
+
+    let justifications = redis_client
+        .get_justifications_in_range(&avail_chain_id, args.start_block, args.end_block)
+        .await;
+    info!(
+        "Found {} justifications in range [{}, {}].",
+        justifications.len(),
+        args.start_block,
+        args.end_block
+    );
+    info!("{}", PARTICIPATION_BIAS_NOTE);
+
+    let mut participation = compute_participation(&justifications, args.near_threshold_margin);
+    if let Some(authority_set_id) = args.authority_set_id {
+        participation.retain(|p| p.authority_set_id == authority_set_id);
+    }
+
+    let report = match args.format {
+        OutputFormat::Json => serde_json::to_string_pretty(&participation).unwrap(),
+        OutputFormat::Csv => participation_to_csv(&participation),
+    };
+
+    match args.output {
+        Some(path) => {
+            fs::write(&path, report).expect("Failed to write report");
+            info!("Wrote participation report to {}.", path);
+        }
+        None => println!("{}", report),
+    }
+}
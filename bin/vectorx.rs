@@ -1,15 +1,32 @@
 use std::cmp::min;
-use std::env;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs};
 
 use alloy_primitives::{Address, Bytes, FixedBytes, B256};
 use alloy_sol_types::{sol, SolType};
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
 use ethers::abi::AbiEncode;
 use ethers::contract::abigen;
 use ethers::providers::{Http, Provider};
-use log::{error, info};
+use ethers::types::{TransactionReceipt, H256, U256};
+use futures::future::join_all;
+use futures::StreamExt;
+use log::{error, info, warn};
+use serde::Deserialize;
 use succinct_client::request::SuccinctClient;
-use vectorx::input::RpcDataFetcher;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, Mutex};
+use vectorx::alert::Alerter;
+use vectorx::consts::{MAX_AUTHORITY_SET_SIZE, MAX_HEADER_SIZE, MAX_NUM_HEADERS};
+use vectorx::input::types::StoredProofRequest;
+use vectorx::input::{RedisClient, RpcDataFetcher};
+use vectorx::metrics::{OperatorMetrics, ThresholdAlert};
+use vectorx::prover::{get_platform_request_status, Circuit, LocalProver, RequestStatus};
+use vectorx::relay::{RelayOutcome, Relayer};
 
 // Note: Update ABI when updating contract.
 abigen!(VectorX, "./abi/VectorX.abi.json",);
@@ -24,11 +41,804 @@ type RotateInputTuple = sol! { tuple(uint64, bytes32) };
 
 type HeaderRangeInputTuple = sol! { tuple(uint32, bytes32, uint64, bytes32, uint32) };
 
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "Continuously request header range and rotate proofs for the VectorX contract.",
+    args_conflicts_with_subcommands = true
+)]
+pub struct VectorXArgs {
+    /// The deployment to act on, by Avail chain name or contract address. Defaults to every
+    /// deployment, but the requests need exactly one.
+    #[arg(long, global = true)]
+    pub deployment: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Continuously request proofs for every deployment. This is the default.
+    Run(RunArgs),
+    /// Print the contract's latest block, the stored authority set hashes, the Avail head and the
+    /// next provable target.
+    Status,
+    /// Print the catch-up plan and the requests the operator would make now, without submitting
+    /// anything.
+    Plan,
+    /// Request a header range proof from the trusted block to the target block.
+    RequestRange {
+        /// The trusted block, which must be the contract's latest block or the target of a
+        /// pending header range.
+        #[arg(long)]
+        from: u32,
+        /// The target block.
+        #[arg(long)]
+        to: u32,
+        /// Print the request instead of submitting it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Request a rotate proof from the authority set to the next one.
+    RequestRotate {
+        /// The authority set whose hash is stored, to rotate to the next one.
+        #[arg(long)]
+        set_id: u64,
+        /// Print the request instead of submitting it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Clear the failed attempts of a request the operator stopped retrying, so it is retried.
+    ResetAttempts {
+        /// The request, as in the operator's logs and alerts (e.g. rotate:120 or
+        /// header_range:4000:4200).
+        #[arg(long)]
+        request: String,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    /// Print the planned requests and the reason for each decision, without submitting anything.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Run a single loop iteration and exit.
+    #[arg(long)]
+    pub once: bool,
+}
+
+// Defaults of the per-deployment settings.
+const DEFAULT_LOOP_DELAY_MINS: u64 = 5;
+const DEFAULT_BLOCK_INTERVAL: u32 = 200;
+const DEFAULT_MAX_RANGE: u32 = 256;
+
+// Delay before restarting a deployment's operator. Doubles on each consecutive failure, up to
+// MAX_RESTART_DELAY, and resets once the operator has run for MAX_RESTART_DELAY.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(5);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ProverBackendKind {
+    #[default]
+    Platform,
+    Local,
+}
+
+/// A VectorX deployment the operator requests proofs for.
+#[derive(Deserialize, Debug, Clone)]
+struct Deployment {
+    /// Name of the Avail chain the contract is a light client of (e.g. goldberg). Also used as the
+    /// Avail chain id in Redis keys.
+    chain_name: String,
+    avail_url: String,
+    /// Id of the Ethereum chain the contract is deployed on.
+    chain_id: u32,
+    ethereum_rpc_url: String,
+    contract_address: String,
+    /// Minutes between loop iterations.
+    #[serde(default = "default_loop_delay_mins")]
+    loop_delay_mins: u64,
+    /// Header ranges target multiples of this many blocks.
+    #[serde(default = "default_block_interval")]
+    block_interval: u32,
+    /// Maximum number of blocks in a header range.
+    #[serde(default = "default_max_range")]
+    max_range: u32,
+    /// Function IDs of the expected circuits. Requests always use the contract's
+    /// headerRangeFunctionId and rotateFunctionId, and requests of a type are refused while it
+    /// differs from the expected one, as their proofs would not verify.
+    header_range_function_id: Option<H256>,
+    rotate_function_id: Option<H256>,
+    #[serde(default)]
+    prover_backend: ProverBackendKind,
+    /// Maximum number of requests submitted in any hour and in any day. Requests over the budget
+    /// are deferred.
+    max_requests_per_hour: Option<u32>,
+    max_requests_per_day: Option<u32>,
+    /// Maximum fee per gas of relayed transactions, in gwei. Relays are deferred while the gas
+    /// price is above it.
+    max_gas_price_gwei: Option<u64>,
+    /// Maximum gas fees paid by relayed transactions in any day, in gwei. Relays are deferred once
+    /// it is spent.
+    max_relay_spend_gwei_per_day: Option<u64>,
+    /// Minimum number of blocks a header range must add, on top of targeting a multiple of
+    /// block_interval.
+    #[serde(default)]
+    min_new_blocks: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct OperatorConfig {
+    deployments: Vec<Deployment>,
+}
+
+fn default_loop_delay_mins() -> u64 {
+    DEFAULT_LOOP_DELAY_MINS
+}
+
+fn default_block_interval() -> u32 {
+    DEFAULT_BLOCK_INTERVAL
+}
+
+fn default_max_range() -> u32 {
+    DEFAULT_MAX_RANGE
+}
+
+// Reads an optional function ID from the environment variable, which may be set but empty.
+fn function_id_from_env(env_var: &str) -> Option<H256> {
+    env::var(env_var)
+        .ok()
+        .filter(|function_id| !function_id.is_empty())
+        .map(|function_id| {
+            function_id
+                .parse::<H256>()
+                .unwrap_or_else(|_| panic!("invalid {}", env_var))
+        })
+}
+
+fn read_config(path: &str) -> OperatorConfig {
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+    if path.ends_with(".json") {
+        serde_json::from_str(&contents).unwrap_or_else(|e| panic!("invalid {}: {}", path, e))
+    } else {
+        toml::from_str(&contents).unwrap_or_else(|e| panic!("invalid {}: {}", path, e))
+    }
+}
+
+impl Deployment {
+    // The single deployment configured with environment variables, used when OPERATOR_CONFIG is
+    // not set.
+    fn from_env() -> Self {
+        let mut loop_delay_mins = DEFAULT_LOOP_DELAY_MINS;
+        if let Ok(loop_delay_mins_env) = env::var("LOOP_DELAY_MINS") {
+            loop_delay_mins = loop_delay_mins_env
+                .parse::<u64>()
+                .expect("invalid LOOP_DELAY_MINS");
+        }
+        let mut block_interval = DEFAULT_BLOCK_INTERVAL;
+        if let Ok(update_delay_blocks_env) = env::var("UPDATE_DELAY_BLOCKS") {
+            block_interval = update_delay_blocks_env
+                .parse::<u32>()
+                .expect("invalid UPDATE_DELAY_BLOCKS");
+        }
+        let max_requests_per_hour = env::var("MAX_REQUESTS_PER_HOUR")
+            .ok()
+            .map(|max| max.parse::<u32>().expect("invalid MAX_REQUESTS_PER_HOUR"));
+        let max_requests_per_day = env::var("MAX_REQUESTS_PER_DAY")
+            .ok()
+            .map(|max| max.parse::<u32>().expect("invalid MAX_REQUESTS_PER_DAY"));
+        let max_gas_price_gwei = env::var("MAX_GAS_PRICE_GWEI")
+            .ok()
+            .map(|max| max.parse::<u64>().expect("invalid MAX_GAS_PRICE_GWEI"));
+        let max_relay_spend_gwei_per_day =
+            env::var("MAX_RELAY_SPEND_GWEI_PER_DAY").ok().map(|max| {
+                max.parse::<u64>()
+                    .expect("invalid MAX_RELAY_SPEND_GWEI_PER_DAY")
+            });
+        let mut min_new_blocks = 0;
+        if let Ok(min_new_blocks_env) = env::var("MIN_NEW_BLOCKS") {
+            min_new_blocks = min_new_blocks_env
+                .parse::<u32>()
+                .expect("invalid MIN_NEW_BLOCKS");
+        }
+        // PROVER_BACKEND is either platform (default) or local.
+        let prover_backend = match env::var("PROVER_BACKEND")
+            .unwrap_or("platform".to_string())
+            .as_str()
+        {
+            "platform" => ProverBackendKind::Platform,
+            "local" => ProverBackendKind::Local,
+            _ => panic!("invalid PROVER_BACKEND"),
+        };
+
+        Self {
+            chain_name: env::var("AVAIL_CHAIN_ID").expect("AVAIL_CHAIN_ID must be set"),
+            avail_url: env::var("AVAIL_URL").expect("AVAIL_URL must be set"),
+            chain_id: env::var("CHAIN_ID")
+                .expect("CHAIN_ID must be set")
+                .parse::<u32>()
+                .expect("invalid chain id"),
+            ethereum_rpc_url: env::var("ETHEREUM_RPC_URL").expect("ETHEREUM_RPC_URL must be set"),
+            contract_address: env::var("CONTRACT_ADDRESS").expect("CONTRACT_ADDRESS must be set"),
+            loop_delay_mins,
+            block_interval,
+            max_range: DEFAULT_MAX_RANGE,
+            header_range_function_id: function_id_from_env("EXPECTED_HEADER_RANGE_FUNCTION_ID"),
+            rotate_function_id: function_id_from_env("EXPECTED_ROTATE_FUNCTION_ID"),
+            prover_backend,
+            max_requests_per_hour,
+            max_requests_per_day,
+            max_gas_price_gwei,
+            max_relay_spend_gwei_per_day,
+            min_new_blocks,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{} at {}", self.chain_name, self.contract_address)
+    }
+
+    // Returns the problems with the deployment's settings, if any.
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.contract_address.parse::<Address>().is_err() {
+            errors.push(format!(
+                "invalid contract_address {}",
+                self.contract_address
+            ));
+        }
+        if self.avail_url.is_empty() {
+            errors.push("avail_url must be set".to_string());
+        }
+        if self.ethereum_rpc_url.is_empty() {
+            errors.push("ethereum_rpc_url must be set".to_string());
+        }
+        if self.loop_delay_mins == 0 {
+            errors.push("loop_delay_mins must be positive".to_string());
+        }
+        if self.block_interval == 0 {
+            errors.push("block_interval must be positive".to_string());
+        }
+        if self.max_range == 0 || self.max_range as usize > MAX_NUM_HEADERS {
+            errors.push(format!(
+                "max_range must be between 1 and {}, the maximum number of headers in a header range proof",
+                MAX_NUM_HEADERS
+            ));
+        }
+        if self.max_requests_per_hour == Some(0) || self.max_requests_per_day == Some(0) {
+            errors.push("request budgets must be positive".to_string());
+        }
+        if self.max_relay_spend_gwei_per_day == Some(0) {
+            errors.push("max_relay_spend_gwei_per_day must be positive".to_string());
+        }
+        if self.min_new_blocks > self.max_range {
+            errors.push("min_new_blocks must not be above max_range".to_string());
+        }
+        errors
+    }
+}
+
+const HEADER_RANGE_REQUEST: &str = "header_range";
+const ROTATE_REQUEST: &str = "rotate";
+// The request type of the relay spend of guardian fallbacks.
+const GUARDIAN_FALLBACK: &str = "guardian_fallback";
+
+// Number of failed or timed out requests with the same parameters before the operator stops
+// resubmitting them and escalates.
+const MAX_REQUEST_ATTEMPTS: u32 = 3;
+
+// Where the operator's proofs are generated.
+#[derive(Clone)]
+enum ProverBackend {
+    Platform {
+        client: SuccinctClient,
+        rpc_url: String,
+        api_key: String,
+    },
+    Local(LocalProver),
+}
+
+impl ProverBackend {
+    fn new(kind: ProverBackendKind) -> Self {
+        match kind {
+            ProverBackendKind::Platform => {
+                let succinct_rpc_url =
+                    env::var("SUCCINCT_RPC_URL").expect("SUCCINCT_RPC_URL must be set");
+                let succinct_api_key =
+                    env::var("SUCCINCT_API_KEY").expect("SUCCINCT_API_KEY must be set");
+                ProverBackend::Platform {
+                    client: SuccinctClient::new(
+                        succinct_rpc_url.clone(),
+                        succinct_api_key.clone(),
+                        false,
+                        false,
+                    ),
+                    rpc_url: succinct_rpc_url,
+                    api_key: succinct_api_key,
+                }
+            }
+            ProverBackendKind::Local => ProverBackend::Local(LocalProver::new()),
+        }
+    }
+}
+
+// Leader election through a lease in Redis, so only one of several operators for the same contract
+// submits requests. The lease is acquired and renewed in the background every third of its TTL, so
+// a standby takes over at most a third of the TTL after the leader's lease expires.
+#[derive(Clone)]
+struct LeaderLease {
+    redis_client: RedisClient,
+    chain_id: u64,
+    address: Vec<u8>,
+    holder: String,
+    ttl: Duration,
+    is_leader: Arc<AtomicBool>,
+    // Whether the lease was released. The renewal holds the lock while it renews, so the lease
+    // cannot be renewed after it is released.
+    released: Arc<Mutex<bool>>,
+}
+
+impl LeaderLease {
+    fn new(
+        redis_client: RedisClient,
+        config: &VectorXConfig,
+        holder: String,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            redis_client,
+            chain_id: config.chain_id as u64,
+            address: config.address.to_vec(),
+            holder,
+            ttl,
+            is_leader: Arc::new(AtomicBool::new(false)),
+            released: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    // Acquires the lease, or renews it if this operator holds it. Returns whether this operator is
+    // the leader. If Redis cannot be reached, the lease may expire before it is renewed, so the
+    // operator steps down until it is acquired again.
+    async fn acquire(&mut self) -> bool {
+        let is_leader = match self
+            .redis_client
+            .acquire_operator_lease(
+                self.chain_id,
+                self.address.clone(),
+                &self.holder,
+                self.ttl.as_millis() as u64,
+            )
+            .await
+        {
+            Ok(is_leader) => is_leader,
+            Err(e) => {
+                error!("Operator {} failed to renew the lease: {}", self.holder, e);
+                false
+            }
+        };
+        let was_leader = self.is_leader.swap(is_leader, Ordering::SeqCst);
+        if is_leader && !was_leader {
+            info!(
+                "Operator {} acquired the lease and is the leader.",
+                self.holder
+            );
+        } else if !is_leader && was_leader {
+            warn!("Operator {} lost the lease and is on standby.", self.holder);
+        }
+        is_leader
+    }
+
+    // Renews the lease until it is released, or until the operator that holds this LeaderLease is
+    // dropped, e.g. when it is restarted.
+    fn spawn_renewal(&self) {
+        let mut lease = self.clone();
+        tokio::spawn(async move {
+            while Arc::strong_count(&lease.is_leader) > 1 {
+                let released = lease.released.clone();
+                let released = released.lock().await;
+                if *released {
+                    break;
+                }
+                lease.acquire().await;
+                drop(released);
+                tokio::time::sleep(lease.ttl / 3).await;
+            }
+        });
+    }
+
+    // Stops renewing the lease, and releases it if this operator holds it, so a standby takes over
+    // right away instead of once the lease expires.
+    async fn release(&mut self) {
+        let mut released = self.released.lock().await;
+        *released = true;
+        self.is_leader.store(false, Ordering::SeqCst);
+        match self
+            .redis_client
+            .release_operator_lease(self.chain_id, self.address.clone(), &self.holder)
+            .await
+        {
+            Ok(true) => info!("Operator {} released the lease.", self.holder),
+            Ok(false) => {}
+            Err(e) => error!(
+                "Operator {} failed to release the lease: {}",
+                self.holder, e
+            ),
+        }
+    }
+
+    fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::SeqCst)
+    }
+}
+
 struct VectorXOperator {
     config: VectorXConfig,
     contract: VectorX<Provider<Http>>,
-    client: SuccinctClient,
+    prover: ProverBackend,
     data_fetcher: RpcDataFetcher,
+    alerter: Alerter,
+    // Time after which a request that has not been committed on-chain is considered failed.
+    request_timeout: Duration,
+    // Number of consecutive header ranges that may be pending at the same time.
+    pipeline_depth: u32,
+    // How often to check on requests that other planned requests depend on while catching up.
+    catch_up_poll_interval: Duration,
+    // Relays local proofs and guardian fallbacks with the operator's own signer, if enabled. Shared
+    // by the deployments on the same chain, so their transactions do not reuse nonces.
+    relayer: Option<Arc<Mutex<Relayer>>>,
+    // Fill header ranges that failed too many times with updateBlockRangeData through the relayer.
+    guardian_fallback: bool,
+    // The transactions the relayer sent for the deployment that are not mined yet, by nonce.
+    relayed_calls: HashMap<U256, RelayedCall>,
+    metrics: OperatorMetrics,
+    // The contract's latestBlock when it was last seen to advance, and when.
+    last_commit: Option<(u32, u64)>,
+    // The authority set of the head, and the estimated epoch end block of that authority set.
+    epoch_end_estimate: Option<(u64, u32)>,
+    blocks_behind_head_alert: ThresholdAlert,
+    seconds_since_last_commit_alert: ThresholdAlert,
+    pending_requests_alert: ThresholdAlert,
+    // With several operators for the same contract, the lease that decides which one submits.
+    leader_lease: Option<LeaderLease>,
+    // Function IDs of the expected circuits, which the contract's must match.
+    header_range_function_id: Option<B256>,
+    rotate_function_id: Option<B256>,
+    // Budgets of the deployment's requests and relays.
+    max_requests_per_hour: Option<u32>,
+    max_requests_per_day: Option<u32>,
+    max_fee_per_gas: Option<U256>,
+    max_relay_spend_gwei_per_day: Option<u64>,
+    min_new_blocks: u32,
+    // The failing preflight checks that were already alerted on, with their reasons.
+    preflight_failures: HashMap<&'static str, String>,
+    // A range found unprovable while planning, to fill with the guardian fallback when submitting.
+    unprovable_range: Option<(u32, u32)>,
+    // The last blocks justified by the authority sets that have ended, which never change.
+    epoch_end_blocks: HashMap<u64, u32>,
+    // Run the loop on Avail finalized heads and contract events, with the loop delay as a fallback.
+    event_driven: bool,
+    // Newly finalized Avail blocks and the contract's events. None until subscribed, or after the
+    // subscription ended.
+    finalized_heads: Option<mpsc::Receiver<u32>>,
+    contract_events: Option<mpsc::Receiver<VectorXEvents>>,
+    // The last finalized head seen, and its authority set.
+    last_finalized_head: Option<(u32, u64)>,
+    // Print planned requests instead of submitting them.
+    dry_run: bool,
+}
+
+// The parameters of a request, used to count failed attempts of the same request.
+fn request_params(request: &StoredProofRequest) -> String {
+    match request.request_type.as_str() {
+        HEADER_RANGE_REQUEST => format!(
+            "{}:{}:{}",
+            HEADER_RANGE_REQUEST, request.trusted_block, request.target_block
+        ),
+        _ => format!("{}:{}", ROTATE_REQUEST, request.authority_set_id),
+    }
+}
+
+// The endpoint of a failed Avail or Redis call, attached to the error as context so the loop
+// counts the error against the right endpoint. Errors without it come from contract calls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Endpoint {
+    Avail,
+    Redis,
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Endpoint::Avail => write!(f, "Avail call failed"),
+            Endpoint::Redis => write!(f, "Redis call failed"),
+        }
+    }
+}
+
+// The rpc_errors label of an error of the loop.
+fn error_endpoint(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<Endpoint>() {
+        Some(Endpoint::Avail) => "avail",
+        Some(Endpoint::Redis) => "redis",
+        None => "ethereum",
+    }
+}
+
+// What ended a wait for the next loop. None if the subscription ended.
+enum Trigger {
+    FinalizedHead(Option<u32>),
+    ContractEvent(Option<VectorXEvents>),
+}
+
+// Receives the next trigger from the subscription, or waits forever without one.
+async fn recv_trigger<T>(receiver: &mut Option<mpsc::Receiver<T>>) -> Option<T> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// The closest multiple of block_interval at or below min(head_block, latest_block +
+// data_commitment_max), which header ranges target.
+fn block_to_request(
+    latest_block: u32,
+    head_block: u32,
+    block_interval: u32,
+    data_commitment_max: u32,
+) -> u32 {
+    let max_block = min(head_block, latest_block + data_commitment_max);
+    max_block - (max_block % block_interval)
+}
+
+// The target of the header range of the pipeline from the trusted block, if any. The first range
+// starts at the contract's latest block, and later ranges are only planned while a full range is
+// available below the head.
+fn pipeline_block_to_request(
+    latest_block: u32,
+    trusted_block: u32,
+    head_block: u32,
+    block_interval: u32,
+    data_commitment_max: u32,
+) -> Option<u32> {
+    if trusted_block > latest_block && trusted_block + data_commitment_max > head_block {
+        return None;
+    }
+    let target_block = block_to_request(
+        trusted_block,
+        head_block,
+        block_interval,
+        data_commitment_max,
+    );
+    (target_block > trusted_block).then_some(target_block)
+}
+
+// The highest target of a header range with an authority set, which cannot be past the last block
+// justified by the authority set. That block is 0 while the authority set has not ended.
+fn cap_to_epoch_end(max_block_to_request: u32, last_justified_block: u32) -> u32 {
+    if last_justified_block == 0 {
+        return max_block_to_request;
+    }
+    min(max_block_to_request, last_justified_block)
+}
+
+// Resolves once the process is asked to stop, with Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// The gas fees paid by a mined transaction, gasUsed times effectiveGasPrice, if the receipt has
+// them.
+fn relay_spend_wei(receipt: &TransactionReceipt) -> Option<U256> {
+    Some(receipt.gas_used? * receipt.effective_gas_price?)
+}
+
+// Why relays are deferred, if they are, as the reason recorded for the deferred requests and an
+// explanation: the max fee per gas is above max_fee_per_gas, or the relays of the last day already
+// paid max_relay_spend_gwei_per_day.
+fn relay_deferral(
+    fee_per_gas: U256,
+    max_fee_per_gas: Option<U256>,
+    spent_gwei: u64,
+    max_relay_spend_gwei_per_day: Option<u64>,
+) -> Option<(&'static str, String)> {
+    if let Some(max_fee_per_gas) = max_fee_per_gas {
+        if fee_per_gas > max_fee_per_gas {
+            return Some((
+                "gas_price",
+                format!(
+                    "the max fee per gas is {} wei, above the budget of {} wei",
+                    fee_per_gas, max_fee_per_gas
+                ),
+            ));
+        }
+    }
+    if let Some(max_spend_gwei) = max_relay_spend_gwei_per_day {
+        if spent_gwei >= max_spend_gwei {
+            return Some((
+                "relay_spend",
+                format!(
+                    "the relays of the last day paid {} gwei, the budget is {} gwei",
+                    spent_gwei, max_spend_gwei
+                ),
+            ));
+        }
+    }
+    None
+}
+
+// A budget of the requests submitted in a window ending now.
+struct RequestBudget {
+    budget: u32,
+    // The requests submitted in the window.
+    submitted: u32,
+    window_secs: u64,
+    // The reason recorded for the requests it defers.
+    reason: &'static str,
+}
+
+// Splits off the planned requests that would go over the budgets, each with the budget that defers
+// it. Requests are planned in order of priority, rotates first, so the last ones are deferred.
+fn split_off_over_budget<'a>(
+    planned_requests: &mut Vec<PlannedRequest>,
+    budgets: &'a [RequestBudget],
+) -> Vec<(PlannedRequest, &'a RequestBudget)> {
+    let mut deferred = Vec::new();
+    for budget in budgets {
+        let remaining = budget.budget.saturating_sub(budget.submitted) as usize;
+        if remaining < planned_requests.len() {
+            deferred.extend(
+                planned_requests
+                    .split_off(remaining)
+                    .into_iter()
+                    .map(|planned| (planned, budget)),
+            );
+        }
+    }
+    deferred
+}
+
+// A request that is ready to be submitted, with its input and callback calldata.
+struct PlannedRequest {
+    circuit: Circuit,
+    description: String,
+    function_id: B256,
+    input: Vec<u8>,
+    function_data: Vec<u8>,
+    request: StoredProofRequest,
+}
+
+// A transaction sent by the relayer that is not mined yet.
+struct RelayedCall {
+    description: String,
+    // The request whose proof it fulfills, or None for a guardian fallback.
+    request: Option<StoredProofRequest>,
+}
+
+// What a step of the catch-up plan commits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CatchUpStepKind {
+    // The header ranges with the authority set, up to the target block.
+    HeaderRanges {
+        authority_set_id: u64,
+        target_block: u32,
+    },
+    // The rotate from the authority set to the next one.
+    Rotate {
+        authority_set_id: u64,
+    },
+}
+
+// A step of the plan to bring the contract to the head. A step can only be requested once the
+// steps it depends on (indices into the plan) are committed on-chain, except that header ranges
+// follow the header ranges before them through the pipeline, which commits them in order.
+#[derive(Debug)]
+struct CatchUpStep {
+    kind: CatchUpStepKind,
+    description: String,
+    depends_on: Vec<usize>,
+    committed: bool,
+}
+
+impl CatchUpStep {
+    // Whether the step is the one the request commits part of.
+    fn covers(&self, request: &StoredProofRequest) -> bool {
+        match self.kind {
+            CatchUpStepKind::HeaderRanges {
+                authority_set_id, ..
+            } => {
+                request.request_type == HEADER_RANGE_REQUEST
+                    && request.authority_set_id == authority_set_id
+            }
+            CatchUpStepKind::Rotate { authority_set_id } => {
+                request.request_type == ROTATE_REQUEST
+                    && request.authority_set_id == authority_set_id
+            }
+        }
+    }
+}
+
+// Lays out the catch-up plan from the contract's authority set to the head's. target_blocks holds
+// the target of the header ranges with each of these authority sets in order: the last block
+// justified by each authority set that has ended, then the target for the head's authority set.
+// Header ranges with an authority set depend on the header ranges before them and on the rotate
+// that stored the hash of the authority set. Each rotate depends on the rotate before it. The steps
+// are laid out as not committed.
+fn catch_up_steps(
+    latest_block: u32,
+    contract_authority_set_id: u64,
+    target_blocks: &[u32],
+) -> Vec<CatchUpStep> {
+    let mut steps: Vec<CatchUpStep> = Vec::new();
+    let mut previous_header_range = None;
+    let mut previous_rotate = None;
+    for (index, target_block) in target_blocks.iter().copied().enumerate() {
+        let authority_set_id = contract_authority_set_id + index as u64;
+        if target_block > latest_block {
+            steps.push(CatchUpStep {
+                kind: CatchUpStepKind::HeaderRanges {
+                    authority_set_id,
+                    target_block,
+                },
+                description: format!(
+                    "header ranges to block {} with authority set {}",
+                    target_block, authority_set_id
+                ),
+                depends_on: previous_header_range
+                    .into_iter()
+                    .chain(previous_rotate)
+                    .collect(),
+                committed: false,
+            });
+            previous_header_range = Some(steps.len() - 1);
+        }
+
+        // The authority set of the head has not ended, so there is no rotate from it yet.
+        if index == target_blocks.len() - 1 {
+            break;
+        }
+
+        steps.push(CatchUpStep {
+            kind: CatchUpStepKind::Rotate { authority_set_id },
+            description: format!(
+                "rotate from authority set {} to {}",
+                authority_set_id,
+                authority_set_id + 1
+            ),
+            depends_on: previous_rotate.into_iter().collect(),
+            committed: false,
+        });
+        previous_rotate = Some(steps.len() - 1);
+    }
+    steps
+}
+
+// The dependencies of the step that are not committed yet. The header ranges before a step of
+// header ranges do not block it, since the pipeline proves ranges ahead of the contract.
+fn blocking_dependencies(steps: &[CatchUpStep], step: &CatchUpStep) -> Vec<usize> {
+    step.depends_on
+        .iter()
+        .copied()
+        .filter(|dependency| {
+            let dependency_step = &steps[*dependency];
+            let pipelined = matches!(step.kind, CatchUpStepKind::HeaderRanges { .. })
+                && matches!(dependency_step.kind, CatchUpStepKind::HeaderRanges { .. });
+            !dependency_step.committed && !pipelined
+        })
+        .collect()
 }
 
 #[derive(Debug)]
@@ -46,396 +856,3090 @@ struct RotateContractData {
 }
 
 impl VectorXOperator {
-    async fn new(data_fetcher: RpcDataFetcher) -> Self {
+    async fn new(
+        deployment: &Deployment,
+        metrics: OperatorMetrics,
+        relayer: Option<Arc<Mutex<Relayer>>>,
+        dry_run: bool,
+        elect_leader: bool,
+    ) -> Self {
         dotenv::dotenv().ok();
 
-        let contract_address = env::var("CONTRACT_ADDRESS").expect("CONTRACT_ADDRESS must be set");
-        let chain_id = env::var("CHAIN_ID").expect("CHAIN_ID must be set");
-        let address = contract_address
+        let data_fetcher =
+            RpcDataFetcher::with_avail(&deployment.avail_url, &deployment.chain_name).await;
+
+        let address = deployment
+            .contract_address
             .parse::<Address>()
             .expect("invalid address");
 
-        let ethereum_rpc_url = env::var("ETHEREUM_RPC_URL").expect("ETHEREUM_RPC_URL must be set");
-        let provider =
-            Provider::<Http>::try_from(ethereum_rpc_url).expect("could not connect to client");
+        let provider = Provider::<Http>::try_from(deployment.ethereum_rpc_url.as_str())
+            .expect("could not connect to client");
 
         let contract = VectorX::new(address.0 .0, provider.clone().into());
 
+        // Manual commands run without a relayer, so without the fallback.
+        let guardian_fallback = relayer.is_some()
+            && env::var("RELAY_GUARDIAN_FALLBACK")
+                .is_ok_and(|guardian_fallback| guardian_fallback == "true");
+
         let config = VectorXConfig {
             address,
-            chain_id: chain_id.parse::<u32>().expect("invalid chain id"),
+            chain_id: deployment.chain_id,
         };
 
-        let succinct_rpc_url = env::var("SUCCINCT_RPC_URL").expect("SUCCINCT_RPC_URL must be set");
-        let succinct_api_key = env::var("SUCCINCT_API_KEY").expect("SUCCINCT_API_KEY must be set");
-        let client = SuccinctClient::new(succinct_rpc_url, succinct_api_key, false, false);
+        let prover = ProverBackend::new(deployment.prover_backend);
+
+        let request_timeout_mins_env = env::var("REQUEST_TIMEOUT_MINS");
+        let mut request_timeout_mins = 60;
+        if let Ok(request_timeout_mins_env) = request_timeout_mins_env {
+            request_timeout_mins = request_timeout_mins_env
+                .parse::<u64>()
+                .expect("invalid REQUEST_TIMEOUT_MINS");
+        }
+
+        let catch_up_poll_secs_env = env::var("CATCH_UP_POLL_SECS");
+        let mut catch_up_poll_secs = 60;
+        if let Ok(catch_up_poll_secs_env) = catch_up_poll_secs_env {
+            catch_up_poll_secs = catch_up_poll_secs_env
+                .parse::<u64>()
+                .expect("invalid CATCH_UP_POLL_SECS");
+        }
+
+        let pipeline_depth_env = env::var("PIPELINE_DEPTH");
+        let mut pipeline_depth = 1;
+        if let Ok(pipeline_depth_env) = pipeline_depth_env {
+            pipeline_depth = pipeline_depth_env
+                .parse::<u32>()
+                .expect("invalid PIPELINE_DEPTH");
+        }
+        // The platform relays each proof as soon as it is proven, so a later range could reach the
+        // contract before the range it starts from. Only the local backend holds proofs until the
+        // contract can verify them.
+        if pipeline_depth > 1 && matches!(prover, ProverBackend::Platform { .. }) {
+            warn!(
+                "PIPELINE_DEPTH={} is not supported with the platform prover on chain {}, using 1.",
+                pipeline_depth, deployment.chain_id
+            );
+            pipeline_depth = 1;
+        }
+
+        // With LEADER_ELECTION=true, only the operator holding the lease submits requests. Dry runs
+        // and manual commands never take the lease.
+        let mut leader_lease = None;
+        if elect_leader
+            && !dry_run
+            && env::var("LEADER_ELECTION").is_ok_and(|leader_election| leader_election == "true")
+        {
+            let mut leader_lease_secs = 30;
+            if let Ok(leader_lease_secs_env) = env::var("LEADER_LEASE_SECS") {
+                leader_lease_secs = leader_lease_secs_env
+                    .parse::<u64>()
+                    .expect("invalid LEADER_LEASE_SECS");
+            }
+            let operator_id =
+                env::var("OPERATOR_ID").unwrap_or(format!("{}-{}", std::process::id(), now_secs()));
+            let lease = LeaderLease::new(
+                data_fetcher.redis_client.clone(),
+                &config,
+                operator_id,
+                Duration::from_secs(leader_lease_secs),
+            );
+            lease.spawn_renewal();
+            leader_lease = Some(lease);
+        }
 
         Self {
             config,
             contract,
-            client,
+            prover,
             data_fetcher,
+            alerter: Alerter::new(),
+            request_timeout: Duration::from_secs(60 * request_timeout_mins),
+            pipeline_depth,
+            catch_up_poll_interval: Duration::from_secs(catch_up_poll_secs),
+            relayer,
+            guardian_fallback,
+            relayed_calls: HashMap::new(),
+            metrics,
+            last_commit: None,
+            epoch_end_estimate: None,
+            blocks_behind_head_alert: ThresholdAlert::from_env(
+                "Blocks behind head",
+                "ALERT_BLOCKS_BEHIND_HEAD",
+            ),
+            seconds_since_last_commit_alert: ThresholdAlert::from_env(
+                "Seconds since last commit",
+                "ALERT_SECS_SINCE_COMMIT",
+            ),
+            pending_requests_alert: ThresholdAlert::from_env(
+                "Pending requests",
+                "ALERT_PENDING_REQUESTS",
+            ),
+            leader_lease,
+            header_range_function_id: deployment
+                .header_range_function_id
+                .map(|function_id| B256::from(function_id.0)),
+            rotate_function_id: deployment
+                .rotate_function_id
+                .map(|function_id| B256::from(function_id.0)),
+            max_requests_per_hour: deployment.max_requests_per_hour,
+            max_requests_per_day: deployment.max_requests_per_day,
+            max_fee_per_gas: deployment
+                .max_gas_price_gwei
+                .map(|gwei| U256::from(gwei) * U256::exp10(9)),
+            max_relay_spend_gwei_per_day: deployment.max_relay_spend_gwei_per_day,
+            min_new_blocks: deployment.min_new_blocks,
+            preflight_failures: HashMap::new(),
+            unprovable_range: None,
+            epoch_end_blocks: HashMap::new(),
+            // EVENT_DRIVEN is either true (default) or false.
+            event_driven: env::var("EVENT_DRIVEN")
+                .map_or(true, |event_driven| event_driven != "false"),
+            finalized_heads: None,
+            contract_events: None,
+            last_finalized_head: None,
+            dry_run,
         }
     }
 
-    async fn request_header_range(
-        &mut self,
-        trusted_block: u32,
-        trusted_authority_set_id: u64,
-        target_block: u32,
-        header_range_function_id: B256,
-    ) -> Result<String> {
-        let client = self.get_succinct_client();
-        let config = self.get_config();
+    // The chain id and address labels of the deployment's metrics.
+    fn metric_labels(&self) -> [String; 2] {
+        [
+            self.config.chain_id.to_string(),
+            self.config.address.to_string(),
+        ]
+    }
 
-        let (trusted_header_hash, trusted_authority_set_hash) = self
-            .get_header_range_input_data(trusted_block, trusted_authority_set_id)
-            .await;
+    fn record_request_failure(&self, request_type: &str, reason: &str) {
+        let [chain_id, contract] = self.metric_labels();
+        self.metrics
+            .request_failures
+            .with_label_values(&[&chain_id, &contract, request_type, reason])
+            .inc();
+    }
 
-        let input = HeaderRangeInputTuple::abi_encode_packed(&(
-            trusted_block,
-            trusted_header_hash,
-            trusted_authority_set_id,
-            trusted_authority_set_hash,
-            target_block,
-        ));
+    fn record_deferred_request(&self, request_type: &str, reason: &str) {
+        let [chain_id, contract] = self.metric_labels();
+        self.metrics
+            .deferred_requests
+            .with_label_values(&[&chain_id, &contract, request_type, reason])
+            .inc();
+    }
 
-        // Encode the call into calldata.
-        // Note: Use vector_x because the calls are the same.
-        let commit_header_range_call = vector_x::CommitHeaderRangeCall {
-            authority_set_id: trusted_authority_set_id,
-            target_block,
+    fn record_rpc_error(&self, endpoint: &str) {
+        let [chain_id, contract] = self.metric_labels();
+        self.metrics
+            .rpc_errors
+            .with_label_values(&[&chain_id, &contract, endpoint])
+            .inc();
+    }
+
+    // Adds the gas fees paid by a mined transaction to the deployment's relay spend metric, and
+    // returns them in gwei.
+    fn record_relay_spend(&self, request_type: &str, receipt: &TransactionReceipt) -> Option<u64> {
+        let spend_wei = relay_spend_wei(receipt)?;
+        info!(
+            "Transaction {:?} paid {} wei in gas fees.",
+            receipt.transaction_hash, spend_wei
+        );
+        let [chain_id, contract] = self.metric_labels();
+        self.metrics
+            .relay_spend_gwei
+            .with_label_values(&[&chain_id, &contract, request_type])
+            .inc_by(spend_wei.as_u128() as f64 / 1e9);
+        Some((spend_wei / U256::exp10(9)).as_u64())
+    }
+
+    // The epoch end block of the head's authority set is not known until it is finalized, so it is
+    // estimated from the length of the previous epoch.
+    async fn update_epoch_end_estimate(&mut self, head_block: u32) -> Result<()> {
+        let head_authority_set_id = self.head_authority_set_id(head_block).await?;
+        if head_authority_set_id >= 2
+            && self
+                .epoch_end_estimate
+                .map(|(authority_set_id, _)| authority_set_id)
+                != Some(head_authority_set_id)
+        {
+            let previous_epoch_end = self.last_justified_block(head_authority_set_id - 1).await?;
+            let epoch_before_end = self.last_justified_block(head_authority_set_id - 2).await?;
+            self.epoch_end_estimate = Some((
+                head_authority_set_id,
+                previous_epoch_end + previous_epoch_end.saturating_sub(epoch_before_end),
+            ));
+        }
+        Ok(())
+    }
+
+    // Updates the metrics of the deployment, and alerts on the values above their thresholds, except
+    // in dry-run mode.
+    async fn update_metrics(&mut self) {
+        let [chain_id, contract] = self.metric_labels();
+        let labels = [chain_id.as_str(), contract.as_str()];
+        let context = format!("on chain {} at {}", chain_id, contract);
+
+        let latest_block = match self.contract.latest_block().await {
+            Ok(latest_block) => latest_block,
+            Err(e) => {
+                error!("Failed to get the latest block: {}", e);
+                self.record_rpc_error("ethereum");
+                return;
+            }
         };
-        let function_data = commit_header_range_call.encode();
+        let head_block = match self.data_fetcher.get_head().await {
+            Ok(head) => head.number,
+            Err(e) => {
+                error!("Failed to get the head of the Avail chain: {}", e);
+                self.record_rpc_error("avail");
+                return;
+            }
+        };
+        let blocks_behind_head = head_block.saturating_sub(latest_block) as i64;
+        self.metrics
+            .blocks_behind_head
+            .with_label_values(&labels)
+            .set(blocks_behind_head);
+        if !self.dry_run {
+            self.blocks_behind_head_alert
+                .check(&self.alerter, blocks_behind_head, &context)
+                .await;
+        }
+
+        if let Err(e) = self.update_epoch_end_estimate(head_block).await {
+            error!("Failed to estimate the epoch end block: {:#}", e);
+            self.record_rpc_error(error_endpoint(&e));
+        }
+        if let Some((_, epoch_end_block)) = self.epoch_end_estimate {
+            self.metrics
+                .blocks_to_epoch_end
+                .with_label_values(&labels)
+                .set(epoch_end_block.saturating_sub(head_block) as i64);
+        }
+
+        let config = self.get_config();
+        let pending_requests = match self
+            .data_fetcher
+            .redis_client
+            .get_pending_proof_requests(config.chain_id as u64, config.address.to_vec())
+            .await
+        {
+            Ok(pending_requests) => pending_requests,
+            Err(e) => {
+                error!("Failed to get the pending requests: {}", e);
+                self.record_rpc_error("redis");
+                return;
+            }
+        };
+        for request_type in [HEADER_RANGE_REQUEST, ROTATE_REQUEST] {
+            let count = pending_requests
+                .iter()
+                .filter(|request| request.request_type == request_type)
+                .count();
+            self.metrics
+                .pending_requests
+                .with_label_values(&[&chain_id, &contract, request_type])
+                .set(count as i64);
+        }
+        if !self.dry_run {
+            self.pending_requests_alert
+                .check(&self.alerter, pending_requests.len() as i64, &context)
+                .await;
+        }
+
+        // Measured from when the operator started if latestBlock has not advanced since.
+        let now = now_secs();
+        match self.last_commit {
+            Some((block, _)) if block == latest_block => {}
+            _ => self.last_commit = Some((latest_block, now)),
+        }
+        let seconds_since_last_commit = now.saturating_sub(
+            self.last_commit
+                .map_or(now, |(_, committed_at)| committed_at),
+        ) as i64;
+        self.metrics
+            .seconds_since_last_commit
+            .with_label_values(&labels)
+            .set(seconds_since_last_commit);
+        if !self.dry_run {
+            self.seconds_since_last_commit_alert
+                .check(&self.alerter, seconds_since_last_commit, &context)
+                .await;
+        }
+    }
+
+    // Reports the reason for a planning decision. In dry-run mode, it is printed with the plan.
+    fn explain(&self, reason: &str) {
+        if self.dry_run {
+            println!("{}", reason);
+        } else {
+            info!("{}", reason);
+        }
+    }
+
+    // Prints a request planned in dry-run mode, with the input and calldata that would be
+    // submitted.
+    fn print_planned_request(
+        &self,
+        description: &str,
+        function_id: B256,
+        input: &[u8],
+        function_data: &[u8],
+    ) {
+        println!("Planned {}:", description);
+        println!("  function id: {}", function_id);
+        println!("  input: 0x{}", hex::encode(input));
+        println!("  calldata: 0x{}", hex::encode(function_data));
+    }
+
+    // Submits a request to the prover backend. With the platform, the platform proves the request
+    // and relays the proof. With the local prover, the proof is generated in the background.
+    async fn submit_request(
+        &self,
+        circuit: Circuit,
+        function_data: Vec<u8>,
+        function_id: B256,
+        input: Vec<u8>,
+    ) -> Result<String> {
+        let config = self.config.clone();
+
+        match self.prover.clone() {
+            ProverBackend::Platform { client, .. } => {
+                client
+                    .submit_platform_request(
+                        config.chain_id,
+                        config.address,
+                        function_data.into(),
+                        function_id,
+                        Bytes::copy_from_slice(&input),
+                    )
+                    .await
+            }
+            ProverBackend::Local(prover) => {
+                prover
+                    .submit(
+                        circuit,
+                        H256::from(function_id.0),
+                        input,
+                        config.address.0 .0.into(),
+                        function_data,
+                    )
+                    .await
+            }
+        }
+    }
+
+    async fn get_request_status(&self, request_id: &str) -> RequestStatus {
+        match &self.prover {
+            ProverBackend::Platform {
+                rpc_url, api_key, ..
+            } => match get_platform_request_status(rpc_url, api_key, request_id).await {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("Failed to get the status of request {}: {}", request_id, e);
+                    self.record_rpc_error("prover");
+                    RequestStatus::Pending
+                }
+            },
+            ProverBackend::Local(prover) => prover.get_status(request_id).await,
+        }
+    }
 
-        let request_id = client
-            .submit_platform_request(
-                config.chain_id,
-                config.address,
-                function_data.into(),
-                header_range_function_id,
-                Bytes::copy_from_slice(&input),
+    // Persists a submitted request, so it is not submitted again while it is pending.
+    async fn track_request(&mut self, mut request: StoredProofRequest) -> Result<()> {
+        let config = self.get_config();
+        let failed_attempts = self
+            .data_fetcher
+            .redis_client
+            .get_failed_request_attempts(
+                config.chain_id as u64,
+                config.address.to_vec(),
+                &request_params(&request),
             )
-            .await?;
+            .await
+            .context(Endpoint::Redis)?;
+        request.attempt = failed_attempts + 1;
 
-        Ok(request_id)
+        self.data_fetcher
+            .redis_client
+            .add_proof_request(config.chain_id as u64, config.address.to_vec(), request)
+            .await
+            .context(Endpoint::Redis)
+    }
+
+    // Whether a request was committed on-chain, from the contract state.
+    async fn is_request_committed(&mut self, request: &StoredProofRequest) -> Result<bool> {
+        match request.request_type.as_str() {
+            HEADER_RANGE_REQUEST => Ok(self.contract.latest_block().await? >= request.target_block),
+            _ => {
+                let next_authority_set_hash = self
+                    .contract
+                    .authority_set_id_to_hash(request.authority_set_id + 1)
+                    .await?;
+                Ok(B256::from_slice(&next_authority_set_hash) != B256::ZERO)
+            }
+        }
+    }
+
+    // Checks every pending request. Requests committed on-chain are done. Requests that failed or
+    // timed out are dropped so they can be retried, and are escalated once the same request has
+    // failed MAX_REQUEST_ATTEMPTS times. Pipelined header ranges do not time out while the ranges
+    // before them are pending, and header ranges that no longer chain from the contract's latest
+    // block are dropped, as they can never be committed.
+    async fn update_pending_requests(&mut self) -> Result<()> {
+        let config = self.get_config();
+        let pending_requests = self
+            .data_fetcher
+            .redis_client
+            .get_pending_proof_requests(config.chain_id as u64, config.address.to_vec())
+            .await
+            .context(Endpoint::Redis)?;
+
+        // The trusted blocks of the pending header ranges that chain from the latest block.
+        let latest_block = self.contract.latest_block().await?;
+        let mut chained_trusted_blocks = HashSet::new();
+        let mut trusted_block = latest_block;
+        while let Some(pending) = pending_requests.iter().find(|pending| {
+            pending.request_type == HEADER_RANGE_REQUEST && pending.trusted_block == trusted_block
+        }) {
+            chained_trusted_blocks.insert(trusted_block);
+            trusted_block = pending.target_block;
+        }
+
+        for request in pending_requests.iter().cloned() {
+            if self.is_request_committed(&request).await? {
+                info!(
+                    "Request {} ({}) was committed on-chain.",
+                    request.request_id,
+                    request_params(&request)
+                );
+                self.data_fetcher
+                    .redis_client
+                    .remove_pending_proof_request(
+                        config.chain_id as u64,
+                        config.address.to_vec(),
+                        &request.request_id,
+                    )
+                    .await
+                    .context(Endpoint::Redis)?;
+                self.data_fetcher
+                    .redis_client
+                    .clear_failed_request_attempts(
+                        config.chain_id as u64,
+                        config.address.to_vec(),
+                        &request_params(&request),
+                    )
+                    .await
+                    .context(Endpoint::Redis)?;
+                continue;
+            }
+
+            if request.request_type == HEADER_RANGE_REQUEST
+                && !chained_trusted_blocks.contains(&request.trusted_block)
+            {
+                info!(
+                    "Request {} ({}) no longer follows the latest block {}, dropping it.",
+                    request.request_id,
+                    request_params(&request),
+                    latest_block
+                );
+                self.data_fetcher
+                    .redis_client
+                    .remove_pending_proof_request(
+                        config.chain_id as u64,
+                        config.address.to_vec(),
+                        &request.request_id,
+                    )
+                    .await
+                    .context(Endpoint::Redis)?;
+                continue;
+            }
+
+            let status = self.get_request_status(&request.request_id).await;
+            let waiting_for_previous_range = request.request_type == HEADER_RANGE_REQUEST
+                && request.trusted_block > latest_block;
+            let timed_out = !waiting_for_previous_range
+                && now_secs().saturating_sub(request.submitted_at) > self.request_timeout.as_secs();
+            if status != RequestStatus::Failed && !timed_out {
+                continue;
+            }
+
+            let reason = if status == RequestStatus::Failed {
+                "failed"
+            } else {
+                "timed out"
+            };
+            self.record_request_failure(&request.request_type, &reason.replace(' ', "_"));
+            let failed_attempts = self
+                .data_fetcher
+                .redis_client
+                .add_failed_request_attempt(
+                    config.chain_id as u64,
+                    config.address.to_vec(),
+                    &request_params(&request),
+                )
+                .await
+                .context(Endpoint::Redis)?;
+            self.data_fetcher
+                .redis_client
+                .remove_pending_proof_request(
+                    config.chain_id as u64,
+                    config.address.to_vec(),
+                    &request.request_id,
+                )
+                .await
+                .context(Endpoint::Redis)?;
+
+            if failed_attempts >= MAX_REQUEST_ATTEMPTS
+                && self.guardian_fallback
+                && request.request_type == HEADER_RANGE_REQUEST
+            {
+                self.alerter
+                    .alert(&format!(
+                        "Request {} ({}) {} after {} attempts on chain {} at {:?}, filling it with the guardian fallback.",
+                        request.request_id,
+                        request_params(&request),
+                        reason,
+                        failed_attempts,
+                        config.chain_id,
+                        config.address
+                    ))
+                    .await;
+                self.fill_with_guardian(request.target_block).await?;
+            } else if failed_attempts >= MAX_REQUEST_ATTEMPTS {
+                self.alerter
+                    .alert(&format!(
+                        "Request {} ({}) {} after {} attempts on chain {} at {:?}, not retrying.",
+                        request.request_id,
+                        request_params(&request),
+                        reason,
+                        failed_attempts,
+                        config.chain_id,
+                        config.address
+                    ))
+                    .await;
+            } else {
+                warn!(
+                    "Request {} ({}) {} after {} attempts, retrying.",
+                    request.request_id,
+                    request_params(&request),
+                    reason,
+                    failed_attempts
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Relays the local proofs of pending requests through the gateway, in the order the contract
+    // can verify them: rotates from stored authority sets first, then header ranges from the
+    // contract's latest block. Proofs that are already being relayed are skipped, and the
+    // transactions are not waited on: check_relayed_transactions follows them on later loops.
+    async fn relay_local_proofs(&mut self) -> Result<()> {
+        let ProverBackend::Local(prover) = self.prover.clone() else {
+            return Ok(());
+        };
+        let Some(relayer) = self.relayer.clone() else {
+            return Ok(());
+        };
+
+        let config = self.get_config();
+        let mut pending_requests = self
+            .data_fetcher
+            .redis_client
+            .get_pending_proof_requests(config.chain_id as u64, config.address.to_vec())
+            .await
+            .context(Endpoint::Redis)?;
+        pending_requests.sort_by_key(|request| {
+            (
+                request.request_type != ROTATE_REQUEST,
+                request.authority_set_id,
+                request.trusted_block,
+            )
+        });
+
+        let gateway = self.contract.gateway().await?;
+        for request in pending_requests {
+            let relaying = self.relayed_calls.values().any(|call| {
+                call.request
+                    .as_ref()
+                    .is_some_and(|relayed| relayed.request_id == request.request_id)
+            });
+            if relaying
+                || prover.get_status(&request.request_id).await != RequestStatus::Succeeded
+                || self.is_request_committed(&request).await?
+            {
+                continue;
+            }
+
+            // Wait for the requests before it to be committed.
+            let verifiable = match request.request_type.as_str() {
+                HEADER_RANGE_REQUEST => {
+                    self.contract.latest_block().await? == request.trusted_block
+                }
+                _ => {
+                    self.authority_set_hash_exists(request.authority_set_id)
+                        .await?
+                }
+            };
+            if !verifiable {
+                continue;
+            }
+
+            // The proofs after it wait for it anyway.
+            if let Some((reason, explanation)) = self.relay_deferral(&relayer).await? {
+                self.explain(&format!("Relay deferred: {}.", explanation));
+                self.record_deferred_request(&request.request_type, reason);
+                break;
+            }
+
+            let Some(proof) = prover.get_proof(&request.request_id).await else {
+                continue;
+            };
+            let result = relayer
+                .lock()
+                .await
+                .fulfill(
+                    self.config.address.0 .0.into(),
+                    gateway,
+                    &proof,
+                    self.max_fee_per_gas,
+                )
+                .await;
+            match result {
+                Ok(nonce) => {
+                    info!(
+                        "Relaying request {} ({}) with nonce {}.",
+                        request.request_id,
+                        request_params(&request),
+                        nonce
+                    );
+                    self.relayed_calls.insert(
+                        nonce,
+                        RelayedCall {
+                            description: format!(
+                                "request {} ({})",
+                                request.request_id,
+                                request_params(&request)
+                            ),
+                            request: Some(request),
+                        },
+                    );
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to relay request {} ({}): {}",
+                        request.request_id,
+                        request_params(&request),
+                        e
+                    );
+                    self.record_request_failure(&request.request_type, "relay");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Polls the relayer's transactions, and reports the deployment's transactions that were mined
+    // or failed. A transaction that is stuck is alerted on but still followed, so its call is not
+    // sent again while a replacement may still be mined.
+    async fn check_relayed_transactions(&mut self) {
+        let Some(relayer) = self.relayer.clone() else {
+            return;
+        };
+        let updates = {
+            let mut relayer = relayer.lock().await;
+            if let Err(e) = relayer.poll().await {
+                error!("Failed to check the relayed transactions: {}", e);
+                self.record_rpc_error("ethereum");
+            }
+            relayer.take_updates(self.config.address.0 .0.into())
+        };
+
+        for update in updates {
+            let Some(call) = self.relayed_calls.get(&update.nonce) else {
+                continue;
+            };
+            if let RelayOutcome::Mined(receipt) | RelayOutcome::Reverted(receipt) = &update.outcome
+            {
+                let request_type = call
+                    .request
+                    .as_ref()
+                    .map_or(GUARDIAN_FALLBACK, |request| request.request_type.as_str());
+                if let Some(spend_gwei) = self.record_relay_spend(request_type, receipt) {
+                    // Persisted for the relay spend budget, which holds across restarts.
+                    if let Err(e) = self
+                        .data_fetcher
+                        .redis_client
+                        .add_relay_spend(
+                            self.config.chain_id as u64,
+                            self.config.address.to_vec(),
+                            receipt.transaction_hash,
+                            spend_gwei,
+                            now_secs(),
+                        )
+                        .await
+                    {
+                        error!(
+                            "Failed to store the relay spend of transaction {:?}: {:#}",
+                            receipt.transaction_hash, e
+                        );
+                        self.record_rpc_error("redis");
+                    }
+                }
+            }
+            let failure = match &update.outcome {
+                RelayOutcome::Mined(receipt) => {
+                    info!(
+                        "Relayed {} in transaction {:?}.",
+                        call.description, receipt.transaction_hash
+                    );
+                    None
+                }
+                RelayOutcome::Reverted(receipt) => Some(format!(
+                    "reverted in transaction {:?}",
+                    receipt.transaction_hash
+                )),
+                RelayOutcome::Dropped => {
+                    Some("was dropped, its nonce was used by another transaction".to_string())
+                }
+                RelayOutcome::Stuck => {
+                    self.alerter
+                        .alert(&format!(
+                            "Relaying {} with nonce {} on chain {} at {:?} is stuck after all fee bumps, still waiting for it.",
+                            call.description, update.nonce, self.config.chain_id, self.config.address
+                        ))
+                        .await;
+                    continue;
+                }
+            };
+
+            let call = self.relayed_calls.remove(&update.nonce).unwrap();
+            let Some(failure) = failure else {
+                continue;
+            };
+            match call.request {
+                Some(request) => {
+                    error!("Relaying {} {}.", call.description, failure);
+                    self.record_request_failure(&request.request_type, "relay");
+                }
+                None => {
+                    self.alerter
+                        .alert(&format!("Relaying {} {}.", call.description, failure))
+                        .await
+                }
+            }
+        }
+    }
+
+    // Posts the header hashes and commitments from the contract's latest block towards the target
+    // block with updateBlockRangeData, which the relayer's signer must be the guardian to call. One
+    // call fills the blocks of one authority set, so a fill across an epoch end block is continued
+    // by the next loops from the new latest block.
+    async fn fill_with_guardian(&mut self, target_block: u32) -> Result<()> {
+        let Some(relayer) = self.relayer.clone() else {
+            return Ok(());
+        };
+
+        if self
+            .relayed_calls
+            .values()
+            .any(|call| call.request.is_none())
+        {
+            self.explain("Guardian fallback deferred, a guardian fallback is still being relayed.");
+            return Ok(());
+        }
+
+        let latest_block = self.contract.latest_block().await?;
+        if target_block > latest_block {
+            if let Some((_, explanation)) = self.relay_deferral(&relayer).await? {
+                self.alerter
+                    .alert(&format!(
+                        "Guardian fallback from block {} to {} deferred, {}.",
+                        latest_block, target_block, explanation
+                    ))
+                    .await;
+                return Ok(());
+            }
+
+            let batch = self
+                .data_fetcher
+                .get_block_range_batch(latest_block, target_block)
+                .await
+                .context(Endpoint::Avail)?;
+            let batch_end = batch.end_blocks[batch.end_blocks.len() - 1];
+            let update_block_range_call = vector_x::UpdateBlockRangeDataCall {
+                start_blocks: batch.start_blocks,
+                end_blocks: batch.end_blocks,
+                header_hashes: batch.header_hashes,
+                data_root_commitments: batch.data_root_commitments,
+                state_root_commitments: batch.state_root_commitments,
+                end_authority_set_id: batch.end_authority_set_id,
+                end_authority_set_hash: batch.end_authority_set_hash,
+            };
+            let result = relayer
+                .lock()
+                .await
+                .send(
+                    self.config.address.0 .0.into(),
+                    self.config.address.0 .0.into(),
+                    update_block_range_call.encode().into(),
+                    self.max_fee_per_gas,
+                )
+                .await;
+            match result {
+                Ok(nonce) => {
+                    info!(
+                        "Filling blocks {} to {} with the guardian fallback, with nonce {}.",
+                        latest_block, batch_end, nonce
+                    );
+                    self.relayed_calls.insert(
+                        nonce,
+                        RelayedCall {
+                            description: format!(
+                                "the guardian fallback from block {} to {}",
+                                latest_block, batch_end
+                            ),
+                            request: None,
+                        },
+                    );
+                }
+                Err(e) => {
+                    self.alerter
+                        .alert(&format!(
+                            "Guardian fallback from block {} to {} failed: {}",
+                            latest_block, batch_end, e
+                        ))
+                        .await
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Reports a request that the check finds certain to fail. The reason is alerted on once until
+    // the check passes or fails for another reason, except in dry-run mode.
+    async fn report_preflight_failure(&mut self, check: &'static str, reason: String) {
+        self.explain(&reason);
+        if !self.dry_run && self.preflight_failures.get(check) != Some(&reason) {
+            self.preflight_failures.insert(check, reason.clone());
+            self.alerter.alert(&reason).await;
+        }
+    }
+
+    // Records that the check passed, so it is alerted on again if it fails later.
+    fn clear_preflight_failure(&mut self, check: &'static str) {
+        if self.preflight_failures.remove(check).is_some() {
+            info!("Preflight check {} passes again.", check);
+        }
+    }
+
+    // Checks the contract state that every request depends on. Returns whether header ranges and
+    // rotates can be requested: none can while the contract is frozen, and a request type cannot
+    // while the contract's function ID differs from the expected one.
+    async fn preflight(&mut self) -> Result<(bool, bool)> {
+        if self.contract.frozen().await? {
+            self.report_preflight_failure(
+                "frozen",
+                "Not requesting anything: the contract is frozen.".to_string(),
+            )
+            .await;
+            return Ok((false, false));
+        }
+        self.clear_preflight_failure("frozen");
+
+        let mut allowed = Vec::new();
+        for (check, request_type, expected_function_id, function_id) in [
+            (
+                "header_range_function_id",
+                HEADER_RANGE_REQUEST,
+                self.header_range_function_id,
+                self.contract.header_range_function_id().await?,
+            ),
+            (
+                "rotate_function_id",
+                ROTATE_REQUEST,
+                self.rotate_function_id,
+                self.contract.rotate_function_id().await?,
+            ),
+        ] {
+            let function_id = B256::from(function_id);
+            match expected_function_id {
+                Some(expected_function_id) if expected_function_id != function_id => {
+                    self.report_preflight_failure(
+                        check,
+                        format!(
+                            "Not requesting {} proofs: the contract's function ID {} is not the expected {}.",
+                            request_type, function_id, expected_function_id
+                        ),
+                    )
+                    .await;
+                    allowed.push(false);
+                }
+                _ => {
+                    self.clear_preflight_failure(check);
+                    allowed.push(true);
+                }
+            }
+        }
+        Ok((allowed[0], allowed[1]))
+    }
+
+    // Checks that a header range can be proven: the authority set must fit in the circuit, and no
+    // header in the range may be larger than the circuit's maximum. Returns the target to request,
+    // which is lowered to a provable block before the first oversized header if needed. Returns
+    // None if no header range from the trusted block can be proven, in which case the range is
+    // planned to be filled with the guardian fallback if enabled.
+    async fn preflight_header_range(
+        &mut self,
+        trusted_block: u32,
+        authority_set_id: u64,
+        target_block: u32,
+    ) -> Result<Option<u32>> {
+        let authority_set_size = self
+            .data_fetcher
+            .get_authority_set_size(target_block - 1)
+            .await;
+        if authority_set_size > MAX_AUTHORITY_SET_SIZE {
+            self.report_preflight_failure(
+                "header_range_limits",
+                format!(
+                    "No header range from block {} can be proven: authority set {} has {} authorities, more than the maximum of {}.",
+                    trusted_block, authority_set_id, authority_set_size, MAX_AUTHORITY_SET_SIZE
+                ),
+            )
+            .await;
+            self.plan_unprovable_range_fill(trusted_block, target_block);
+            return Ok(None);
+        }
+
+        let Some((oversized_block, header_size)) = self
+            .data_fetcher
+            .find_oversized_header(trusted_block + 1, target_block)
+            .await
+        else {
+            self.clear_preflight_failure("header_range_limits");
+            return Ok(Some(target_block));
+        };
+
+        let rerouted_target_block = if oversized_block - 1 > trusted_block {
+            self.find_block_to_step_to(trusted_block, oversized_block - 1, authority_set_id)
+                .await?
+        } else {
+            None
+        };
+        match rerouted_target_block {
+            Some(rerouted_target_block) => {
+                self.explain(&format!(
+                    "The header of block {} is {} bytes, more than the maximum of {}, so the header range from block {} targets block {} instead of {}.",
+                    oversized_block, header_size, MAX_HEADER_SIZE, trusted_block, rerouted_target_block, target_block
+                ));
+                self.clear_preflight_failure("header_range_limits");
+                Ok(Some(rerouted_target_block))
+            }
+            None => {
+                self.report_preflight_failure(
+                    "header_range_limits",
+                    format!(
+                        "No header range from block {} can be proven: the header of block {} is {} bytes, more than the maximum of {}.",
+                        trusted_block, oversized_block, header_size, MAX_HEADER_SIZE
+                    ),
+                )
+                .await;
+                self.plan_unprovable_range_fill(trusted_block, target_block);
+                Ok(None)
+            }
+        }
+    }
+
+    // Checks that the rotate from the authority set can be proven: the epoch end block's header and
+    // both authority sets must fit in the circuit. If not, the blocks up to the epoch end block,
+    // and with them the hash of the next authority set, are planned to be filled with the guardian
+    // fallback if enabled.
+    async fn preflight_rotate(&mut self, authority_set_id: u64) -> Result<bool> {
+        let epoch_end_block = self.last_justified_block(authority_set_id).await?;
+
+        let mut reason = None;
+        for (set_id, block) in [
+            (authority_set_id, epoch_end_block - 1),
+            (authority_set_id + 1, epoch_end_block),
+        ] {
+            let authority_set_size = self.data_fetcher.get_authority_set_size(block).await;
+            if authority_set_size > MAX_AUTHORITY_SET_SIZE {
+                reason = Some(format!(
+                    "authority set {} has {} authorities, more than the maximum of {}",
+                    set_id, authority_set_size, MAX_AUTHORITY_SET_SIZE
+                ));
+            }
+        }
+        if let Some((_, header_size)) = self
+            .data_fetcher
+            .find_oversized_header(epoch_end_block, epoch_end_block)
+            .await
+        {
+            reason = Some(format!(
+                "the header of epoch end block {} is {} bytes, more than the maximum of {}",
+                epoch_end_block, header_size, MAX_HEADER_SIZE
+            ));
+        }
+
+        let Some(reason) = reason else {
+            self.clear_preflight_failure("rotate_limits");
+            return Ok(true);
+        };
+        self.report_preflight_failure(
+            "rotate_limits",
+            format!(
+                "The rotate from authority set {} cannot be proven: {}.",
+                authority_set_id, reason
+            ),
+        )
+        .await;
+        let latest_block = self.contract.latest_block().await?;
+        self.plan_unprovable_range_fill(latest_block, epoch_end_block);
+        Ok(false)
+    }
+
+    // Plans to fill a range that cannot be proven with the guardian fallback, if enabled. Only the
+    // first range planned in a loop is kept, as the ranges are planned from the latest block.
+    fn plan_unprovable_range_fill(&mut self, trusted_block: u32, target_block: u32) {
+        if self.guardian_fallback && self.unprovable_range.is_none() {
+            self.unprovable_range = Some((trusted_block, target_block));
+        }
+    }
+
+    // Fills the range found unprovable while planning with the guardian fallback. The fill starts
+    // at the contract's latest block, so it is only done if the range starts there. In dry-run
+    // mode, the fill is printed instead.
+    async fn fill_unprovable_range(&mut self) -> Result<()> {
+        let Some((trusted_block, target_block)) = self.unprovable_range.take() else {
+            return Ok(());
+        };
+        if self.contract.latest_block().await? != trusted_block {
+            return Ok(());
+        }
+        if self.dry_run {
+            println!(
+                "Planned guardian fallback from block {} to block {}.",
+                trusted_block, target_block
+            );
+            return Ok(());
+        }
+        warn!(
+            "Filling blocks {} to {} with the guardian fallback, as they cannot be proven.",
+            trusted_block, target_block
+        );
+        self.fill_with_guardian(target_block).await
+    }
+
+    // Why relays are deferred by the deployment's budgets, if they are. The fee per gas and the
+    // relay spend are only fetched when their budget is set.
+    async fn relay_deferral(
+        &mut self,
+        relayer: &Arc<Mutex<Relayer>>,
+    ) -> Result<Option<(&'static str, String)>> {
+        let fee_per_gas = match self.max_fee_per_gas {
+            Some(_) => relayer.lock().await.estimate_max_fee_per_gas().await?,
+            None => U256::zero(),
+        };
+        let spent_gwei = match self.max_relay_spend_gwei_per_day {
+            Some(_) => {
+                let config = self.get_config();
+                self.data_fetcher
+                    .redis_client
+                    .get_relay_spend_since(
+                        config.chain_id as u64,
+                        config.address.to_vec(),
+                        now_secs().saturating_sub(24 * 60 * 60),
+                    )
+                    .await
+                    .context(Endpoint::Redis)?
+            }
+            None => 0,
+        };
+        Ok(relay_deferral(
+            fee_per_gas,
+            self.max_fee_per_gas,
+            spent_gwei,
+            self.max_relay_spend_gwei_per_day,
+        ))
+    }
+
+    // Defers the planned requests that would go over the budget of requests submitted in the last
+    // hour or day.
+    async fn apply_request_budget(
+        &mut self,
+        mut planned_requests: Vec<PlannedRequest>,
+    ) -> Result<Vec<PlannedRequest>> {
+        let config = self.get_config();
+        let now = now_secs();
+        let mut budgets = Vec::new();
+        for (budget, window_secs, reason) in [
+            (self.max_requests_per_hour, 60 * 60, "hourly_budget"),
+            (self.max_requests_per_day, 24 * 60 * 60, "daily_budget"),
+        ] {
+            let Some(budget) = budget else {
+                continue;
+            };
+            let submitted = self
+                .data_fetcher
+                .redis_client
+                .count_proof_requests_since(
+                    config.chain_id as u64,
+                    config.address.to_vec(),
+                    now.saturating_sub(window_secs),
+                )
+                .await
+                .context(Endpoint::Redis)?;
+            budgets.push(RequestBudget {
+                budget,
+                submitted,
+                window_secs,
+                reason,
+            });
+        }
+
+        for (planned, budget) in split_off_over_budget(&mut planned_requests, &budgets) {
+            self.explain(&format!(
+                "Deferring {}: {} of the {} requests allowed in the last {} hours were submitted.",
+                planned.description,
+                budget.submitted,
+                budget.budget,
+                budget.window_secs / (60 * 60)
+            ));
+            self.record_deferred_request(&planned.request.request_type, budget.reason);
+        }
+        Ok(planned_requests)
+    }
+
+    // Whether a request of the type is pending, or may not be submitted again because the same
+    // request failed too many times. For header ranges, any pending request from the trusted
+    // block blocks a new one, as it would advance the same trusted block.
+    async fn should_skip_request(&mut self, request: &StoredProofRequest) -> Result<bool> {
+        if let Some(pending) = self.find_pending_request(request).await? {
+            self.explain(&format!(
+                "Request {} ({}) is pending, not submitting {}.",
+                pending.request_id,
+                request_params(&pending),
+                request_params(request)
+            ));
+            return Ok(true);
+        }
+
+        let config = self.get_config();
+        let failed_attempts = self
+            .data_fetcher
+            .redis_client
+            .get_failed_request_attempts(
+                config.chain_id as u64,
+                config.address.to_vec(),
+                &request_params(request),
+            )
+            .await
+            .context(Endpoint::Redis)?;
+        if failed_attempts >= MAX_REQUEST_ATTEMPTS {
+            self.explain(&format!(
+                "Request {} failed {} times, not submitting.",
+                request_params(request),
+                failed_attempts
+            ));
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    // The pending request of the same type from the same trusted block or authority set, if any.
+    async fn find_pending_request(
+        &mut self,
+        request: &StoredProofRequest,
+    ) -> Result<Option<StoredProofRequest>> {
+        let config = self.get_config();
+        let pending_requests = self
+            .data_fetcher
+            .redis_client
+            .get_pending_proof_requests(config.chain_id as u64, config.address.to_vec())
+            .await
+            .context(Endpoint::Redis)?;
+        Ok(pending_requests.into_iter().find(|pending| {
+            pending.request_type == request.request_type
+                && match request.request_type.as_str() {
+                    HEADER_RANGE_REQUEST => pending.trusted_block == request.trusted_block,
+                    _ => pending.authority_set_id == request.authority_set_id,
+                }
+        }))
+    }
+
+    // Encodes the input and the callback calldata of a header range request.
+    async fn encode_header_range_request(
+        &mut self,
+        trusted_block: u32,
+        trusted_authority_set_id: u64,
+        target_block: u32,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (mut trusted_header_hash, trusted_authority_set_hash) = self
+            .get_header_range_input_data(trusted_block, trusted_authority_set_id)
+            .await?;
+
+        // A pipelined range starts at the target of a range that is not committed yet, so the
+        // contract has no header hash for its trusted block. Use the chain's hash of the block,
+        // which is the hash the range before it commits.
+        if trusted_header_hash == B256::ZERO {
+            trusted_header_hash = B256::from(
+                self.data_fetcher
+                    .get_block_hash(trusted_block)
+                    .await
+                    .context(Endpoint::Avail)?
+                    .0,
+            );
+        }
+
+        let input = HeaderRangeInputTuple::abi_encode_packed(&(
+            trusted_block,
+            trusted_header_hash,
+            trusted_authority_set_id,
+            trusted_authority_set_hash,
+            target_block,
+        ));
+
+        // Encode the call into calldata.
+        // Note: Use vector_x because the calls are the same.
+        let commit_header_range_call = vector_x::CommitHeaderRangeCall {
+            authority_set_id: trusted_authority_set_id,
+            target_block,
+        };
+        let function_data = commit_header_range_call.encode();
+
+        Ok((input, function_data))
+    }
+
+    // Encodes the input and the callback calldata of a rotate request.
+    async fn encode_rotate_request(
+        &mut self,
+        current_authority_set_id: u64,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let current_authority_set_hash =
+            self.get_rotate_input_data(current_authority_set_id).await?;
+
+        info!(
+            "Current authority set hash: {:?}",
+            hex::encode(current_authority_set_hash)
+        );
+
+        let input = RotateInputTuple::abi_encode_packed(&(
+            current_authority_set_id,
+            current_authority_set_hash,
+        ));
+
+        let rotate_call = vector_x::RotateCall {
+            current_authority_set_id,
+        };
+        let function_data = rotate_call.encode();
+
+        Ok((input, function_data))
+    }
+
+    // Finds the rotate to request, if any. Rotates only depend on each other: the rotate from an
+    // authority set needs the hash of that set, which is stored by the previous rotate. So the
+    // lowest authority set whose next hash is missing can be rotated from, independently of how
+    // far header ranges have advanced.
+    async fn find_rotate_request(&mut self) -> Result<Option<PlannedRequest>> {
+        let mut data_fetcher = self.get_data_fetcher();
+
+        let rotate_contract_data = self.get_contract_data_for_rotate().await?;
+
+        let head_block = data_fetcher
+            .get_head()
+            .await
+            .context(Endpoint::Avail)?
+            .number;
+        let head_authority_set_id = self.head_authority_set_id(head_block).await?;
+
+        // The current authority set id is the authority set id of the block before the current block.
+        let contract_authority_set_id = data_fetcher
+            .get_authority_set_id(rotate_contract_data.current_block - 1)
+            .await
+            .context(Endpoint::Avail)?;
+
+        // Find the first authority set whose next authority set hash is not stored yet.
+        let mut current_authority_set_id = contract_authority_set_id;
+        let mut next_authority_set_hash_exists =
+            rotate_contract_data.next_authority_set_hash_exists;
+        while next_authority_set_hash_exists && current_authority_set_id < head_authority_set_id {
+            current_authority_set_id += 1;
+            next_authority_set_hash_exists = self
+                .authority_set_hash_exists(current_authority_set_id + 1)
+                .await?;
+        }
+
+        if current_authority_set_id >= head_authority_set_id {
+            self.explain(&format!(
+                "No rotate needed: the hashes of the authority sets up to {}, the authority set of the head, are stored.",
+                head_authority_set_id
+            ));
+            return Ok(None);
+        }
+
+        let planned = self.plan_rotate_request(current_authority_set_id).await?;
+        if self.should_skip_request(&planned.request).await?
+            || !self.preflight_rotate(current_authority_set_id).await?
+        {
+            return Ok(None);
+        }
+
+        self.explain(&format!(
+            "Rotate needed: the contract is on authority set {}, the head is on authority set {}, and the hash of authority set {} is missing.",
+            contract_authority_set_id,
+            head_authority_set_id,
+            current_authority_set_id + 1
+        ));
+
+        Ok(Some(planned))
+    }
+
+    // Encodes the rotate from the authority set as a request.
+    async fn plan_rotate_request(
+        &mut self,
+        current_authority_set_id: u64,
+    ) -> Result<PlannedRequest> {
+        let rotate_function_id = self
+            .get_contract_data_for_rotate()
+            .await?
+            .rotate_function_id;
+        let (input, function_data) = self.encode_rotate_request(current_authority_set_id).await?;
+        Ok(PlannedRequest {
+            circuit: Circuit::Rotate,
+            description: format!(
+                "rotate from authority set {} to {}",
+                current_authority_set_id,
+                current_authority_set_id + 1
+            ),
+            function_id: rotate_function_id,
+            input,
+            function_data,
+            request: StoredProofRequest {
+                request_id: String::new(),
+                request_type: ROTATE_REQUEST.to_string(),
+                trusted_block: 0,
+                target_block: 0,
+                authority_set_id: current_authority_set_id,
+                submitted_at: 0,
+                attempt: 0,
+            },
+        })
+    }
+
+    // Finds the header range to request from the trusted block, if any.
+    async fn find_header_range_request(
+        &mut self,
+        trusted_block: u32,
+        max_block_to_step_to: u32,
+    ) -> Result<Option<PlannedRequest>> {
+        let Some((request_authority_set_id, block_to_step_to)) = self
+            .find_header_range_target(trusted_block, max_block_to_step_to)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // A range that adds few blocks is deferred until more blocks are provable, unless it ends
+        // at the last block justified by its authority set. Header ranges with the next authority
+        // set can only start from that block.
+        if block_to_step_to - trusted_block < self.min_new_blocks
+            && self.last_justified_block(request_authority_set_id).await? != block_to_step_to
+        {
+            self.explain(&format!(
+                "Deferring the header range from block {} to block {}: it adds fewer than {} blocks.",
+                trusted_block, block_to_step_to, self.min_new_blocks
+            ));
+            self.record_deferred_request(HEADER_RANGE_REQUEST, "min_new_blocks");
+            return Ok(None);
+        }
+
+        let Some(block_to_step_to) = self
+            .preflight_header_range(trusted_block, request_authority_set_id, block_to_step_to)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let planned = self
+            .plan_header_range_request(trusted_block, request_authority_set_id, block_to_step_to)
+            .await?;
+        if self.should_skip_request(&planned.request).await? {
+            return Ok(None);
+        }
+
+        self.explain(&format!(
+            "Header range from block {} to block {} with authority set {}.",
+            trusted_block, block_to_step_to, request_authority_set_id
+        ));
+
+        Ok(Some(planned))
+    }
+
+    // Finds the authority set that can prove a header range from the trusted block. That is the
+    // authority set of the block after it, which is the next authority set if the trusted block is
+    // the last block justified by its authority set. Returns None if the hash of the next
+    // authority set is needed but missing.
+    async fn find_header_range_authority_set_id(
+        &mut self,
+        trusted_block: u32,
+    ) -> Result<Option<u64>> {
+        let mut data_fetcher = self.get_data_fetcher();
+
+        let header_range_contract_data = self
+            .get_contract_data_for_header_range(trusted_block)
+            .await?;
+
+        // The current authority set id is the authority set id of the block before the current block.
+        let current_authority_set_id = data_fetcher
+            .get_authority_set_id(header_range_contract_data.current_block - 1)
+            .await
+            .context(Endpoint::Avail)?;
+
+        // Get the last justified block by the current authority set id.
+        let last_justified_block = self.last_justified_block(current_authority_set_id).await?;
+
+        // If this is the last justified block, check for header range with next authority set.
+        if header_range_contract_data.current_block != last_justified_block {
+            return Ok(Some(current_authority_set_id));
+        }
+        let next_authority_set_id = current_authority_set_id + 1;
+
+        // Check if the next authority set id exists in the contract. If not, a rotate is needed.
+        if !header_range_contract_data.next_authority_set_hash_exists {
+            self.explain(&format!(
+                "No header range possible: block {} is the last justified block of authority set {}, and the hash of the next authority set {} is missing.",
+                header_range_contract_data.current_block,
+                current_authority_set_id,
+                next_authority_set_id
+            ));
+            return Ok(None);
+        }
+        Ok(Some(next_authority_set_id))
+    }
+
+    // Finds the authority set and the highest provable target block of a header range from the
+    // trusted block, if any.
+    async fn find_header_range_target(
+        &mut self,
+        trusted_block: u32,
+        max_block_to_step_to: u32,
+    ) -> Result<Option<(u64, u32)>> {
+        let Some(request_authority_set_id) = self
+            .find_header_range_authority_set_id(trusted_block)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // Step as far as possible within blocks attested by the requested authority set.
+        let block_to_step_to = self
+            .find_block_to_step_to(
+                trusted_block,
+                max_block_to_step_to,
+                request_authority_set_id,
+            )
+            .await?;
+
+        Ok(block_to_step_to.map(|block_to_step_to| (request_authority_set_id, block_to_step_to)))
+    }
+
+    // Encodes the header range from the trusted block to the target block as a request.
+    async fn plan_header_range_request(
+        &mut self,
+        trusted_block: u32,
+        authority_set_id: u64,
+        target_block: u32,
+    ) -> Result<PlannedRequest> {
+        let header_range_function_id = self
+            .get_contract_data_for_header_range(trusted_block)
+            .await?
+            .header_range_function_id;
+        let (input, function_data) = self
+            .encode_header_range_request(trusted_block, authority_set_id, target_block)
+            .await?;
+        Ok(PlannedRequest {
+            circuit: Circuit::HeaderRange,
+            description: format!(
+                "header range from block {} to block {}",
+                trusted_block, target_block
+            ),
+            function_id: header_range_function_id,
+            input,
+            function_data,
+            request: StoredProofRequest {
+                request_id: String::new(),
+                request_type: HEADER_RANGE_REQUEST.to_string(),
+                trusted_block,
+                target_block,
+                authority_set_id,
+                submitted_at: 0,
+                attempt: 0,
+            },
+        })
+    }
+
+    // Finds the header ranges to request, as a pipeline of up to pipeline_depth consecutive ranges.
+    // The first range starts at the contract's latest block, and each following range starts at
+    // the target of the range before it, so they can be proven at the same time. The contract
+    // only verifies a range from its latest block, so relay_local_proofs holds each proof until
+    // the range before it is committed. Pipelining is only enabled with the local backend. Ranges
+    // of the pipeline that are already pending are followed instead of requested again.
+    async fn find_header_range_requests(
+        &mut self,
+        latest_block: u32,
+        head_block: u32,
+        block_interval: u32,
+        data_commitment_max: u32,
+    ) -> Result<Vec<PlannedRequest>> {
+        let config = self.get_config();
+        let pending_requests = self
+            .data_fetcher
+            .redis_client
+            .get_pending_proof_requests(config.chain_id as u64, config.address.to_vec())
+            .await
+            .context(Endpoint::Redis)?;
+
+        let mut planned_requests = Vec::new();
+        let mut trusted_block = latest_block;
+        for _ in 0..self.pipeline_depth {
+            if let Some(pending) = pending_requests.iter().find(|pending| {
+                pending.request_type == HEADER_RANGE_REQUEST
+                    && pending.trusted_block == trusted_block
+            }) {
+                self.explain(&format!(
+                    "Request {} ({}) is pending, planning from its target block {}.",
+                    pending.request_id,
+                    request_params(pending),
+                    pending.target_block
+                ));
+                trusted_block = pending.target_block;
+                continue;
+            }
+
+            let Some(block_to_request) = pipeline_block_to_request(
+                latest_block,
+                trusted_block,
+                head_block,
+                block_interval,
+                data_commitment_max,
+            ) else {
+                break;
+            };
+
+            match self
+                .find_header_range_request(trusted_block, block_to_request)
+                .await?
+            {
+                Some(planned) => {
+                    trusted_block = planned.request.target_block;
+                    planned_requests.push(planned);
+                }
+                None => break,
+            }
+        }
+
+        Ok(planned_requests)
+    }
+
+    // Plans the rotates and header ranges needed to bring the contract to the head, as laid out by
+    // catch_up_steps. Whether each step is committed is read from the contract.
+    async fn plan_catch_up(&mut self, block_to_request: u32) -> Result<Vec<CatchUpStep>> {
+        let mut data_fetcher = self.get_data_fetcher();
+
+        let latest_block = self.contract.latest_block().await?;
+        let contract_authority_set_id = data_fetcher
+            .get_authority_set_id(latest_block - 1)
+            .await
+            .context(Endpoint::Avail)?;
+        let head_block = data_fetcher
+            .get_head()
+            .await
+            .context(Endpoint::Avail)?
+            .number;
+        let head_authority_set_id = self.head_authority_set_id(head_block).await?;
+
+        // The header ranges with each authority set go through the blocks it justified. The
+        // authority set of the head has no last justified block yet.
+        let mut target_blocks = Vec::new();
+        for authority_set_id in contract_authority_set_id..head_authority_set_id {
+            target_blocks.push(self.last_justified_block(authority_set_id).await?);
+        }
+        target_blocks.push(block_to_request);
+
+        let mut steps = catch_up_steps(latest_block, contract_authority_set_id, &target_blocks);
+        for step in steps.iter_mut() {
+            step.committed = match step.kind {
+                // Ranges can also be committed past the latest block by the guardian, which stores
+                // the header hash of their end block.
+                CatchUpStepKind::HeaderRanges { target_block, .. } => {
+                    let target_header_hash = self
+                        .contract
+                        .block_height_to_header_hash(target_block)
+                        .await?;
+                    B256::from(target_header_hash) != B256::ZERO
+                }
+                CatchUpStepKind::Rotate { authority_set_id } => {
+                    self.authority_set_hash_exists(authority_set_id + 1).await?
+                }
+            };
+        }
+
+        Ok(steps)
+    }
+
+    // Reports the catch-up plan, with the steps each waiting step depends on.
+    fn explain_catch_up(&self, steps: &[CatchUpStep]) {
+        if steps.len() <= 1 {
+            return;
+        }
+        self.explain(&format!("Catch-up plan of {} steps:", steps.len()));
+        for (index, step) in steps.iter().enumerate() {
+            let waiting_for: Vec<String> = blocking_dependencies(steps, step)
+                .iter()
+                .map(|dependency| dependency.to_string())
+                .collect();
+            let status = if step.committed {
+                "committed".to_string()
+            } else if waiting_for.is_empty() {
+                "ready".to_string()
+            } else {
+                format!("waiting for {}", waiting_for.join(", "))
+            };
+            self.explain(&format!("  {}: {} ({})", index, step.description, status));
+        }
+    }
+
+    // Drops the planned requests of steps of the catch-up plan that are committed or wait for
+    // steps that are not committed yet.
+    fn apply_catch_up_plan(
+        &self,
+        steps: &[CatchUpStep],
+        planned_requests: Vec<PlannedRequest>,
+    ) -> Vec<PlannedRequest> {
+        planned_requests
+            .into_iter()
+            .filter(|planned| {
+                let Some(step) = steps.iter().find(|step| step.covers(&planned.request)) else {
+                    return true;
+                };
+                let blocking = blocking_dependencies(steps, step);
+                if step.committed || !blocking.is_empty() {
+                    self.explain(&format!(
+                        "Not submitting the {}: its catch-up step is {}.",
+                        planned.description,
+                        if step.committed {
+                            "committed".to_string()
+                        } else {
+                            format!("waiting for steps {:?}", blocking)
+                        }
+                    ));
+                    return false;
+                }
+                true
+            })
+            .collect()
+    }
+
+    // Submits the planned requests concurrently, as they do not depend on each other, and tracks
+    // the submitted ones. Returns the IDs of the submitted requests. In dry-run mode, the requests
+    // are printed instead.
+    async fn submit_planned_requests(
+        &mut self,
+        planned_requests: Vec<PlannedRequest>,
+    ) -> Vec<String> {
+        let mut request_ids = Vec::new();
+        if planned_requests.is_empty() {
+            return request_ids;
+        }
+        // Confirm the lease right before submitting, in case it was lost since the loop started.
+        if let Some(leader_lease) = self.leader_lease.as_mut() {
+            if !leader_lease.acquire().await {
+                info!("Not the leader anymore, not submitting the planned requests.");
+                return request_ids;
+            }
+        }
+
+        if self.dry_run {
+            for planned in planned_requests.iter() {
+                self.print_planned_request(
+                    &planned.description,
+                    planned.function_id,
+                    &planned.input,
+                    &planned.function_data,
+                );
+            }
+            return request_ids;
+        }
+
+        let results = join_all(planned_requests.iter().map(|planned| {
+            info!("Requesting {}.", planned.description);
+            self.submit_request(
+                planned.circuit,
+                planned.function_data.clone(),
+                planned.function_id,
+                planned.input.clone(),
+            )
+        }))
+        .await;
+
+        for (planned, result) in planned_requests.into_iter().zip(results) {
+            match result {
+                Ok(request_id) => {
+                    info!(
+                        "Request for {} submitted with request ID: {}",
+                        planned.description, request_id
+                    );
+                    request_ids.push(request_id.clone());
+                    if let Err(e) = self
+                        .track_request(StoredProofRequest {
+                            request_id: request_id.clone(),
+                            submitted_at: now_secs(),
+                            ..planned.request
+                        })
+                        .await
+                    {
+                        error!("Failed to track request {}: {:#}", request_id, e);
+                        self.record_rpc_error(error_endpoint(&e));
+                    }
+                }
+                Err(e) => {
+                    error!("Request for {} failed: {}", planned.description, e);
+                    self.record_request_failure(&planned.request.request_type, "submit");
+                }
+            }
+        }
+
+        request_ids
+    }
+
+    async fn get_header_range_input_data(
+        &mut self,
+        trusted_block: u32,
+        trusted_authority_set_id: u64,
+    ) -> Result<(B256, B256)> {
+        let trusted_header_hash = self
+            .contract
+            .block_height_to_header_hash(trusted_block)
+            .await?;
+        let trusted_authority_set_hash = self
+            .contract
+            .authority_set_id_to_hash(trusted_authority_set_id)
+            .await?;
+
+        Ok((
+            B256::from_slice(&trusted_header_hash),
+            B256::from_slice(&trusted_authority_set_hash),
+        ))
+    }
+
+    // Current authority set hash.
+    async fn get_rotate_input_data(&mut self, current_authority_set_id: u64) -> Result<B256> {
+        Ok(alloy_primitives::FixedBytes(
+            self.contract
+                .authority_set_id_to_hash(current_authority_set_id)
+                .await?,
+        ))
+    }
+
+    // Current block, step_range_max and whether next authority set hash exists. The current block
+    // is the trusted block of the range, which is ahead of the contract's latest block for
+    // pipelined ranges.
+    async fn get_contract_data_for_header_range(
+        &mut self,
+        current_block: u32,
+    ) -> Result<HeaderRangeContractData> {
+        let header_range_function_id: B256 =
+            FixedBytes(self.contract.header_range_function_id().await?);
+
+        let current_authority_set_id = self
+            .data_fetcher
+            .get_authority_set_id(current_block - 1)
+            .await
+            .context(Endpoint::Avail)?;
+        let next_authority_set_id = current_authority_set_id + 1;
+
+        let next_authority_set_hash = self
+            .contract
+            .authority_set_id_to_hash(next_authority_set_id)
+            .await?;
+
+        Ok(HeaderRangeContractData {
+            current_block,
+            next_authority_set_hash_exists: B256::from_slice(&next_authority_set_hash)
+                != B256::ZERO,
+            header_range_function_id,
+        })
+    }
+
+    // Current block and whether next authority set hash exists.
+    async fn get_contract_data_for_rotate(&mut self) -> Result<RotateContractData> {
+        let rotate_function_id: B256 = FixedBytes(self.contract.rotate_function_id().await?);
+        let current_block = self.contract.latest_block().await?;
+
+        let current_authority_set_id = self
+            .data_fetcher
+            .get_authority_set_id(current_block - 1)
+            .await
+            .context(Endpoint::Avail)?;
+        let next_authority_set_id = current_authority_set_id + 1;
+
+        let next_authority_set_hash = self
+            .contract
+            .authority_set_id_to_hash(next_authority_set_id)
+            .await?;
+
+        Ok(RotateContractData {
+            current_block,
+            next_authority_set_hash_exists: B256::from_slice(&next_authority_set_hash)
+                != B256::ZERO,
+            rotate_function_id,
+        })
+    }
+
+    async fn authority_set_hash_exists(&mut self, authority_set_id: u64) -> Result<bool> {
+        let authority_set_hash = self
+            .contract
+            .authority_set_id_to_hash(authority_set_id)
+            .await?;
+        Ok(B256::from_slice(&authority_set_hash) != B256::ZERO)
+    }
+
+    fn get_config(&mut self) -> VectorXConfig {
+        self.config.clone()
+    }
+
+    fn get_data_fetcher(&mut self) -> RpcDataFetcher {
+        self.data_fetcher.clone()
+    }
+
+    // The authority set of the head, used by the planner, the status and the metrics alike. It is
+    // the authority set in effect at the head itself, so an authority set counts as ended, and the
+    // rotate from it can be requested, as soon as its epoch end block is finalized.
+    async fn head_authority_set_id(&mut self, head_block: u32) -> Result<u64> {
+        self.data_fetcher
+            .get_authority_set_id(head_block)
+            .await
+            .context(Endpoint::Avail)
+    }
+
+    // The last block justified by the authority set, which is 0 until the authority set has ended.
+    // It is found by a binary search over the Avail chain, so it is cached once the authority set
+    // has ended.
+    async fn last_justified_block(&mut self, authority_set_id: u64) -> Result<u32> {
+        if let Some(epoch_end_block) = self.epoch_end_blocks.get(&authority_set_id) {
+            return Ok(*epoch_end_block);
+        }
+        let epoch_end_block = self
+            .data_fetcher
+            .last_justified_block(authority_set_id)
+            .await
+            .context(Endpoint::Avail)?;
+        if epoch_end_block != 0 {
+            self.epoch_end_blocks
+                .insert(authority_set_id, epoch_end_block);
+        }
+        Ok(epoch_end_block)
+    }
+
+    // Finds the highest block in (trusted_block, max_block_to_request] that is provable with the
+    // authority set: it must have a justification stored by the indexer or be an epoch end block,
+    // and it must not be past the last block justified by the authority set. Returns None if there
+    // is no provable target.
+    async fn find_block_to_step_to(
+        &mut self,
+        trusted_block: u32,
+        max_block_to_request: u32,
+        authority_set_id: u64,
+    ) -> Result<Option<u32>> {
+        let last_justified_block = self.last_justified_block(authority_set_id).await?;
+
+        // Last justified block will be 0 in this is the current authority set.
+        let max_target_block = cap_to_epoch_end(max_block_to_request, last_justified_block);
+        if max_target_block <= trusted_block {
+            self.explain(&format!(
+                "No provable target: the last block justified by authority set {} is {}, which is not past block {}.",
+                authority_set_id, last_justified_block, trusted_block
+            ));
+            return Ok(None);
+        }
+
+        // The justification of the epoch end block can always be fetched from the chain.
+        if max_target_block == last_justified_block {
+            return Ok(Some(max_target_block));
+        }
+
+        let justified_blocks = self
+            .data_fetcher
+            .find_justifications_in_range(trusted_block + 1, max_target_block)
+            .await;
+        let target_block = justified_blocks
+            .into_iter()
+            .filter(|block| *block > trusted_block && *block <= max_target_block)
+            .max();
+        if target_block.is_none() {
+            self.explain(&format!(
+                "No provable target: no block in ({}, {}] has a stored or epoch end justification for authority set {}.",
+                trusted_block, max_target_block, authority_set_id
+            ));
+        }
+
+        Ok(target_block)
+    }
+
+    // Prints the contract's state next to the Avail chain's: the latest block, the stored authority
+    // set hashes, the head, and the next header range and rotate the operator would request.
+    async fn print_status(&mut self, block_interval: u32, data_commitment_max: u32) -> Result<()> {
+        let mut data_fetcher = self.get_data_fetcher();
+
+        let latest_block = self.contract.latest_block().await?;
+        let contract_authority_set_id = data_fetcher
+            .get_authority_set_id(latest_block - 1)
+            .await
+            .context(Endpoint::Avail)?;
+        let head_block = data_fetcher
+            .get_head()
+            .await
+            .context(Endpoint::Avail)?
+            .number;
+        let head_authority_set_id = self.head_authority_set_id(head_block).await?;
+
+        println!(
+            "Latest block: {} (authority set {})",
+            latest_block, contract_authority_set_id
+        );
+        println!("Authority set hashes:");
+        for authority_set_id in contract_authority_set_id..=head_authority_set_id + 1 {
+            let authority_set_hash = self
+                .contract
+                .authority_set_id_to_hash(authority_set_id)
+                .await?;
+            if B256::from_slice(&authority_set_hash) == B256::ZERO {
+                println!("  {}: missing", authority_set_id);
+            } else {
+                println!(
+                    "  {}: 0x{}",
+                    authority_set_id,
+                    hex::encode(authority_set_hash)
+                );
+            }
+        }
+        println!(
+            "Avail head: {} (authority set {}, {} blocks ahead)",
+            head_block,
+            head_authority_set_id,
+            head_block.saturating_sub(latest_block)
+        );
+
+        let (header_range_allowed, rotate_allowed) = self.preflight().await?;
+        println!(
+            "Preflight: header ranges {}, rotates {}",
+            if header_range_allowed {
+                "allowed"
+            } else {
+                "refused"
+            },
+            if rotate_allowed { "allowed" } else { "refused" }
+        );
+
+        let block_to_request = block_to_request(
+            latest_block,
+            head_block,
+            block_interval,
+            data_commitment_max,
+        );
+        if block_to_request <= latest_block {
+            println!(
+                "Next provable target: none, the next multiple of {} is not below the head.",
+                block_interval
+            );
+        } else if let Some((authority_set_id, target_block)) = self
+            .find_header_range_target(latest_block, block_to_request)
+            .await?
+        {
+            println!(
+                "Next provable target: block {} with authority set {}",
+                target_block, authority_set_id
+            );
+        } else {
+            println!("Next provable target: none");
+        }
+        Ok(())
+    }
+
+    // Requests a header range chosen by hand. The trusted block must be the contract's latest
+    // block, or the target of a pending header range, as the contract only verifies ranges from
+    // its latest block. The target must be provable with the authority set of the range.
+    async fn request_range(&mut self, trusted_block: u32, target_block: u32) -> Result<()> {
+        if target_block <= trusted_block {
+            bail!(
+                "The target block {} must be after the trusted block {}",
+                target_block,
+                trusted_block
+            );
+        }
+        if target_block - trusted_block > MAX_NUM_HEADERS as u32 {
+            bail!(
+                "A header range spans at most {} blocks, got {}",
+                MAX_NUM_HEADERS,
+                target_block - trusted_block
+            );
+        }
+
+        let latest_block = self.contract.latest_block().await?;
+        if trusted_block != latest_block {
+            let config = self.get_config();
+            let pending_requests = self
+                .data_fetcher
+                .redis_client
+                .get_pending_proof_requests(config.chain_id as u64, config.address.to_vec())
+                .await
+                .context(Endpoint::Redis)?;
+            if !pending_requests.iter().any(|pending| {
+                pending.request_type == HEADER_RANGE_REQUEST
+                    && pending.target_block == trusted_block
+            }) {
+                bail!(
+                    "The trusted block {} is neither the contract's latest block {} nor the target of a pending header range",
+                    trusted_block, latest_block
+                );
+            }
+        }
+
+        if !self.preflight().await?.0 {
+            bail!("Header range requests are refused, see above");
+        }
+
+        let Some(authority_set_id) = self
+            .find_header_range_authority_set_id(trusted_block)
+            .await?
+        else {
+            bail!(
+                "No authority set can prove a header range from block {}",
+                trusted_block
+            );
+        };
+        if self
+            .find_block_to_step_to(target_block - 1, target_block, authority_set_id)
+            .await?
+            .is_none()
+        {
+            bail!(
+                "Block {} is not provable with authority set {}",
+                target_block,
+                authority_set_id
+            );
+        }
+
+        if self
+            .preflight_header_range(trusted_block, authority_set_id, target_block)
+            .await?
+            != Some(target_block)
+        {
+            bail!(
+                "The header range from block {} to block {} cannot be proven",
+                trusted_block,
+                target_block
+            );
+        }
+
+        let planned = self
+            .plan_header_range_request(trusted_block, authority_set_id, target_block)
+            .await?;
+        self.submit_manual_request(planned).await
+    }
+
+    // Requests the rotate from an authority set chosen by hand. The contract must store the hash of
+    // the authority set but not of the next one, and the authority set must have ended.
+    async fn request_rotate(&mut self, authority_set_id: u64) -> Result<()> {
+        if !self.authority_set_hash_exists(authority_set_id).await? {
+            bail!(
+                "The hash of authority set {} is not stored in the contract",
+                authority_set_id
+            );
+        }
+        if self.authority_set_hash_exists(authority_set_id + 1).await? {
+            bail!(
+                "The hash of authority set {} is already stored in the contract",
+                authority_set_id + 1
+            );
+        }
+        if self.last_justified_block(authority_set_id).await? == 0 {
+            bail!("Authority set {} has not ended yet", authority_set_id);
+        }
+
+        if !self.preflight().await?.1 || !self.preflight_rotate(authority_set_id).await? {
+            bail!(
+                "The rotate from authority set {} cannot be proven",
+                authority_set_id
+            );
+        }
+
+        let planned = self.plan_rotate_request(authority_set_id).await?;
+        self.submit_manual_request(planned).await
+    }
+
+    // Clears the failed attempts of a request, so the operator retries it if it still needs it.
+    async fn reset_attempts(&mut self, params: &str) -> Result<()> {
+        let request_type = params.split(':').next().unwrap_or_default();
+        if request_type != HEADER_RANGE_REQUEST && request_type != ROTATE_REQUEST {
+            bail!(
+                "Unknown request {}, expected {}:<trusted block>:<target block> or {}:<authority set id>",
+                params, HEADER_RANGE_REQUEST, ROTATE_REQUEST
+            );
+        }
+
+        let config = self.get_config();
+        let failed_attempts = self
+            .data_fetcher
+            .redis_client
+            .get_failed_request_attempts(config.chain_id as u64, config.address.to_vec(), params)
+            .await
+            .context(Endpoint::Redis)?;
+        self.data_fetcher
+            .redis_client
+            .clear_failed_request_attempts(config.chain_id as u64, config.address.to_vec(), params)
+            .await
+            .context(Endpoint::Redis)?;
+        println!(
+            "Cleared {} failed attempts of request {}.",
+            failed_attempts, params
+        );
+        Ok(())
+    }
+
+    // Submits a request chosen by hand, unless the same request is pending. It is tracked like the
+    // operator's own requests, so a running operator follows it. A request that already failed
+    // MAX_REQUEST_ATTEMPTS times is submitted anyway. With the local prover, the proof is generated
+    // by this process, so this waits until it is done.
+    async fn submit_manual_request(&mut self, planned: PlannedRequest) -> Result<()> {
+        if let Some(pending) = self.find_pending_request(&planned.request).await? {
+            println!(
+                "Request {} ({}) is pending, not submitting {}.",
+                pending.request_id,
+                request_params(&pending),
+                planned.description
+            );
+            return Ok(());
+        }
+
+        let request_ids = self.submit_planned_requests(vec![planned]).await;
+        if !matches!(self.prover, ProverBackend::Local(_)) {
+            return Ok(());
+        }
+        for request_id in request_ids {
+            info!("Waiting for the local proof of request {}.", request_id);
+            while self.get_request_status(&request_id).await == RequestStatus::Pending {
+                tokio::time::sleep(self.catch_up_poll_interval).await;
+            }
+        }
+        Ok(())
     }
 
-    async fn request_rotate(
+    // Relays and updates the pending requests, then plans and submits the requests that bring the
+    // contract to the head. Returns the catch-up plan, which sets how soon the loop runs again.
+    async fn plan_and_submit(
         &mut self,
-        current_authority_set_id: u64,
-        rotate_function_id: B256,
-    ) -> Result<String> {
-        let client = self.get_succinct_client();
-        let config = self.get_config();
+        block_interval: u32,
+        data_commitment_max: u32,
+    ) -> Result<Vec<CatchUpStep>> {
+        // Relay finished local proofs, and drop failed or timed out requests so they can be
+        // retried. Skipped in dry-run mode, which does not modify any state.
+        if !self.dry_run {
+            self.check_relayed_transactions().await;
+            self.relay_local_proofs().await?;
+            self.update_pending_requests().await?;
+        }
 
-        let current_authority_set_hash = self.get_rotate_input_data(current_authority_set_id).await;
+        // Get latest block of the Avail chain.
+        let avail_chain_latest_block_nb = self
+            .data_fetcher
+            .get_head()
+            .await
+            .context(Endpoint::Avail)?
+            .number;
 
-        info!(
-            "Current authority set hash: {:?}",
-            hex::encode(current_authority_set_hash)
+        // Get latest block of contract.
+        let contract_latest_block_nb = self.contract.latest_block().await?;
+
+        let block_to_request = block_to_request(
+            contract_latest_block_nb,
+            avail_chain_latest_block_nb,
+            block_interval,
+            data_commitment_max,
         );
 
-        let input = RotateInputTuple::abi_encode_packed(&(
-            current_authority_set_id,
-            current_authority_set_hash,
-        ));
+        let catch_up_steps = self.plan_catch_up(block_to_request).await?;
+        self.explain_catch_up(&catch_up_steps);
 
-        let rotate_call = vector_x::RotateCall {
-            current_authority_set_id,
-        };
-        let function_data = rotate_call.encode();
+        // Requests that the contract's state makes certain to fail are not planned.
+        let (header_range_allowed, rotate_allowed) = self.preflight().await?;
+        self.unprovable_range = None;
 
-        let request_id = client
-            .submit_platform_request(
-                config.chain_id,
-                config.address,
-                function_data.into(),
-                rotate_function_id,
-                Bytes::copy_from_slice(&input),
-            )
-            .await?;
+        // The rotate and the header range are independent of each other, so both are planned
+        // and submitted together.
+        let mut planned_requests = Vec::new();
 
-        Ok(request_id)
-    }
+        // Check if there is a rotate available for an authority set whose next hash is missing.
+        if rotate_allowed {
+            if let Some(planned) = self.find_rotate_request().await? {
+                planned_requests.push(planned);
+            }
+        }
 
-    async fn find_and_request_rotate(&mut self) {
-        let mut data_fetcher = self.get_data_fetcher();
+        if !header_range_allowed {
+            // The preflight already reported why.
+        } else if block_to_request > contract_latest_block_nb {
+            info!("Attempting to step to block: {}", block_to_request);
+            planned_requests.extend(
+                self.find_header_range_requests(
+                    contract_latest_block_nb,
+                    avail_chain_latest_block_nb,
+                    block_interval,
+                    data_commitment_max,
+                )
+                .await?,
+            );
+        } else {
+            self.explain(&format!(
+                "No header range needed: the contract is at block {} and the closest multiple of {} below min(Avail head {}, contract block + {}) is {}.",
+                contract_latest_block_nb,
+                block_interval,
+                avail_chain_latest_block_nb,
+                data_commitment_max,
+                block_to_request
+            ));
+        }
 
-        let rotate_contract_data = self.get_contract_data_for_rotate().await;
+        let planned_requests = self.apply_catch_up_plan(&catch_up_steps, planned_requests);
+        let planned_requests = self.apply_request_budget(planned_requests).await?;
+        self.submit_planned_requests(planned_requests).await;
+        self.fill_unprovable_range().await?;
 
-        let head = data_fetcher.get_head().await;
-        let head_block = head.number;
-        let head_authority_set_id = data_fetcher.get_authority_set_id(head_block - 1).await;
+        Ok(catch_up_steps)
+    }
 
-        // The current authority set id is the authority set id of the block before the current block.
-        let current_authority_set_id = data_fetcher
-            .get_authority_set_id(rotate_contract_data.current_block - 1)
+    // Runs the operator, and releases the lease once it stops, on shutdown or with --once, so a
+    // standby does not wait for the lease to expire.
+    async fn run(
+        &mut self,
+        loop_delay_mins: u64,
+        block_interval: u32,
+        data_commitment_max: u32,
+        once: bool,
+    ) -> Result<()> {
+        let result = self
+            .run_loop(loop_delay_mins, block_interval, data_commitment_max, once)
             .await;
+        if let Some(leader_lease) = self.leader_lease.as_mut() {
+            leader_lease.release().await;
+        }
+        result
+    }
 
-        if current_authority_set_id < head_authority_set_id
-            && !rotate_contract_data.next_authority_set_hash_exists
-        {
-            info!(
-                "Requesting rotate to next authority set id, which is {:?}.",
-                current_authority_set_id + 1
-            );
+    // Runs the loop until shutdown, which is only handled between loops so that submitted
+    // requests are always tracked.
+    async fn run_loop(
+        &mut self,
+        loop_delay_mins: u64,
+        block_interval: u32,
+        data_commitment_max: u32,
+        once: bool,
+    ) -> Result<()> {
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        loop {
+            self.update_metrics().await;
+
+            // A standby only watches the lease, and checks it more often than the loop delay so it
+            // can take over soon after the leader's lease expires.
+            if let Some(leader_lease) = self.leader_lease.clone() {
+                if !leader_lease.is_leader() {
+                    let config = self.get_config();
+                    let holder = self
+                        .data_fetcher
+                        .redis_client
+                        .get_operator_lease_holder(config.chain_id as u64, config.address.to_vec())
+                        .await;
+                    match holder {
+                        Ok(holder) => info!(
+                            "On standby, the lease is held by {}.",
+                            holder.unwrap_or("nobody".to_string())
+                        ),
+                        Err(e) => error!("On standby, failed to get the lease holder: {}", e),
+                    }
+                    if once {
+                        break;
+                    }
+                    tokio::select! {
+                        _ = &mut shutdown => break,
+                        _ = tokio::time::sleep(leader_lease.ttl / 3) => continue,
+                    }
+                }
+            }
 
-            // Request a rotate for the next authority set id.
-            match self
-                .request_rotate(
-                    current_authority_set_id,
-                    rotate_contract_data.rotate_function_id,
-                )
+            // A failed call to the contract, Avail or Redis skips the rest of the loop, which is
+            // retried after the wait. With --once, it is returned instead.
+            let catch_up_steps = match self
+                .plan_and_submit(block_interval, data_commitment_max)
                 .await
             {
-                Ok(request_id) => {
-                    info!("Rotate request submitted: {}", request_id)
-                }
+                Ok(catch_up_steps) => catch_up_steps,
                 Err(e) => {
-                    error!("Rotate request failed: {}", e);
+                    self.record_rpc_error(error_endpoint(&e));
+                    if once {
+                        return Err(e);
+                    }
+                    error!("Loop failed, skipping the rest of it: {:#}", e);
+                    Vec::new()
                 }
             };
+
+            if once {
+                break;
+            }
+
+            // While steps of the plan wait for other steps to be committed, poll more often, so
+            // they are requested as soon as their dependencies land.
+            let catching_up = catch_up_steps.iter().any(|step| {
+                !step.committed && !blocking_dependencies(&catch_up_steps, step).is_empty()
+            });
+            let timeout = if catching_up {
+                info!(
+                    "Catching up, waiting for at most {} seconds.",
+                    self.catch_up_poll_interval.as_secs()
+                );
+                self.catch_up_poll_interval
+            } else if !self.relayed_calls.is_empty() {
+                // Relayed transactions are checked on each loop, so they are replaced in time.
+                info!(
+                    "Relaying {} transactions, waiting for at most {} seconds.",
+                    self.relayed_calls.len(),
+                    self.catch_up_poll_interval.as_secs()
+                );
+                self.catch_up_poll_interval
+            } else {
+                // Wait for at most N minutes.
+                info!("Waiting for at most {} minutes.", loop_delay_mins);
+                Duration::from_secs(60 * loop_delay_mins)
+            };
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = self.wait_for_trigger(timeout, block_interval) => {}
+            }
         }
+        Ok(())
     }
 
-    async fn find_and_request_header_range(&mut self, max_block_to_step_to: u32) {
-        let mut data_fetcher = self.get_data_fetcher();
+    // Subscribes to the Avail finalized heads and the contract's events, if not subscribed yet.
+    // Failing to subscribe leaves the loop on its timer.
+    async fn subscribe_triggers(&mut self) {
+        if self.finalized_heads.is_none() {
+            match self.data_fetcher.subscribe_finalized_heads().await {
+                Ok(finalized_heads) => {
+                    self.finalized_heads = Some(finalized_heads);
+                    // Heads may have been missed while unsubscribed.
+                    self.last_finalized_head = None;
+                }
+                Err(e) => warn!("Failed to subscribe to the Avail finalized heads: {}", e),
+            }
+        }
 
-        let header_range_contract_data = self.get_contract_data_for_header_range().await;
+        if self.contract_events.is_none() {
+            let (sender, receiver) = mpsc::channel(64);
+            let contract = self.contract.clone();
+            tokio::spawn(async move {
+                let events = contract.events();
+                let mut stream = match events.stream().await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Failed to subscribe to the contract's events: {}", e);
+                        return;
+                    }
+                };
+                while let Some(event) = stream.next().await {
+                    match event {
+                        Ok(event) => {
+                            if sender.send(event).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => warn!("Failed to decode a contract event: {}", e),
+                    }
+                }
+            });
+            self.contract_events = Some(receiver);
+        }
+    }
 
-        // The current authority set id is the authority set id of the block before the current block.
-        let current_authority_set_id = data_fetcher
-            .get_authority_set_id(header_range_contract_data.current_block - 1)
-            .await;
+    // Waits until the loop should run again: when a finalized head ends an authority set or crosses
+    // a multiple of block_interval, when the contract commits a header range or stores an authority
+    // set hash, or at the latest after the timeout. Without EVENT_DRIVEN, only waits for the
+    // timeout.
+    async fn wait_for_trigger(&mut self, timeout: Duration, block_interval: u32) {
+        if !self.event_driven {
+            tokio::time::sleep(timeout).await;
+            return;
+        }
+        self.subscribe_triggers().await;
 
-        // Get the last justified block by the current authority set id.
-        let last_justified_block = data_fetcher
-            .last_justified_block(current_authority_set_id)
-            .await;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let trigger = tokio::select! {
+                head = recv_trigger(&mut self.finalized_heads) => Trigger::FinalizedHead(head),
+                event = recv_trigger(&mut self.contract_events) => Trigger::ContractEvent(event),
+                _ = tokio::time::sleep_until(deadline) => return,
+            };
+            match trigger {
+                Trigger::FinalizedHead(Some(head)) => {
+                    if self.is_head_trigger(head, block_interval).await {
+                        return;
+                    }
+                }
+                Trigger::ContractEvent(Some(VectorXEvents::HeadUpdateFilter(event))) => {
+                    info!("The contract committed up to block {}.", event.block_number);
+                    return;
+                }
+                Trigger::ContractEvent(Some(VectorXEvents::AuthoritySetStoredFilter(event))) => {
+                    info!(
+                        "The contract stored the hash of authority set {}.",
+                        event.authority_set_id
+                    );
+                    return;
+                }
+                Trigger::ContractEvent(Some(_)) => {}
+                // The subscription ended. It is renewed on the next wait, and the timer still
+                // applies meanwhile.
+                Trigger::FinalizedHead(None) => {
+                    warn!("The Avail finalized heads subscription ended.");
+                    self.finalized_heads = None;
+                }
+                Trigger::ContractEvent(None) => {
+                    warn!("The contract events subscription ended.");
+                    self.contract_events = None;
+                }
+            }
+        }
+    }
 
-        // If this is the last justified block, check for header range with next authority set.
-        let mut request_authority_set_id = current_authority_set_id;
-        if header_range_contract_data.current_block == last_justified_block {
-            let next_authority_set_id = current_authority_set_id + 1;
+    // Whether a newly finalized head should run the loop: when it is in a new authority set, as the
+    // epoch end block that can be rotated from was just finalized, or when it crosses a multiple of
+    // block_interval, as a new header range target is available. A failed check is logged and does
+    // not run the loop, which still runs on its timer.
+    async fn is_head_trigger(&mut self, head: u32, block_interval: u32) -> bool {
+        match self.check_head_trigger(head, block_interval).await {
+            Ok(trigger) => trigger,
+            Err(e) => {
+                warn!("Failed to check finalized block {}: {:#}", head, e);
+                self.record_rpc_error(error_endpoint(&e));
+                false
+            }
+        }
+    }
 
-            // Check if the next authority set id exists in the contract. If not, a rotate is needed.
-            if !header_range_contract_data.next_authority_set_hash_exists {
-                return;
+    // The first head after subscribing has no previous head, so its authority set is compared
+    // against the contract's instead. That way an authority set that ended while unsubscribed, or
+    // before the operator started, still runs the loop.
+    async fn check_head_trigger(&mut self, head: u32, block_interval: u32) -> Result<bool> {
+        let authority_set_id = self.head_authority_set_id(head).await?;
+        let (previous_head, previous_authority_set_id) = match self.last_finalized_head {
+            Some(previous) => previous,
+            None => {
+                let latest_block = self.contract.latest_block().await?;
+                let contract_authority_set_id = self
+                    .data_fetcher
+                    .get_authority_set_id(latest_block - 1)
+                    .await
+                    .context(Endpoint::Avail)?;
+                (head, contract_authority_set_id)
             }
-            request_authority_set_id = next_authority_set_id;
+        };
+        self.last_finalized_head = Some((head, authority_set_id));
+
+        if authority_set_id > previous_authority_set_id {
+            info!(
+                "Authority set {} ended by finalized block {}.",
+                previous_authority_set_id, head
+            );
+            return Ok(true);
+        }
+        if head / block_interval > previous_head / block_interval {
+            info!(
+                "Finalized block {} crossed a multiple of {}.",
+                head, block_interval
+            );
+            return Ok(true);
         }
+        Ok(false)
+    }
+}
 
-        // Step as far as possible within blocks attested by the requested authority set.
-        let block_to_step_to = self
-            .find_block_to_step_to(max_block_to_step_to, request_authority_set_id)
-            .await;
-        if block_to_step_to.is_none() {
+async fn run_deployment(
+    deployment: Deployment,
+    metrics: OperatorMetrics,
+    relayer: Option<Arc<Mutex<Relayer>>>,
+    args: RunArgs,
+) {
+    let mut operator =
+        VectorXOperator::new(&deployment, metrics, relayer, args.dry_run, true).await;
+    if let Err(e) = operator
+        .run(
+            deployment.loop_delay_mins,
+            deployment.block_interval,
+            deployment.max_range,
+            args.once,
+        )
+        .await
+    {
+        error!("Operator for {} failed: {:#}", deployment.name(), e);
+    }
+}
+
+// Runs the operator of a deployment in its own task, and restarts it with backoff whenever it
+// panics, so one failing deployment does not stop the others. Pending requests are tracked in
+// Redis, so a restarted operator does not resubmit them.
+async fn supervise(
+    deployment: Deployment,
+    metrics: OperatorMetrics,
+    relayer: Option<Arc<Mutex<Relayer>>>,
+    args: RunArgs,
+) {
+    let alerter = Alerter::new();
+    let mut restart_delay = MIN_RESTART_DELAY;
+    loop {
+        let started = Instant::now();
+        let result = tokio::spawn(run_deployment(
+            deployment.clone(),
+            metrics.clone(),
+            relayer.clone(),
+            args.clone(),
+        ))
+        .await;
+        // The operator only returns by itself with --once, or on shutdown.
+        match result {
+            Ok(()) => return,
+            Err(e) => {
+                alerter
+                    .alert(&format!(
+                        "Operator for {} failed, restarting: {}",
+                        deployment.name(),
+                        e
+                    ))
+                    .await
+            }
+        }
+        if args.once {
             return;
         }
 
-        info!(
-            "Requesting header range with end block: {:?}.",
-            block_to_step_to.unwrap()
-        );
+        if started.elapsed() >= MAX_RESTART_DELAY {
+            restart_delay = MIN_RESTART_DELAY;
+        }
+        tokio::time::sleep(restart_delay).await;
+        restart_delay = min(restart_delay * 2, MAX_RESTART_DELAY);
+    }
+}
 
-        // Request the header range proof to block_to_step_to.
-        match self
-            .request_header_range(
-                header_range_contract_data.current_block,
-                request_authority_set_id,
-                block_to_step_to.unwrap(),
-                header_range_contract_data.header_range_function_id,
-            )
-            .await
-        {
-            Ok(request_id) => {
-                info!(
-                    "Header range request submitted from block {} to block {} with request ID: {}",
-                    header_range_contract_data.current_block,
-                    block_to_step_to.unwrap(),
-                    request_id
-                )
-            }
-            Err(e) => {
-                error!("Header range request failed: {}", e);
+// Runs a manual command against each of the deployments. The requests need exactly one. Fails on
+// invalid input, and stops at the first deployment that fails.
+async fn run_manual_command(deployments: Vec<Deployment>, command: Command) -> Result<()> {
+    let requests_one = matches!(
+        command,
+        Command::RequestRange { .. }
+            | Command::RequestRotate { .. }
+            | Command::ResetAttempts { .. }
+    );
+    if requests_one && deployments.len() > 1 {
+        bail!("Several deployments are configured, select one with --deployment");
+    }
+
+    for deployment in deployments {
+        let dry_run = match command {
+            Command::RequestRange { dry_run, .. } | Command::RequestRotate { dry_run, .. } => {
+                dry_run
             }
+            Command::ResetAttempts { .. } => false,
+            _ => true,
         };
+        let mut operator =
+            VectorXOperator::new(&deployment, OperatorMetrics::new(), None, dry_run, false).await;
+
+        println!("Deployment {}:", deployment.name());
+        match command {
+            Command::Status => {
+                operator
+                    .print_status(deployment.block_interval, deployment.max_range)
+                    .await?
+            }
+            Command::Plan => {
+                operator
+                    .run(
+                        deployment.loop_delay_mins,
+                        deployment.block_interval,
+                        deployment.max_range,
+                        true,
+                    )
+                    .await?
+            }
+            Command::RequestRange { from, to, .. } => operator.request_range(from, to).await?,
+            Command::RequestRotate { set_id, .. } => operator.request_rotate(set_id).await?,
+            Command::ResetAttempts { ref request } => operator.reset_attempts(request).await?,
+            Command::Run(_) => unreachable!(),
+        }
     }
+    Ok(())
+}
 
-    async fn get_header_range_input_data(
-        &mut self,
-        trusted_block: u32,
-        trusted_authority_set_id: u64,
-    ) -> (B256, B256) {
-        let trusted_header_hash = self
-            .contract
-            .block_height_to_header_hash(trusted_block)
-            .await
-            .unwrap();
-        let trusted_authority_set_hash = self
-            .contract
-            .authority_set_id_to_hash(trusted_authority_set_id)
-            .await
-            .unwrap();
+#[tokio::main]
+async fn main() {
+    env::set_var("RUST_LOG", "info");
+    dotenv::dotenv().ok();
+    env_logger::init();
 
-        (
-            B256::from_slice(&trusted_header_hash),
-            B256::from_slice(&trusted_authority_set_hash),
-        )
+    let args = VectorXArgs::parse();
+    let command = args
+        .command
+        .clone()
+        .unwrap_or(Command::Run(args.run.clone()));
+
+    // The deployments are listed in the config file at OPERATOR_CONFIG (TOML, or JSON if the path
+    // ends in .json). See operator.example.toml. Without it, a single deployment is configured with
+    // environment variables.
+    let deployments = match env::var("OPERATOR_CONFIG") {
+        Ok(config_path) => read_config(&config_path).deployments,
+        Err(_) => vec![Deployment::from_env()],
+    };
+    if deployments.is_empty() {
+        error!("No deployments configured.");
+        return;
     }
 
-    // Current authority set hash.
-    async fn get_rotate_input_data(&mut self, current_authority_set_id: u64) -> B256 {
-        alloy_primitives::FixedBytes(
-            self.contract
-                .authority_set_id_to_hash(current_authority_set_id)
-                .await
-                .unwrap(),
-        )
+    let mut errors = Vec::new();
+    let mut contracts = HashSet::new();
+    for deployment in deployments.iter() {
+        for error in deployment.validate() {
+            errors.push(format!("{}: {}", deployment.name(), error));
+        }
+        if !contracts.insert((
+            deployment.chain_id,
+            deployment.contract_address.to_lowercase(),
+        )) {
+            errors.push(format!(
+                "{}: configured more than once on chain {}",
+                deployment.name(),
+                deployment.chain_id
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        panic!("Invalid operator config:\n{}", errors.join("\n"));
     }
 
-    // Current block, step_range_max and whether next authority set hash exists.
-    async fn get_contract_data_for_header_range(&mut self) -> HeaderRangeContractData {
-        let header_range_function_id: B256 =
-            FixedBytes(self.contract.header_range_function_id().await.unwrap());
-        let current_block = self.contract.latest_block().await.unwrap();
+    // With --deployment, only the deployment with that Avail chain name or contract address.
+    let deployments: Vec<Deployment> = match args.deployment.as_deref() {
+        Some(selected) => deployments
+            .into_iter()
+            .filter(|deployment| {
+                deployment.chain_name == selected
+                    || deployment.contract_address.eq_ignore_ascii_case(selected)
+            })
+            .collect(),
+        None => deployments,
+    };
+    if deployments.is_empty() {
+        panic!(
+            "No deployment matches {}",
+            args.deployment.unwrap_or_default()
+        );
+    }
 
-        let current_authority_set_id = self
-            .data_fetcher
-            .get_authority_set_id(current_block - 1)
-            .await;
-        let next_authority_set_id = current_authority_set_id + 1;
+    // Local proofs the gateway cannot verify would be generated for nothing.
+    if deployments
+        .iter()
+        .any(|deployment| deployment.prover_backend == ProverBackendKind::Local)
+    {
+        let prover = LocalProver::new();
+        for circuit in [Circuit::HeaderRange, Circuit::Rotate] {
+            if let Err(e) = prover.check_relayable(circuit) {
+                panic!("{}", e);
+            }
+        }
+    }
 
-        let next_authority_set_hash = self
-            .contract
-            .authority_set_id_to_hash(next_authority_set_id)
-            .await
-            .unwrap();
+    let run_args = match command {
+        Command::Run(run_args) => run_args,
+        command => {
+            if let Err(e) = run_manual_command(deployments, command).await {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    };
 
-        HeaderRangeContractData {
-            current_block,
-            next_authority_set_hash_exists: B256::from_slice(&next_authority_set_hash)
-                != B256::ZERO,
-            header_range_function_id,
+    // With METRICS_PORT set, the operator's metrics are served on /metrics.
+    let metrics = OperatorMetrics::new();
+    if let Ok(metrics_port) = env::var("METRICS_PORT") {
+        let metrics_port = metrics_port.parse::<u16>().expect("invalid METRICS_PORT");
+        tokio::spawn(metrics.clone().serve(metrics_port));
+    }
+
+    // With RELAY_PROOFS=true, the operator sends transactions itself with PRIVATE_KEY, through one
+    // relayer per Ethereum chain.
+    let mut relayers: HashMap<u32, Arc<Mutex<Relayer>>> = HashMap::new();
+    if env::var("RELAY_PROOFS").is_ok_and(|relay_proofs| relay_proofs == "true") {
+        let private_key = env::var("PRIVATE_KEY").expect("PRIVATE_KEY must be set");
+        for deployment in deployments.iter() {
+            relayers.entry(deployment.chain_id).or_insert_with(|| {
+                Arc::new(Mutex::new(Relayer::new(
+                    &deployment.ethereum_rpc_url,
+                    &private_key,
+                    deployment.chain_id as u64,
+                )))
+            });
         }
+    } else if env::var("RELAY_GUARDIAN_FALLBACK")
+        .is_ok_and(|guardian_fallback| guardian_fallback == "true")
+    {
+        panic!("RELAY_GUARDIAN_FALLBACK requires RELAY_PROOFS");
     }
 
-    // Current block and whether next authority set hash exists.
-    async fn get_contract_data_for_rotate(&mut self) -> RotateContractData {
-        let rotate_function_id: B256 =
-            FixedBytes(self.contract.rotate_function_id().await.unwrap());
-        let current_block = self.contract.latest_block().await.unwrap();
+    let mut join_handles = Vec::new();
+    for deployment in deployments {
+        info!("Running the operator for {}.", deployment.name());
+        let relayer = relayers.get(&deployment.chain_id).cloned();
+        join_handles.push(tokio::spawn(supervise(
+            deployment,
+            metrics.clone(),
+            relayer,
+            run_args.clone(),
+        )));
+    }
 
-        let current_authority_set_id = self
-            .data_fetcher
-            .get_authority_set_id(current_block - 1)
-            .await;
-        let next_authority_set_id = current_authority_set_id + 1;
+    for handle in join_handles {
+        handle.await.expect("Supervisor failed");
+    }
+}
 
-        let next_authority_set_hash = self
-            .contract
-            .authority_set_id_to_hash(next_authority_set_id)
-            .await
-            .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        RotateContractData {
-            current_block,
-            next_authority_set_hash_exists: B256::from_slice(&next_authority_set_hash)
-                != B256::ZERO,
-            rotate_function_id,
+    fn request(
+        request_type: &str,
+        trusted_block: u32,
+        target_block: u32,
+        set_id: u64,
+    ) -> StoredProofRequest {
+        StoredProofRequest {
+            request_id: String::new(),
+            request_type: request_type.to_string(),
+            trusted_block,
+            target_block,
+            authority_set_id: set_id,
+            submitted_at: 0,
+            attempt: 0,
         }
     }
 
-    fn get_succinct_client(&mut self) -> SuccinctClient {
-        self.client.clone()
+    // The header ranges the pipeline plans from the latest block, with the authority set of each
+    // range ending at its epoch end block in epoch_ends.
+    fn plan_pipeline(
+        latest_block: u32,
+        head_block: u32,
+        block_interval: u32,
+        pipeline_depth: u32,
+        epoch_ends: &[u32],
+    ) -> Vec<(u32, u32)> {
+        let mut ranges = Vec::new();
+        let mut trusted_block = latest_block;
+        for _ in 0..pipeline_depth {
+            let Some(block_to_request) = pipeline_block_to_request(
+                latest_block,
+                trusted_block,
+                head_block,
+                block_interval,
+                MAX_NUM_HEADERS as u32,
+            ) else {
+                break;
+            };
+            let last_justified_block = epoch_ends
+                .iter()
+                .copied()
+                .find(|epoch_end| *epoch_end > trusted_block)
+                .unwrap_or(0);
+            let target_block = cap_to_epoch_end(block_to_request, last_justified_block);
+            ranges.push((trusted_block, target_block));
+            trusted_block = target_block;
+        }
+        ranges
     }
 
-    fn get_config(&mut self) -> VectorXConfig {
-        self.config.clone()
+    #[test]
+    fn test_request_params() {
+        assert_eq!(
+            request_params(&request(HEADER_RANGE_REQUEST, 4000, 4200, 10)),
+            "header_range:4000:4200"
+        );
+        assert_eq!(
+            request_params(&request(ROTATE_REQUEST, 0, 0, 120)),
+            "rotate:120"
+        );
     }
 
-    fn get_data_fetcher(&mut self) -> RpcDataFetcher {
-        self.data_fetcher.clone()
+    #[test]
+    fn test_block_to_request() {
+        // The closest multiple of the interval below the head.
+        assert_eq!(block_to_request(1000, 1150, 100, 256), 1100);
+        // At most data_commitment_max blocks after the latest block.
+        assert_eq!(block_to_request(1000, 5000, 100, 256), 1200);
+        assert_eq!(block_to_request(1000, 5000, 1, 256), 1256);
     }
 
-    // If the authority_set_id is the current authority set, return the max_block_to_request. Else,
-    // return the minimum of max_block_to_request and last_justified_block.
-    async fn find_block_to_step_to(
-        &mut self,
-        max_block_to_request: u32,
-        authority_set_id: u64,
-    ) -> Option<u32> {
-        let last_justified_block = self
-            .data_fetcher
-            .last_justified_block(authority_set_id)
-            .await;
+    #[test]
+    fn test_pipeline_splits_at_max_num_headers_and_epoch_ends() {
+        // Ranges span at most MAX_NUM_HEADERS blocks, and the range through the epoch end block of
+        // authority set 1300 stops there, so the next range starts with the next authority set.
+        assert_eq!(
+            plan_pipeline(1000, 2000, 1, 5, &[1300]),
+            vec![(1000, 1256), (1256, 1300), (1300, 1556), (1556, 1812)]
+        );
 
-        // Last justified block will be 0 in this is the current authority set.
-        if last_justified_block == 0 {
-            return Some(max_block_to_request);
-        }
+        // The pipeline depth limits the number of ranges.
+        assert_eq!(
+            plan_pipeline(1000, 2000, 1, 2, &[1300]),
+            vec![(1000, 1256), (1256, 1300)]
+        );
+
+        // Targets are multiples of the block interval, except at epoch ends.
+        assert_eq!(
+            plan_pipeline(1000, 2000, 100, 3, &[1250]),
+            vec![(1000, 1200), (1200, 1250), (1250, 1500)]
+        );
 
-        Some(min(max_block_to_request, last_justified_block))
+        // The first range is planned even if it is not full, but later ranges are not.
+        assert_eq!(plan_pipeline(1000, 1300, 1, 3, &[]), vec![(1000, 1256)]);
+        assert_eq!(plan_pipeline(1000, 1100, 1, 3, &[]), vec![(1000, 1100)]);
     }
 
-    async fn run(&mut self, loop_delay_mins: u64, block_interval: u32, data_commitment_max: u32) {
-        loop {
-            // Check if there is a rotate available for the next authority set.
-            self.find_and_request_rotate().await;
+    #[test]
+    fn test_no_request_at_head() {
+        // The contract is at the head, or at the last multiple of the interval below it.
+        assert_eq!(pipeline_block_to_request(2000, 2000, 2000, 1, 256), None);
+        assert_eq!(pipeline_block_to_request(2000, 2000, 2150, 200, 256), None);
+        assert!(plan_pipeline(2000, 2000, 1, 3, &[]).is_empty());
+
+        // With no authority set change since the latest block, there is nothing to catch up.
+        assert!(catch_up_steps(2000, 12, &[2000]).is_empty());
+    }
 
-            // Get latest block of the Avail chain.
-            let avail_chain_latest_block_nb = self.data_fetcher.get_head().await.number;
+    #[test]
+    fn test_catch_up_dependencies_across_missed_rotate() {
+        // The contract is on authority set 10, and the head on authority set 12.
+        let mut steps = catch_up_steps(1000, 10, &[1300, 1600, 1800]);
+        let kinds = steps.iter().map(|step| step.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                CatchUpStepKind::HeaderRanges {
+                    authority_set_id: 10,
+                    target_block: 1300
+                },
+                CatchUpStepKind::Rotate {
+                    authority_set_id: 10
+                },
+                CatchUpStepKind::HeaderRanges {
+                    authority_set_id: 11,
+                    target_block: 1600
+                },
+                CatchUpStepKind::Rotate {
+                    authority_set_id: 11
+                },
+                CatchUpStepKind::HeaderRanges {
+                    authority_set_id: 12,
+                    target_block: 1800
+                },
+            ]
+        );
 
-            // Get latest block of contract.
-            let contract_latest_block_nb = self.contract.latest_block().await.unwrap();
+        // Until the rotate from authority set 10 is committed, the header ranges with authority
+        // set 11 and the next rotate wait for it. Header ranges do not wait for the header ranges
+        // before them, which the pipeline proves ahead of the contract.
+        let blocking = |steps: &[CatchUpStep]| {
+            steps
+                .iter()
+                .map(|step| blocking_dependencies(steps, step))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            blocking(&steps),
+            vec![vec![], vec![], vec![1], vec![1], vec![3]]
+        );
 
-            // block_to_request is the closest interval of block_interval less than min(avail_chain_latest_block_nb, data_commitment_max + current_block)
-            let max_block = std::cmp::min(
-                avail_chain_latest_block_nb,
-                data_commitment_max + contract_latest_block_nb,
-            );
-            let block_to_request = max_block - (max_block % block_interval);
+        steps[1].committed = true;
+        assert_eq!(
+            blocking(&steps),
+            vec![vec![], vec![], vec![], vec![], vec![3]]
+        );
 
-            if block_to_request > contract_latest_block_nb {
-                info!("Attempting to step to block: {}", block_to_request);
-                self.find_and_request_header_range(block_to_request).await;
+        // Requests are matched to the step of their type and authority set.
+        assert!(steps[2].covers(&request(HEADER_RANGE_REQUEST, 1300, 1556, 11)));
+        assert!(!steps[2].covers(&request(HEADER_RANGE_REQUEST, 1000, 1256, 10)));
+        assert!(steps[3].covers(&request(ROTATE_REQUEST, 0, 0, 11)));
+        assert!(!steps[3].covers(&request(HEADER_RANGE_REQUEST, 1600, 1800, 11)));
+
+        // Once the guardian filled the blocks of authority set 10, only its rotate is left.
+        let steps = catch_up_steps(1300, 10, &[1300, 1600, 1800]);
+        assert_eq!(
+            steps[0].kind,
+            CatchUpStepKind::Rotate {
+                authority_set_id: 10
             }
+        );
+        assert_eq!(steps[1].depends_on, vec![0]);
+    }
 
-            // Sleep for N minutes.
-            info!("Sleeping for {} minutes.", loop_delay_mins);
-            tokio::time::sleep(tokio::time::Duration::from_secs(60 * loop_delay_mins)).await;
+    fn planned(request: StoredProofRequest) -> PlannedRequest {
+        PlannedRequest {
+            circuit: match request.request_type.as_str() {
+                ROTATE_REQUEST => Circuit::Rotate,
+                _ => Circuit::HeaderRange,
+            },
+            description: request_params(&request),
+            function_id: B256::ZERO,
+            input: Vec::new(),
+            function_data: Vec::new(),
+            request,
         }
     }
-}
 
-#[tokio::main]
-async fn main() {
-    env::set_var("RUST_LOG", "info");
-    dotenv::dotenv().ok();
-    env_logger::init();
+    // The trusted and target blocks of the planned requests.
+    fn blocks(planned_requests: &[PlannedRequest]) -> Vec<(u32, u32)> {
+        planned_requests
+            .iter()
+            .map(|planned| (planned.request.trusted_block, planned.request.target_block))
+            .collect()
+    }
 
-    let data_fetcher = RpcDataFetcher::new().await;
-
-    let loop_delay_mins_env = env::var("LOOP_DELAY_MINS");
-    let mut loop_delay_mins = 5;
-    if loop_delay_mins_env.is_ok() {
-        loop_delay_mins = loop_delay_mins_env
-            .unwrap()
-            .parse::<u64>()
-            .expect("invalid LOOP_DELAY_MINS");
-    }
-    let update_delay_blocks_env = env::var("UPDATE_DELAY_BLOCKS");
-    let mut update_delay_blocks = 200;
-    if update_delay_blocks_env.is_ok() {
-        update_delay_blocks = update_delay_blocks_env
-            .unwrap()
-            .parse::<u32>()
-            .expect("invalid UPDATE_DELAY_BLOCKS");
-    }
-    let mut operator = VectorXOperator::new(data_fetcher).await;
-    const DATA_COMMITMENT_MAX: u32 = 256;
-    operator
-        .run(loop_delay_mins, update_delay_blocks, DATA_COMMITMENT_MAX)
-        .await;
+    #[test]
+    fn test_request_budget() {
+        let plan = || {
+            vec![
+                planned(request(ROTATE_REQUEST, 0, 0, 10)),
+                planned(request(HEADER_RANGE_REQUEST, 1000, 1180, 10)),
+                planned(request(HEADER_RANGE_REQUEST, 1180, 1360, 10)),
+            ]
+        };
+        let budget = |budget, submitted, reason| RequestBudget {
+            budget,
+            submitted,
+            window_secs: 60 * 60,
+            reason,
+        };
+
+        // Within the budget, every planned request is kept.
+        let mut planned_requests = plan();
+        let budgets = [budget(10, 3, "hourly_budget")];
+        assert!(split_off_over_budget(&mut planned_requests, &budgets).is_empty());
+        assert_eq!(planned_requests.len(), 3);
+
+        // The last header range goes over the hourly budget, and the last two over the daily one.
+        // The rotate is kept.
+        let mut planned_requests = plan();
+        let budgets = [
+            budget(10, 8, "hourly_budget"),
+            budget(100, 99, "daily_budget"),
+        ];
+        let deferred = split_off_over_budget(&mut planned_requests, &budgets);
+        assert_eq!(planned_requests[0].request.request_type, ROTATE_REQUEST);
+        assert_eq!(planned_requests.len(), 1);
+        let deferred = deferred
+            .iter()
+            .map(|(planned, budget)| (planned.request.trusted_block, budget.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            deferred,
+            vec![(1180, "hourly_budget"), (1000, "daily_budget")]
+        );
+
+        // An exhausted budget defers every request, even if more were submitted than the budget.
+        let mut planned_requests = plan();
+        let budgets = [budget(10, 12, "hourly_budget")];
+        let deferred = split_off_over_budget(&mut planned_requests, &budgets);
+        assert!(planned_requests.is_empty());
+        assert_eq!(
+            blocks(
+                &deferred
+                    .into_iter()
+                    .map(|(planned, _)| planned)
+                    .collect::<Vec<_>>()
+            ),
+            vec![(0, 0), (1000, 1180), (1180, 1360)]
+        );
+    }
+
+    #[test]
+    fn test_relay_deferral() {
+        let gwei = U256::exp10(9);
+        let receipt = |gas_used: u64, effective_gas_price_gwei: u64| TransactionReceipt {
+            gas_used: Some(gas_used.into()),
+            effective_gas_price: Some(U256::from(effective_gas_price_gwei) * gwei),
+            ..Default::default()
+        };
+
+        // The relays of the last day, a header range and a rotate.
+        let spent_gwei = [receipt(300_000, 20), receipt(1_200_000, 25)]
+            .iter()
+            .map(|receipt| (relay_spend_wei(receipt).unwrap() / gwei).as_u64())
+            .sum::<u64>();
+        assert_eq!(spent_gwei, 36_000_000);
+        // A receipt without the gas fees is not counted.
+        assert_eq!(relay_spend_wei(&TransactionReceipt::default()), None);
+
+        // Without budgets, relays are never deferred.
+        assert_eq!(relay_deferral(U256::MAX, None, u64::MAX, None), None);
+
+        let max_fee_per_gas = Some(U256::from(50) * gwei);
+        let fee_per_gas = U256::from(50) * gwei;
+        // A fee exactly at the cap is allowed, one above it is not.
+        assert_eq!(
+            relay_deferral(fee_per_gas, max_fee_per_gas, spent_gwei, None),
+            None
+        );
+        let (reason, _) =
+            relay_deferral(fee_per_gas + 1, max_fee_per_gas, spent_gwei, None).unwrap();
+        assert_eq!(reason, "gas_price");
+
+        // Relays are deferred once the spend of the last day reaches the budget.
+        assert_eq!(
+            relay_deferral(fee_per_gas, max_fee_per_gas, spent_gwei, Some(40_000_000)),
+            None
+        );
+        let (reason, explanation) =
+            relay_deferral(fee_per_gas, max_fee_per_gas, spent_gwei, Some(36_000_000)).unwrap();
+        assert_eq!(reason, "relay_spend");
+        assert_eq!(
+            explanation,
+            "the relays of the last day paid 36000000 gwei, the budget is 36000000 gwei"
+        );
+    }
+
+    #[test]
+    fn test_error_endpoint() {
+        // Contract calls are not labelled.
+        assert_eq!(error_endpoint(&anyhow::anyhow!("reverted")), "ethereum");
+
+        let avail_error: Result<()> = Err(anyhow::anyhow!("connection reset"));
+        let avail_error = avail_error.context(Endpoint::Avail).unwrap_err();
+        assert_eq!(error_endpoint(&avail_error), "avail");
+
+        // The label survives further context.
+        let redis_error: Result<()> = Err(anyhow::anyhow!("connection refused"));
+        let redis_error = redis_error
+            .context(Endpoint::Redis)
+            .context("Failed to plan")
+            .unwrap_err();
+        assert_eq!(error_endpoint(&redis_error), "redis");
+    }
 }
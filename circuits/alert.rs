@@ -0,0 +1,47 @@
+use std::env;
+
+use log::error;
+use serde_json::json;
+
+/// Sends alerts to the log and, if `ALERT_WEBHOOK_URL` is set, to a webhook. The webhook receives
+/// a JSON body of the form `{"text": <message>}`, which is accepted by Slack and Discord.
+#[derive(Clone)]
+pub struct Alerter {
+    webhook_url: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Alerter {
+    pub fn new() -> Self {
+        dotenv::dotenv().ok();
+
+        Alerter {
+            webhook_url: env::var("ALERT_WEBHOOK_URL").ok(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Logs the alert and posts it to the webhook. Failing to reach the webhook is logged, but
+    /// does not stop the caller.
+    pub async fn alert(&self, message: &str) {
+        error!("ALERT: {}", message);
+
+        if let Some(webhook_url) = &self.webhook_url {
+            let res = self
+                .client
+                .post(webhook_url)
+                .json(&json!({ "text": message }))
+                .send()
+                .await;
+            if let Err(e) = res {
+                error!("Failed to send alert to webhook: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for Alerter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::input::decode_precommit;
+use crate::input::types::StoredJustificationData;
+
+/// Participation of a single validator in the justifications of an authority set.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorParticipation {
+    pub pubkey: String,
+    pub signed: usize,
+    pub total: usize,
+    pub participation_rate: f64,
+}
+
+/// Participation of an authority set over the indexed justifications in a block range.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthoritySetParticipation {
+    pub authority_set_id: u64,
+    pub start_block: u32,
+    pub end_block: u32,
+    pub num_authorities: usize,
+    pub num_justifications: usize,
+    // Minimum number of signatures for a justification to be stored and provable.
+    pub min_signatures: usize,
+    // Smallest number of signatures above min_signatures across all justifications.
+    pub min_margin: usize,
+    // Number of justifications with at most near_threshold_margin signatures above min_signatures.
+    pub near_threshold_count: usize,
+    pub validators: Vec<ValidatorParticipation>,
+    pub never_signed: Vec<String>,
+}
+
+/// The bias of the participation computed from stored justifications, which only exist for the
+/// blocks where more than 2/3 of the authorities signed.
+pub const PARTICIPATION_BIAS_NOTE: &str = "Participation is counted over the stored justifications only. Each had more than 2/3 of the authorities sign, so rounds that did not reach the threshold are not counted, and participation and margins are overstated.";
+
+// Minimum number of signatures for more than 2/3 of the authorities to have signed. Matches the
+// check in the indexer.
+fn min_signatures(num_authorities: usize) -> usize {
+    (num_authorities * 2) / 3 + 1
+}
+
+/// Computes per authority set participation from stored justifications. The authority set of each
+/// justification is decoded from its signed message. A justification is near the threshold if it
+/// has at most `near_threshold_margin` signatures above the minimum. See
+/// [`PARTICIPATION_BIAS_NOTE`] for the bias of counting stored justifications only.
+pub fn compute_participation(
+    justifications: &[StoredJustificationData],
+    near_threshold_margin: usize,
+) -> Vec<AuthoritySetParticipation> {
+    let mut by_authority_set: BTreeMap<u64, Vec<&StoredJustificationData>> = BTreeMap::new();
+    for justification in justifications.iter() {
+        let (_, _, _, authority_set_id) = decode_precommit(justification.signed_message.clone());
+        by_authority_set
+            .entry(authority_set_id)
+            .or_default()
+            .push(justification);
+    }
+
+    by_authority_set
+        .into_iter()
+        .map(|(authority_set_id, justifications)| {
+            // The authority set is the same for all justifications signed by it.
+            let pubkeys = &justifications[0].pubkeys;
+            let num_authorities = justifications[0].num_authorities;
+            let min_signatures = min_signatures(num_authorities);
+
+            let mut signed_counts = vec![0usize; pubkeys.len()];
+            let mut min_margin = usize::MAX;
+            let mut near_threshold_count = 0;
+            for justification in justifications.iter() {
+                let mut signed = 0usize;
+                for (i, validator_signed) in justification.validator_signed.iter().enumerate() {
+                    if *validator_signed {
+                        signed_counts[i] += 1;
+                        signed += 1;
+                    }
+                }
+
+                let margin = signed.saturating_sub(min_signatures);
+                min_margin = min_margin.min(margin);
+                if margin <= near_threshold_margin {
+                    near_threshold_count += 1;
+                }
+            }
+
+            let total = justifications.len();
+            let validators = pubkeys
+                .iter()
+                .zip(signed_counts.iter())
+                .map(|(pubkey, signed)| ValidatorParticipation {
+                    pubkey: format!("0x{}", hex::encode(pubkey)),
+                    signed: *signed,
+                    total,
+                    participation_rate: *signed as f64 / total as f64,
+                })
+                .collect::<Vec<_>>();
+            let never_signed = validators
+                .iter()
+                .filter(|v| v.signed == 0)
+                .map(|v| v.pubkey.clone())
+                .collect();
+
+            AuthoritySetParticipation {
+                authority_set_id,
+                start_block: justifications.iter().map(|j| j.block_number).min().unwrap(),
+                end_block: justifications.iter().map(|j| j.block_number).max().unwrap(),
+                num_authorities,
+                num_justifications: total,
+                min_signatures,
+                min_margin,
+                near_threshold_count,
+                validators,
+                never_signed,
+            }
+        })
+        .collect()
+}
+
+/// Formats the participation as CSV, with one row per validator per authority set, after a `#`
+/// comment line with [`PARTICIPATION_BIAS_NOTE`].
+pub fn participation_to_csv(participation: &[AuthoritySetParticipation]) -> String {
+    let mut csv = format!("# {}\n", PARTICIPATION_BIAS_NOTE);
+    csv.push_str(
+        "authority_set_id,start_block,end_block,num_justifications,min_margin,near_threshold_count,pubkey,signed,participation_rate\n",
+    );
+    for authority_set in participation.iter() {
+        for validator in authority_set.validators.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{:.4}\n",
+                authority_set.authority_set_id,
+                authority_set.start_block,
+                authority_set.end_block,
+                authority_set.num_justifications,
+                authority_set.min_margin,
+                authority_set.near_threshold_count,
+                validator.pubkey,
+                validator.signed,
+                validator.participation_rate
+            ));
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn justification(
+        block_number: u32,
+        authority_set_id: u64,
+        validator_signed: Vec<bool>,
+    ) -> StoredJustificationData {
+        // Encoded precommit: 1 || block hash || block number || round || authority set id.
+        let mut signed_message = vec![1u8];
+        signed_message.extend_from_slice(&[0u8; 32]);
+        signed_message.extend_from_slice(&block_number.to_le_bytes());
+        signed_message.extend_from_slice(&0u64.to_le_bytes());
+        signed_message.extend_from_slice(&authority_set_id.to_le_bytes());
+
+        let num_authorities = validator_signed.len();
+        StoredJustificationData {
+            block_number,
+            signed_message,
+            pubkeys: (0..num_authorities as u8).map(|i| vec![i; 32]).collect(),
+            signatures: vec![vec![0u8; 64]; num_authorities],
+            validator_signed,
+            num_authorities,
+        }
+    }
+
+    #[test]
+    fn test_compute_participation() {
+        let justifications = vec![
+            justification(10, 1, vec![true, true, true, true, true, false, false]),
+            justification(20, 1, vec![true, true, true, true, true, true, false]),
+            justification(30, 2, vec![true, true, true, true, true, true]),
+        ];
+
+        let participation = compute_participation(&justifications, 0);
+        assert_eq!(participation.len(), 2);
+
+        let first = &participation[0];
+        assert_eq!(first.authority_set_id, 1);
+        assert_eq!((first.start_block, first.end_block), (10, 20));
+        assert_eq!(first.num_justifications, 2);
+        assert_eq!(first.min_signatures, 5);
+        assert_eq!(first.min_margin, 0);
+        assert_eq!(first.near_threshold_count, 1);
+        assert_eq!(first.validators[0].participation_rate, 1.0);
+        assert_eq!(first.validators[5].participation_rate, 0.5);
+        assert_eq!(
+            first.never_signed,
+            vec![format!("0x{}", hex::encode([6u8; 32]))]
+        );
+
+        let second = &participation[1];
+        assert_eq!(second.authority_set_id, 2);
+        assert_eq!(second.min_margin, 1);
+        assert_eq!(second.near_threshold_count, 0);
+        assert!(second.never_signed.is_empty());
+
+        // The CSV starts with the bias of the report.
+        let csv = participation_to_csv(&participation);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(format!("# {}", PARTICIPATION_BIAS_NOTE).as_str())
+        );
+        assert!(lines.next().unwrap().starts_with("authority_set_id,"));
+        assert_eq!(lines.count(), 13);
+    }
+}
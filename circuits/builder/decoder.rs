@@ -289,6 +289,7 @@ pub mod tests {
             data_fetcher
                 .get_block_headers_range(HEAD_BLOCK_NUM, HEAD_BLOCK_NUM + NUM_BLOCKS as u32)
                 .await
+                .unwrap()
         });
 
         let encoded_headers_values: Vec<EncodedHeader<MAX_HEADER_SIZE, F>> = headers[0..NUM_BLOCKS]
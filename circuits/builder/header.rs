@@ -67,6 +67,7 @@ mod tests {
             data_fetcher
                 .get_block_headers_range(HEAD_BLOCK_NUM, HEAD_BLOCK_NUM + NUM_HEADERS as u32)
                 .await
+                .unwrap()
         });
 
         let encoded_headers_values: Vec<EncodedHeader<MAX_HEADER_SIZE, F>> = headers
@@ -113,6 +114,7 @@ mod tests {
             data_fetcher
                 .get_block_headers_range(START_BLOCK_NUM, START_BLOCK_NUM + NUM_BLOCKS as u32)
                 .await
+                .unwrap()
         });
 
         let mut max_size = 0;
@@ -157,6 +159,7 @@ mod tests {
             data_fetcher
                 .get_block_headers_range(HEAD_BLOCK_NUM, HEAD_BLOCK_NUM + NUM_HEADERS as u32)
                 .await
+                .unwrap()
         });
 
         let encoded_headers_values: Vec<EncodedHeader<MAX_HEADER_SIZE, F>> = headers
@@ -202,7 +205,7 @@ mod tests {
         let block_nbr = 397859;
 
         let data_fetcher = RpcDataFetcher::new().await;
-        let header = data_fetcher.get_header(block_nbr).await;
+        let header = data_fetcher.get_header(block_nbr).await.unwrap();
         let header_bytes = header.encode();
         let header_size = header_bytes.len();
         println!("Header size: {:?}", header_size);
@@ -346,6 +346,7 @@ impl<
                 data_fetcher
                     .get_block_headers_range(start_block, last_block)
                     .await
+                    .expect("Failed to get headers from Avail.")
             });
         }
 
@@ -25,6 +25,7 @@ impl Program for DummyHeaderRange {
             let target_header_hash = data_fetcher
                 .get_header(target_block)
                 .await
+                .unwrap()
                 .hash()
                 .0
                 .to_vec();
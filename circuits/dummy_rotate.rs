@@ -17,7 +17,10 @@ impl Program for DummyRotate {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let new_authority_set_hash: Vec<u8> = rt.block_on(async {
             let mut data_fetcher = RpcDataFetcher::new().await;
-            let epoch_end_block_number = data_fetcher.last_justified_block(authority_set_id).await;
+            let epoch_end_block_number = data_fetcher
+                .last_justified_block(authority_set_id)
+                .await
+                .expect("Failed to find the last justified block on Avail.");
             data_fetcher
                 .compute_authority_set_hash(epoch_end_block_number)
                 .await
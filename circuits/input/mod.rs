@@ -1,6 +1,6 @@
 pub mod types;
 
-use std::cmp::Ordering;
+use std::cmp::{min, Ordering};
 use std::collections::HashMap;
 use std::env;
 use std::time::Duration;
@@ -9,28 +9,34 @@ use alloy_sol_types::{sol, SolType};
 use anyhow::Error;
 use avail_subxt::avail::Client;
 use avail_subxt::config::substrate::DigestItem;
+use avail_subxt::config::Header as HeaderTrait;
 use avail_subxt::primitives::Header;
 use avail_subxt::subxt_rpc::RpcParams;
 use avail_subxt::{api, build_client};
 use codec::{Compact, Decode, Encode};
 use ed25519_dalek::{PublicKey, Signature, Verifier};
 use ethers::types::H256;
+use ethers::utils::keccak256;
 use futures::future::join_all;
+use futures::StreamExt;
 use log::{debug, info};
 use plonky2x::frontend::curta::ec::point::CompressedEdwardsY;
 use plonky2x::frontend::ecc::curve25519::ed25519::eddsa::{DUMMY_PUBLIC_KEY, DUMMY_SIGNATURE};
 use redis::aio::Connection;
 use redis::{AsyncCommands, JsonAsyncCommands};
 use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
 use tokio::time::sleep;
 
 use self::types::{
-    CircuitJustification, EncodedFinalityProof, FinalityProof, GrandpaJustification,
-    HeaderRotateData, SignerMessage, SimpleJustificationData, StoredJustificationData,
+    BlockRangeData, CircuitJustification, EncodedFinalityProof, FinalityProof,
+    GrandpaJustification, HeaderRotateData, SignerMessage, SimpleJustificationData,
+    StoredCommitmentMismatch, StoredContractEvent, StoredContractState, StoredDataRootProof,
+    StoredEquivocation, StoredJustificationData, StoredProofRequest,
 };
 use crate::consts::{
-    BASE_PREFIX_LENGTH, DELAY_LENGTH, HASH_SIZE, MAX_NUM_HEADERS, PUBKEY_LENGTH, VALIDATOR_LENGTH,
-    WEIGHT_LENGTH,
+    BASE_PREFIX_LENGTH, DELAY_LENGTH, HASH_SIZE, MAX_HEADER_SIZE, MAX_NUM_HEADERS, PUBKEY_LENGTH,
+    VALIDATOR_LENGTH, WEIGHT_LENGTH,
 };
 
 #[derive(Clone)]
@@ -38,6 +44,7 @@ pub struct RedisClient {
     pub redis: redis::Client,
 }
 
+#[derive(Clone, Debug)]
 pub struct DataCommitmentRange {
     pub start: u32,
     pub end: u32,
@@ -46,6 +53,48 @@ pub struct DataCommitmentRange {
 
 type DataCommitmentRangeTuple = sol! { tuple(uint32, uint32, bytes32) };
 
+sol! { struct RangeHashInput {
+    uint32 trusted_block;
+    uint32 end_block;
+} }
+
+/// Computes the key of the range (start_block, end_block] in the VectorX contract's commitment
+/// mappings, which is keccak256(abi.encode(startBlock, endBlock)).
+pub fn compute_range_hash(start_block: u32, end_block: u32) -> H256 {
+    let range_hash_input = RangeHashInput {
+        trusted_block: start_block,
+        end_block,
+    };
+    H256::from(keccak256(RangeHashInput::abi_encode(&range_hash_input)))
+}
+
+// Time after the last failure of a proof request until its failed attempts are forgotten.
+const FAILED_REQUEST_ATTEMPTS_TTL_SECS: usize = 6 * 60 * 60;
+
+// Offset of the entries without a log index within the scores of their block, so they are ordered
+// after the logs of the block.
+const TIMELINE_ENTRY_INDEX_OFFSET: u64 = 90_000;
+
+// Score of a timeline entry, ordering entries by block and then log index. Entries without a log
+// index are ordered after the logs of their block, by their entry index.
+fn timeline_score(block_number: u64, log_index: Option<u64>, entry_index: Option<u64>) -> u64 {
+    let index = match log_index {
+        Some(log_index) => log_index,
+        None => TIMELINE_ENTRY_INDEX_OFFSET + entry_index.unwrap_or(0),
+    };
+    block_number.saturating_mul(100_000).saturating_add(index)
+}
+
+// Decodes a range stored as start (4 bytes) || end (4 bytes) || data commitment (32 bytes).
+fn decode_data_commitment_range(range: &str) -> DataCommitmentRange {
+    let range_data = hex::decode(range).unwrap();
+    DataCommitmentRange {
+        start: u32::from_be_bytes(range_data[0..4].try_into().unwrap()),
+        end: u32::from_be_bytes(range_data[4..8].try_into().unwrap()),
+        data_commitment: range_data[8..40].to_vec(),
+    }
+}
+
 impl RedisClient {
     const MAX_RECONNECT_ATTEMPTS: usize = 3;
     const RECONNECT_DELAY: Duration = Duration::from_secs(5);
@@ -144,6 +193,42 @@ impl RedisClient {
         }
     }
 
+    /// Stores an equivocation as evidence in Redis. Errors if setting the key fails.
+    pub async fn add_equivocation(
+        &mut self,
+        avail_chain_id: &str,
+        equivocation: StoredEquivocation,
+    ) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let equivocation_key = format!(
+            "{}:equivocation:{}:{}:{}",
+            avail_chain_id,
+            equivocation.authority_set_id,
+            equivocation.round,
+            hex::encode(&equivocation.pubkey)
+        );
+
+        // Equivocation is stored as a JSON object.
+        let _: () = con
+            .json_set(equivocation_key.clone(), "$", &equivocation)
+            .await
+            .expect("Failed to set key");
+
+        // Add the key to a sorted set by authority set id, so we can query for all equivocations.
+        let _: () = con
+            .zadd(
+                format!("{}:equivocations", avail_chain_id),
+                equivocation_key,
+                equivocation.authority_set_id,
+            )
+            .await
+            .expect("Failed to add key to sorted set");
+    }
+
     /// Gets all blocks in range [start, end] (inclusive) that have justifications in Redis.
     pub async fn get_blocks_in_range(
         &mut self,
@@ -163,6 +248,50 @@ impl RedisClient {
             .expect("Failed to get keys")
     }
 
+    /// Gets all justifications in range [start, end] (inclusive) from Redis. Justifications that
+    /// fail to deserialize are skipped.
+    pub async fn get_justifications_in_range(
+        &mut self,
+        avail_chain_id: &str,
+        start: u32,
+        end: u32,
+    ) -> Vec<StoredJustificationData> {
+        let blocks = self.get_blocks_in_range(avail_chain_id, start, end).await;
+
+        let mut justifications = Vec::new();
+        for block_number in blocks {
+            match self.get_justification(avail_chain_id, block_number).await {
+                Ok(justification) => justifications.push(justification),
+                Err(e) => debug!("Skipping justification for block {}: {}", block_number, e),
+            }
+        }
+        justifications
+    }
+
+    /// Gets the last Ethereum block whose events have been fully processed for the contract.
+    pub async fn get_events_checkpoint(&mut self, chain_id: u64, address: Vec<u8>) -> Option<u64> {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:checkpoint", chain_id, hex::encode(address));
+
+        con.get(key).await.expect("Failed to get key")
+    }
+
+    /// Stores the last Ethereum block whose events have been fully processed for the contract.
+    pub async fn set_events_checkpoint(&mut self, chain_id: u64, address: Vec<u8>, block: u64) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:checkpoint", chain_id, hex::encode(address));
+
+        let _: () = con.set(key, block).await.expect("Failed to set key");
+    }
+
     /// Stores data commitment range data in Redis. Errors if setting the key fails.
     pub async fn add_data_commitment_range(
         &mut self,
@@ -197,6 +326,696 @@ impl RedisClient {
             hex::encode(data_commitment)
         );
     }
+
+    /// Removes a data commitment range from Redis, e.g. after its event was reorged out. Errors if
+    /// removing the key fails.
+    pub async fn remove_data_commitment_range(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        range: DataCommitmentRange,
+    ) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:ranges", chain_id, hex::encode(address));
+
+        let data_commitment: [u8; 32] = range.data_commitment.try_into().unwrap();
+
+        let range_data: Vec<u8> =
+            DataCommitmentRangeTuple::abi_encode_packed(&(range.start, range.end, data_commitment));
+        let _: () = con
+            .zrem(key, hex::encode(range_data))
+            .await
+            .expect("Failed to remove key");
+
+        info!(
+            "Removed range: {:?}-{:?} with data commitment: {:?}",
+            range.start,
+            range.end,
+            hex::encode(data_commitment)
+        );
+    }
+
+    /// Marks a data commitment range whose on-chain commitments do not match the recomputed
+    /// commitments. Errors if setting the key fails.
+    pub async fn add_commitment_mismatch(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        mismatch: StoredCommitmentMismatch,
+    ) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let address = format!("0x{}", hex::encode(address));
+
+        let mismatch_key = format!(
+            "{}:{}:mismatch:{}:{}",
+            chain_id, address, mismatch.start_block, mismatch.end_block
+        );
+
+        // Mismatch is stored as a JSON object.
+        let _: () = con
+            .json_set(mismatch_key.clone(), "$", &mismatch)
+            .await
+            .expect("Failed to set key");
+
+        // Add the key to a sorted set by end block, so we can query for all mismatches.
+        let _: () = con
+            .zadd(
+                format!("{}:{}:mismatches", chain_id, address),
+                mismatch_key,
+                mismatch.end_block,
+            )
+            .await
+            .expect("Failed to add key to sorted set");
+    }
+
+    /// Stores the data root proof of each block, keyed by block number, with an index from block
+    /// hash to block number. Errors if Redis cannot be reached.
+    pub async fn add_data_root_proofs(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        proofs: Vec<StoredDataRootProof>,
+    ) -> Result<(), Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let address = format!("0x{}", hex::encode(address));
+
+        for proof in proofs.iter() {
+            let proof_key = format!("{}:{}:proof:{}", chain_id, address, proof.block_number);
+
+            // Proof is stored as a JSON object.
+            let _: () = con.json_set(proof_key, "$", proof).await?;
+
+            let block_hash_key = format!(
+                "{}:{}:proof_block:{:?}",
+                chain_id, address, proof.block_hash
+            );
+            let _: () = con.set(block_hash_key, proof.block_number).await?;
+        }
+
+        debug!(
+            "Added data root proofs for {} blocks on chain {} at {}",
+            proofs.len(),
+            chain_id,
+            address
+        );
+        Ok(())
+    }
+
+    /// Gets the data root proof of a block by block number, or None if no proof is stored for the
+    /// block. Errors if Redis cannot be reached or the stored proof cannot be deserialized.
+    pub async fn get_data_root_proof(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        block_number: u32,
+    ) -> Result<Option<StoredDataRootProof>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!(
+            "{}:0x{}:proof:{}",
+            chain_id,
+            hex::encode(address),
+            block_number
+        );
+
+        // Result is always stored as serialized bytes: https://github.com/redis-rs/redis-rs#json-support.
+        let serialized_proof: Option<Vec<u8>> = con.json_get(key, "$").await?;
+        let Some(serialized_proof) = serialized_proof else {
+            return Ok(None);
+        };
+
+        match serde_json::from_slice::<Vec<StoredDataRootProof>>(&serialized_proof) {
+            Ok(proof) => Ok(Some(proof[0].clone())),
+            Err(e) => Err(anyhow::anyhow!("Failed to deserialize proof: {}", e)),
+        }
+    }
+
+    /// Gets the data root proof of a block by block hash, or None if no proof is stored for the
+    /// block. Errors if Redis cannot be reached or the stored proof cannot be deserialized.
+    pub async fn get_data_root_proof_by_hash(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        block_hash: H256,
+    ) -> Result<Option<StoredDataRootProof>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!(
+            "{}:0x{}:proof_block:{:?}",
+            chain_id,
+            hex::encode(&address),
+            block_hash
+        );
+        let block_number: Option<u32> = con.get(key).await?;
+
+        match block_number {
+            Some(block_number) => {
+                self.get_data_root_proof(chain_id, address, block_number)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the data root proofs of the blocks in the range [start_block + 1, end_block].
+    pub async fn remove_data_root_proofs(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        start_block: u32,
+        end_block: u32,
+    ) {
+        for block_number in start_block + 1..=end_block {
+            let Ok(Some(proof)) = self
+                .get_data_root_proof(chain_id, address.clone(), block_number)
+                .await
+            else {
+                continue;
+            };
+
+            let mut con = match self.get_connection().await {
+                Ok(con) => con,
+                Err(e) => panic!("{}", e),
+            };
+
+            let address = format!("0x{}", hex::encode(&address));
+            let _: () = con
+                .del(&[
+                    format!("{}:{}:proof:{}", chain_id, address, block_number),
+                    format!(
+                        "{}:{}:proof_block:{:?}",
+                        chain_id, address, proof.block_hash
+                    ),
+                ])
+                .await
+                .expect("Failed to delete keys");
+        }
+    }
+
+    /// Gets the stored data commitment range (start, end] that contains the block, if any, or an
+    /// error if Redis cannot be reached.
+    pub async fn get_data_commitment_range_for_block(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        block_number: u32,
+    ) -> Result<Option<DataCommitmentRange>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:ranges", chain_id, hex::encode(address));
+
+        // Ranges are scored by end block, so the first range ending at or after the block is the
+        // only range that can contain it.
+        let ranges: Vec<String> = con
+            .zrangebyscore_limit(key, block_number, "+inf", 0, 1)
+            .await?;
+
+        Ok(ranges
+            .first()
+            .map(|range| decode_data_commitment_range(range))
+            .filter(|range| range.start < block_number))
+    }
+
+    /// Stores the Ethereum block of the last HeaderRangeCommitmentStored event of the contract.
+    pub async fn set_last_log_block(&mut self, chain_id: u64, address: Vec<u8>, block: u64) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:last_log_block", chain_id, hex::encode(address));
+
+        let _: () = con.set(key, block).await.expect("Failed to set key");
+    }
+
+    /// Gets the Ethereum block of the last HeaderRangeCommitmentStored event of the contract, or an
+    /// error if Redis cannot be reached.
+    pub async fn get_last_log_block(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+    ) -> Result<Option<u64>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:last_log_block", chain_id, hex::encode(address));
+
+        Ok(con.get(key).await?)
+    }
+
+    /// Adds an entry to the timeline of the contract.
+    pub async fn add_contract_event(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        event: StoredContractEvent,
+    ) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:timeline", chain_id, hex::encode(address));
+        let score = timeline_score(event.block_number, event.log_index, event.entry_index);
+
+        // Replace any entry already stored for the same log, so reprocessing a block is idempotent.
+        let _: () = con
+            .zrembyscore(&key, score, score)
+            .await
+            .expect("Failed to remove key");
+        let _: () = con
+            .zadd(&key, serde_json::to_string(&event).unwrap(), score)
+            .await
+            .expect("Failed to add key");
+    }
+
+    /// Removes the timeline entry of a log, e.g. when the log is reorged out.
+    pub async fn remove_contract_event(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        block_number: u64,
+        log_index: u64,
+    ) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:timeline", chain_id, hex::encode(address));
+        let score = timeline_score(block_number, Some(log_index), None);
+
+        let _: () = con
+            .zrembyscore(key, score, score)
+            .await
+            .expect("Failed to remove key");
+    }
+
+    /// Gets the timeline entries of the contract in the Ethereum block range [from_block, to_block],
+    /// or an error if Redis cannot be reached or an entry cannot be deserialized.
+    pub async fn get_contract_events(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<StoredContractEvent>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:timeline", chain_id, hex::encode(address));
+
+        let events: Vec<String> = con
+            .zrangebyscore(
+                key,
+                timeline_score(from_block, Some(0), None),
+                timeline_score(to_block, Some(99_999), None),
+            )
+            .await?;
+
+        events
+            .iter()
+            .map(|event| serde_json::from_str(event).map_err(Error::from))
+            .collect()
+    }
+
+    /// Gets the contract state last observed by the events service.
+    pub async fn get_contract_state(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+    ) -> Option<StoredContractState> {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:state", chain_id, hex::encode(address));
+
+        // Result is always stored as serialized bytes: https://github.com/redis-rs/redis-rs#json-support.
+        let serialized_state: Option<Vec<u8>> =
+            con.json_get(key, "$").await.expect("Failed to get key");
+
+        serialized_state.map(|serialized_state| {
+            serde_json::from_slice::<Vec<StoredContractState>>(&serialized_state).unwrap()[0]
+                .clone()
+        })
+    }
+
+    /// Stores the contract state last observed by the events service.
+    pub async fn set_contract_state(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        state: StoredContractState,
+    ) {
+        let mut con = match self.get_connection().await {
+            Ok(con) => con,
+            Err(e) => panic!("{}", e),
+        };
+
+        let key = format!("{}:0x{}:state", chain_id, hex::encode(address));
+
+        let _: () = con
+            .json_set(key, "$", &state)
+            .await
+            .expect("Failed to set key");
+    }
+
+    /// Adds a pending proof request of the operator for the contract.
+    pub async fn add_proof_request(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        request: StoredProofRequest,
+    ) -> Result<(), Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let request_key = format!(
+            "{}:0x{}:request:{}",
+            chain_id,
+            hex::encode(&address),
+            request.request_id
+        );
+        let _: () = con.json_set(request_key, "$", &request).await?;
+
+        // Index the pending requests by submission time.
+        let pending_key = format!("{}:0x{}:pending_requests", chain_id, hex::encode(&address));
+        let _: () = con
+            .zadd(pending_key, &request.request_id, request.submitted_at)
+            .await?;
+
+        // Log the submission for request budgets. Only the last day is kept, which is the longest
+        // budget window.
+        let submitted_key = format!(
+            "{}:0x{}:submitted_requests",
+            chain_id,
+            hex::encode(&address)
+        );
+        let _: () = con
+            .zadd(&submitted_key, &request.request_id, request.submitted_at)
+            .await?;
+        let _: () = con
+            .zrembyscore(
+                &submitted_key,
+                "-inf",
+                request.submitted_at.saturating_sub(24 * 60 * 60),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Gets the number of proof requests of the operator for the contract submitted at or after
+    /// the timestamp, within the last day.
+    pub async fn count_proof_requests_since(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        since: u64,
+    ) -> Result<u32, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let submitted_key = format!("{}:0x{}:submitted_requests", chain_id, hex::encode(address));
+        Ok(con.zcount(submitted_key, since, "+inf").await?)
+    }
+
+    /// Adds the gas fees paid by a relayed transaction of the operator for the contract, in gwei,
+    /// to its relay spend.
+    pub async fn add_relay_spend(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        transaction_hash: H256,
+        spend_gwei: u64,
+        mined_at: u64,
+    ) -> Result<(), Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        // The spend is part of the member, as the members of a sorted set are unique. Only the
+        // last day is kept, which is the relay spend budget's window.
+        let spend_key = format!("{}:0x{}:relay_spend", chain_id, hex::encode(address));
+        let _: () = con
+            .zadd(
+                &spend_key,
+                format!("{:?}:{}", transaction_hash, spend_gwei),
+                mined_at,
+            )
+            .await?;
+        let _: () = con
+            .zrembyscore(&spend_key, "-inf", mined_at.saturating_sub(24 * 60 * 60))
+            .await?;
+        Ok(())
+    }
+
+    /// Gets the gas fees paid by the relayed transactions of the operator for the contract mined at
+    /// or after the timestamp, within the last day, in gwei.
+    pub async fn get_relay_spend_since(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        since: u64,
+    ) -> Result<u64, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let spend_key = format!("{}:0x{}:relay_spend", chain_id, hex::encode(address));
+        let spends: Vec<String> = con.zrangebyscore(spend_key, since, "+inf").await?;
+        spends
+            .iter()
+            .map(|spend| -> Result<u64, Error> {
+                let (_, spend_gwei) = spend
+                    .rsplit_once(':')
+                    .ok_or_else(|| Error::msg(format!("Invalid relay spend {}", spend)))?;
+                Ok(spend_gwei.parse::<u64>()?)
+            })
+            .sum()
+    }
+
+    /// Gets the pending proof requests of the operator for the contract, oldest first.
+    pub async fn get_pending_proof_requests(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+    ) -> Result<Vec<StoredProofRequest>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let pending_key = format!("{}:0x{}:pending_requests", chain_id, hex::encode(&address));
+        let request_ids: Vec<String> = con.zrange(pending_key, 0, -1).await?;
+
+        let mut requests = Vec::new();
+        for request_id in request_ids {
+            let request_key = format!(
+                "{}:0x{}:request:{}",
+                chain_id,
+                hex::encode(&address),
+                request_id
+            );
+
+            // Result is always stored as serialized bytes: https://github.com/redis-rs/redis-rs#json-support.
+            let serialized_request: Option<Vec<u8>> = con.json_get(request_key, "$").await?;
+            if let Some(serialized_request) = serialized_request {
+                let request: Vec<StoredProofRequest> = serde_json::from_slice(&serialized_request)?;
+                requests.push(request[0].clone());
+            }
+        }
+        Ok(requests)
+    }
+
+    /// Marks a proof request as no longer pending. The request itself is kept for auditing.
+    pub async fn remove_pending_proof_request(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        request_id: &str,
+    ) -> Result<(), Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let pending_key = format!("{}:0x{}:pending_requests", chain_id, hex::encode(&address));
+        let _: () = con.zrem(pending_key, request_id).await?;
+        Ok(())
+    }
+
+    /// Gets the number of failed or timed out proof requests with the given parameters.
+    pub async fn get_failed_request_attempts(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        request_params: &str,
+    ) -> Result<u32, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!(
+            "{}:0x{}:failed_attempts:{}",
+            chain_id,
+            hex::encode(address),
+            request_params
+        );
+        let attempts: Option<u32> = con.get(key).await?;
+        Ok(attempts.unwrap_or(0))
+    }
+
+    /// Increments the number of failed or timed out proof requests with the given parameters. The
+    /// count expires FAILED_REQUEST_ATTEMPTS_TTL_SECS after the last failure, so a request that was
+    /// given up on is eventually retried.
+    pub async fn add_failed_request_attempt(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        request_params: &str,
+    ) -> Result<u32, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!(
+            "{}:0x{}:failed_attempts:{}",
+            chain_id,
+            hex::encode(address),
+            request_params
+        );
+        let attempts: u32 = con.incr(&key, 1).await?;
+        let _: () = con.expire(&key, FAILED_REQUEST_ATTEMPTS_TTL_SECS).await?;
+        Ok(attempts)
+    }
+
+    /// Clears the failed attempts of proof requests with the given parameters, e.g. once the
+    /// request is committed.
+    pub async fn clear_failed_request_attempts(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        request_params: &str,
+    ) -> Result<(), Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!(
+            "{}:0x{}:failed_attempts:{}",
+            chain_id,
+            hex::encode(address),
+            request_params
+        );
+        let _: () = con.del(key).await?;
+        Ok(())
+    }
+
+    /// Acquires the operator lease of the contract for the holder, or renews it if the holder
+    /// already holds it. Returns whether the holder holds the lease for the next ttl_ms
+    /// milliseconds. Fails if Redis cannot be reached, in which case the lease may be lost.
+    pub async fn acquire_operator_lease(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        holder: &str,
+        ttl_ms: u64,
+    ) -> Result<bool, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:operator_lease", chain_id, hex::encode(address));
+        // Checking the holder and setting the lease must be atomic, so two operators cannot both
+        // acquire an expired lease.
+        let script = redis::Script::new(
+            r"
+            local holder = redis.call('GET', KEYS[1])
+            if holder == false or holder == ARGV[1] then
+                redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+                return 1
+            end
+            return 0
+            ",
+        );
+        let acquired: i32 = script
+            .key(key)
+            .arg(holder)
+            .arg(ttl_ms)
+            .invoke_async(&mut con)
+            .await?;
+        Ok(acquired == 1)
+    }
+
+    /// Gets the current holder of the operator lease of the contract, if any.
+    pub async fn get_operator_lease_holder(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+    ) -> Result<Option<String>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:operator_lease", chain_id, hex::encode(address));
+        Ok(con.get(key).await?)
+    }
+
+    /// Releases the operator lease of the contract if the holder holds it, so another operator can
+    /// acquire it right away. Returns whether the lease was released.
+    pub async fn release_operator_lease(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+        holder: &str,
+    ) -> Result<bool, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:operator_lease", chain_id, hex::encode(address));
+        // Checking the holder and deleting the lease must be atomic, so a lease that another
+        // operator acquired after this one expired is not deleted.
+        let script = redis::Script::new(
+            r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+            end
+            return 0
+            ",
+        );
+        let released: i32 = script.key(key).arg(holder).invoke_async(&mut con).await?;
+        Ok(released == 1)
+    }
+
+    /// Gets all data commitment ranges stored for the contract, or an error if Redis cannot be
+    /// reached.
+    pub async fn get_data_commitment_ranges(
+        &mut self,
+        chain_id: u64,
+        address: Vec<u8>,
+    ) -> Result<Vec<DataCommitmentRange>, Error> {
+        let mut con = self.get_connection().await.map_err(Error::msg)?;
+
+        let key = format!("{}:0x{}:ranges", chain_id, hex::encode(address));
+
+        let ranges: Vec<String> = con.zrange(key, 0, -1).await?;
+
+        Ok(ranges
+            .iter()
+            .map(|range| decode_data_commitment_range(range))
+            .collect())
+    }
+}
+
+/// Splits the blocks after batch_start into the batch of its authority set, which ends at the epoch
+/// end block of the authority set, or at end_block if that is first or the epoch end block is not
+/// known yet (0). Returns the end of the batch and its ranges of at most MAX_NUM_HEADERS blocks.
+pub fn split_batch(
+    batch_start: u32,
+    epoch_end_block: u32,
+    end_block: u32,
+) -> (u32, Vec<(u32, u32)>) {
+    let batch_end = if epoch_end_block == 0 {
+        end_block
+    } else {
+        min(epoch_end_block, end_block)
+    };
+    let ranges = (batch_start..batch_end)
+        .step_by(MAX_NUM_HEADERS)
+        .map(|range_start| {
+            (
+                range_start,
+                min(range_start + MAX_NUM_HEADERS as u32, batch_end),
+            )
+        })
+        .collect();
+    (batch_end, ranges)
 }
 
 /// This function is useful for verifying that a Ed25519 signature is valid, it will panic if the signature is not valid
@@ -268,12 +1087,19 @@ impl RpcDataFetcher {
         dotenv::dotenv().ok();
 
         let url = env::var("AVAIL_URL").expect("AVAIL_URL must be set");
-        let client = build_client(url.as_str(), false).await.unwrap();
+        let avail_chain_id = env::var("AVAIL_CHAIN_ID").expect("AVAIL_CHAIN_ID must be set");
+        Self::with_avail(&url, &avail_chain_id).await
+    }
+
+    /// Creates a data fetcher for the given Avail RPC and chain id, instead of reading them from
+    /// AVAIL_URL and AVAIL_CHAIN_ID.
+    pub async fn with_avail(avail_url: &str, avail_chain_id: &str) -> Self {
+        let client = build_client(avail_url, false).await.unwrap();
         let redis_client = RedisClient::new().await;
         RpcDataFetcher {
             client: client.0,
-            avail_url: url,
-            avail_chain_id: env::var("AVAIL_CHAIN_ID").expect("AVAIL_CHAIN_ID must be set"),
+            avail_url: avail_url.to_string(),
+            avail_chain_id: avail_chain_id.to_string(),
             redis_client,
             save: None,
         }
@@ -303,7 +1129,10 @@ impl RpcDataFetcher {
             .await
             .expect("Failed to establish connection to Avail WS.");
 
-        let header = self.get_header(block).await;
+        let header = self
+            .get_header(block)
+            .await
+            .expect("Failed to get header from Avail.");
         let data_root = header.data_root().0.to_vec();
         println!("data_root {:?}", data_root);
 
@@ -345,13 +1174,19 @@ impl RpcDataFetcher {
         info!("Found {} blocks in Redis.", redis_blocks.len());
 
         // Query the chain for all era end blocks in the range [start_block, end_block].
-        let start_era = self.get_authority_set_id(start_block - 1).await;
+        let start_era = self
+            .get_authority_set_id(start_block - 1)
+            .await
+            .expect("Failed to get the authority set id from Avail.");
 
         let mut curr_block = start_block;
         let mut curr_era = start_era;
         let mut epoch_end_blocks = Vec::new();
         while curr_block < end_block {
-            let epoch_end_block = self.last_justified_block(curr_era).await;
+            let epoch_end_block = self
+                .last_justified_block(curr_era)
+                .await
+                .expect("Failed to find the last justified block on Avail.");
             if epoch_end_block == 0 {
                 // This era is currently active, so there are no epoch end blocks.
                 break;
@@ -376,20 +1211,22 @@ impl RpcDataFetcher {
 
     // This function returns the last block justified by target_authority_set_id. This block
     // also specifies the new authority set, which starts justifying after this block.
-    // Returns 0 if curr_authority_set_id <= target_authority_set_id.
-    pub async fn last_justified_block(&mut self, target_authority_set_id: u64) -> u32 {
-        self.refresh_ws_connection()
-            .await
-            .expect("Failed to establish connection to Avail WS.");
+    // Returns 0 if curr_authority_set_id <= target_authority_set_id. Errors if the Avail node
+    // cannot be reached.
+    pub async fn last_justified_block(
+        &mut self,
+        target_authority_set_id: u64,
+    ) -> Result<u32, Error> {
+        self.refresh_ws_connection().await.map_err(Error::msg)?;
 
         let mut low = 0;
-        let head_block = self.get_head().await;
+        let head_block = self.get_head().await?;
         let mut high = head_block.number;
         let mut epoch_end_block_number = 0;
 
         while low <= high {
             let mid = (low + high) / 2;
-            let mid_authority_set_id = self.get_authority_set_id(mid).await;
+            let mid_authority_set_id = self.get_authority_set_id(mid).await?;
 
             match mid_authority_set_id.cmp(&(target_authority_set_id + 1)) {
                 Ordering::Equal => {
@@ -398,7 +1235,7 @@ impl RpcDataFetcher {
                         epoch_end_block_number = mid;
                         break;
                     }
-                    let prev_authority_set_id = self.get_authority_set_id(mid - 1).await;
+                    let prev_authority_set_id = self.get_authority_set_id(mid - 1).await?;
                     if prev_authority_set_id == target_authority_set_id {
                         epoch_end_block_number = mid;
                         break;
@@ -410,16 +1247,22 @@ impl RpcDataFetcher {
                 Ordering::Greater => high = mid - 1,
             }
         }
-        epoch_end_block_number
+        Ok(epoch_end_block_number)
     }
 
-    pub async fn get_block_hash(&self, block_number: u32) -> H256 {
-        let block_hash = self
-            .client
+    /// Gets the hash of a block by number, or an error if the Avail node cannot be reached or does
+    /// not know the block.
+    pub async fn get_block_hash(&self, block_number: u32) -> Result<H256, Error> {
+        self.client
             .rpc()
             .block_hash(Some(block_number.into()))
-            .await;
-        block_hash.unwrap().unwrap()
+            .await?
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "Block {} is unknown to the Avail node",
+                    block_number
+                ))
+            })
     }
 
     // Computes the simple Merkle root of the leaves.
@@ -451,6 +1294,48 @@ impl RpcDataFetcher {
         nodes[0].clone()
     }
 
+    // Computes the layers of the simple Merkle tree of the leaves, ordered from the leaves to the
+    // root. The leaves are extended with 0s to the next power of 2, as in get_merkle_root.
+    pub fn get_merkle_layers(leaves: Vec<Vec<u8>>) -> Vec<Vec<Vec<u8>>> {
+        // Extend leaves to a power of 2.
+        let mut leaves = leaves;
+        while leaves.len().count_ones() != 1 {
+            leaves.push([0u8; 32].to_vec());
+        }
+
+        let mut layers = vec![leaves];
+        while layers[layers.len() - 1].len() > 1 {
+            let nodes = &layers[layers.len() - 1];
+            let parents = (0..nodes.len() / 2)
+                .map(|i| {
+                    let mut hasher = Sha256::new();
+                    hasher.update(&nodes[2 * i]);
+                    hasher.update(&nodes[2 * i + 1]);
+                    hasher.finalize().to_vec()
+                })
+                .collect();
+            layers.push(parents);
+        }
+
+        layers
+    }
+
+    // Reads the Merkle branch of the leaf at index from the layers of a tree built by
+    // get_merkle_layers, ordered from the leaf to the root.
+    pub fn get_merkle_branch_from_layers(layers: &[Vec<Vec<u8>>], index: usize) -> Vec<Vec<u8>> {
+        layers[..layers.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(height, nodes)| nodes[(index >> height) ^ 1].clone())
+            .collect()
+    }
+
+    // Computes the Merkle branch of the leaf at index, ordered from the leaf to the root. The
+    // leaves are extended with 0s to the next power of 2, as in get_merkle_root.
+    pub fn get_merkle_branch(leaves: Vec<Vec<u8>>, index: usize) -> Vec<Vec<u8>> {
+        Self::get_merkle_branch_from_layers(&Self::get_merkle_layers(leaves), index)
+    }
+
     /// Get the state root commitment and data root commitment for the range [start_block + 1, end_block].
     /// Returns a tuple of the state root commitment and data root commitment.
     pub async fn get_merkle_root_commitments(
@@ -465,8 +1350,114 @@ impl RpcDataFetcher {
         // Uses the simple merkle tree implementation, which defaults to 256 leaves in Avail.
         let headers = self
             .get_block_headers_range(start_block + 1, end_block)
-            .await;
+            .await
+            .expect("Failed to get headers from Avail.");
+
+        Self::compute_merkle_root_commitments(&headers)
+    }
+
+    /// Fetches the headers of (start_block, end_block] and checks that they chain from the hash of
+    /// start_block to the hash of end_block, before computing the range's commitments. Returns the
+    /// hash of end_block, and the state root and data root commitments.
+    pub async fn get_verified_merkle_root_commitments(
+        &mut self,
+        start_block: u32,
+        end_block: u32,
+    ) -> Result<(H256, Vec<u8>, Vec<u8>), Error> {
+        if end_block <= start_block || (end_block - start_block) as usize > MAX_NUM_HEADERS {
+            return Err(Error::msg(format!(
+                "Invalid range ({}, {}]",
+                start_block, end_block
+            )));
+        }
 
+        let headers = self
+            .get_block_headers_range(start_block + 1, end_block)
+            .await?;
+        let mut parent_hash = self.get_block_hash(start_block).await?;
+        for header in headers.iter() {
+            if header.parent_hash.0 != parent_hash.0 {
+                return Err(Error::msg(format!(
+                    "Header of block {} does not chain from its parent",
+                    header.number
+                )));
+            }
+            parent_hash = H256::from(header.hash().0);
+        }
+        let end_block_hash = self.get_block_hash(end_block).await?;
+        if parent_hash != end_block_hash {
+            return Err(Error::msg(format!(
+                "Headers of ({}, {}] do not chain to the hash of block {}",
+                start_block, end_block, end_block
+            )));
+        }
+
+        let (state_root_commitment, data_root_commitment) =
+            Self::compute_merkle_root_commitments(&headers);
+        Ok((end_block_hash, state_root_commitment, data_root_commitment))
+    }
+
+    /// Gets the header hashes and commitments of the blocks after start_block signed by its
+    /// authority set, up to the set's epoch end block, or end_block if that is first or the set has
+    /// not ended. The blocks are split into ranges of at most MAX_NUM_HEADERS blocks, each checked
+    /// to chain from its start block to its end block, with the authority set at the end of the
+    /// batch. This is the data posted by the guardian with one updateBlockRangeData call.
+    pub async fn get_block_range_batch(
+        &mut self,
+        start_block: u32,
+        end_block: u32,
+    ) -> Result<BlockRangeData, Error> {
+        let authority_set_id = self.get_authority_set_id(start_block).await?;
+        let epoch_end_block = self.last_justified_block(authority_set_id).await?;
+        let (batch_end, range_bounds) = split_batch(start_block, epoch_end_block, end_block);
+        if range_bounds.is_empty() {
+            return Err(Error::msg(format!(
+                "No blocks to fill from block {} to {}",
+                start_block, end_block
+            )));
+        }
+
+        let mut batch = BlockRangeData {
+            start_blocks: Vec::new(),
+            end_blocks: Vec::new(),
+            header_hashes: Vec::new(),
+            data_root_commitments: Vec::new(),
+            state_root_commitments: Vec::new(),
+            end_authority_set_id: self.get_authority_set_id(batch_end).await?,
+            end_authority_set_hash: [0u8; 32],
+        };
+        for (range_start, range_end) in range_bounds {
+            let (header_hash, state_root_commitment, data_root_commitment) = self
+                .get_verified_merkle_root_commitments(range_start, range_end)
+                .await
+                .map_err(|e| {
+                    e.context(format!(
+                        "Check of range ({}, {}] failed",
+                        range_start, range_end
+                    ))
+                })?;
+            info!(
+                "Checked range ({}, {}] of authority set {}.",
+                range_start, range_end, authority_set_id
+            );
+            batch.start_blocks.push(range_start);
+            batch.end_blocks.push(range_end);
+            batch.header_hashes.push(header_hash.0);
+            batch
+                .data_root_commitments
+                .push(H256::from_slice(&data_root_commitment).0);
+            batch
+                .state_root_commitments
+                .push(H256::from_slice(&state_root_commitment).0);
+        }
+        batch.end_authority_set_hash = self.compute_authority_set_hash(batch_end).await.0;
+        Ok(batch)
+    }
+
+    /// Computes the state root commitment and data root commitment from the headers of the range
+    /// [start_block + 1, end_block]. Returns a tuple of the state root commitment and data root
+    /// commitment.
+    pub fn compute_merkle_root_commitments(headers: &[Header]) -> (Vec<u8>, Vec<u8>) {
         let mut data_root_leaves = Vec::new();
         let mut state_root_leaves = Vec::new();
         for i in 0..headers.len() {
@@ -486,15 +1477,52 @@ impl RpcDataFetcher {
         )
     }
 
-    // This function returns a vector of headers for a given range of block numbers, inclusive of the start and end block numbers.
+    /// Computes the data root Merkle proof of every block in the range [start_block + 1, end_block]
+    /// from the headers of the range. The proofs are against the data root commitment of the range,
+    /// which uses the same 256 leaf tree as compute_merkle_root_commitments.
+    pub fn compute_data_root_proofs(
+        start_block: u32,
+        end_block: u32,
+        headers: &[Header],
+    ) -> Vec<StoredDataRootProof> {
+        let mut data_root_leaves = headers
+            .iter()
+            .map(|header| header.data_root().0.to_vec())
+            .collect::<Vec<_>>();
+        data_root_leaves.resize(MAX_NUM_HEADERS, [0u8; 32].to_vec());
+
+        // Build the tree once and read the branch of every leaf from its layers.
+        let layers = Self::get_merkle_layers(data_root_leaves);
+        let data_commitment = layers[layers.len() - 1][0].clone();
+        let range_hash = compute_range_hash(start_block, end_block);
+
+        headers
+            .iter()
+            .enumerate()
+            .map(|(index, header)| StoredDataRootProof {
+                block_number: header.number,
+                range_hash,
+                data_commitment: H256::from_slice(&data_commitment),
+                merkle_branch: Self::get_merkle_branch_from_layers(&layers, index)
+                    .iter()
+                    .map(|node| H256::from_slice(node))
+                    .collect(),
+                index,
+                total_leaves: MAX_NUM_HEADERS,
+                data_root: H256::from(header.data_root().0),
+                block_hash: H256::from(header.hash().0),
+            })
+            .collect()
+    }
+
+    /// Gets the headers of the blocks in the range [start_block_number, end_block_number], or an
+    /// error if the Avail node cannot be reached or does not know one of the blocks.
     pub async fn get_block_headers_range(
         &mut self,
         start_block_number: u32,
         end_block_number: u32,
-    ) -> Vec<Header> {
-        self.refresh_ws_connection()
-            .await
-            .expect("Failed to establish connection to Avail WS.");
+    ) -> Result<Vec<Header>, Error> {
+        self.refresh_ws_connection().await.map_err(Error::msg)?;
 
         // Fetch the headers in batches of MAX_CONCURRENT_WS_REQUESTS. The WS connection will error if there
         // are too many concurrent requests with Rpc(ClientError(MaxSlotsExceeded)).
@@ -511,46 +1539,90 @@ impl RpcDataFetcher {
                 .collect();
 
             // Await all futures concurrently
-            let headers_batch: Vec<Header> = join_all(header_futures)
+            let headers_batch = join_all(header_futures)
                 .await
                 .into_iter()
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>, _>>()?;
 
             headers.extend_from_slice(&headers_batch);
             curr_block += MAX_CONCURRENT_WS_REQUESTS as u32;
         }
-        headers
+        Ok(headers)
     }
 
-    pub async fn get_header(&self, block_number: u32) -> Header {
-        let block_hash = self.get_block_hash(block_number).await;
-        let header_result = self.client.rpc().header(Some(block_hash)).await;
-        header_result.unwrap().unwrap()
+    /// Gets the header of a block by number, or an error if the Avail node cannot be reached or
+    /// does not know the block.
+    pub async fn get_header(&self, block_number: u32) -> Result<Header, Error> {
+        let block_hash = self.get_block_hash(block_number).await?;
+        self.client
+            .rpc()
+            .header(Some(block_hash))
+            .await?
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "Block {} is unknown to the Avail node",
+                    block_number
+                ))
+            })
     }
 
-    pub async fn get_head(&mut self) -> Header {
-        self.refresh_ws_connection()
-            .await
-            .expect("Failed to establish connection to Avail WS.");
-        let head_block_hash = self.client.rpc().finalized_head().await.unwrap();
-        let header = self.client.rpc().header(Some(head_block_hash)).await;
-        header.unwrap().unwrap()
+    /// Gets the header of a block by hash, or None if the block is unknown to the Avail node. Errors
+    /// if the Avail node cannot be reached.
+    pub async fn get_header_by_hash(&mut self, block_hash: H256) -> Result<Option<Header>, Error> {
+        self.refresh_ws_connection().await.map_err(Error::msg)?;
+        Ok(self.client.rpc().header(Some(block_hash)).await?)
     }
 
-    pub async fn get_authority_set_id(&mut self, block_number: u32) -> u64 {
-        self.refresh_ws_connection()
-            .await
-            .expect("Failed to establish connection to Avail WS.");
-        let block_hash = self.get_block_hash(block_number).await;
+    /// Gets the header of the finalized head, or an error if the Avail node cannot be reached.
+    pub async fn get_head(&mut self) -> Result<Header, Error> {
+        self.refresh_ws_connection().await.map_err(Error::msg)?;
+        let head_block_hash = self.client.rpc().finalized_head().await?;
+        self.client
+            .rpc()
+            .header(Some(head_block_hash))
+            .await?
+            .ok_or_else(|| Error::msg("The finalized head is unknown to the Avail node"))
+    }
+
+    /// Subscribes to the finalized heads of the Avail chain. The number of each newly finalized
+    /// block is sent to the receiver, until the subscription ends or the receiver is dropped.
+    pub async fn subscribe_finalized_heads(&mut self) -> Result<mpsc::Receiver<u32>, Error> {
+        self.refresh_ws_connection().await.map_err(Error::msg)?;
+        let mut subscription = self
+            .client
+            .rpc()
+            .subscribe_finalized_block_headers()
+            .await?;
+
+        let (sender, receiver) = mpsc::channel(64);
+        tokio::spawn(async move {
+            while let Some(Ok(header)) = subscription.next().await {
+                if sender.send(header.number).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// Gets the authority set id at a block, or an error if the Avail node cannot be reached or
+    /// does not know the block.
+    pub async fn get_authority_set_id(&mut self, block_number: u32) -> Result<u64, Error> {
+        self.refresh_ws_connection().await.map_err(Error::msg)?;
+        let block_hash = self.get_block_hash(block_number).await?;
 
         let set_id_key = api::storage().grandpa().current_set_id();
         self.client
             .storage()
             .at(block_hash)
             .fetch(&set_id_key)
-            .await
-            .unwrap()
-            .unwrap()
+            .await?
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "No authority set id is stored at block {}",
+                    block_number
+                ))
+            })
     }
 
     // This function returns the authorities (as AffinePoint and public key bytes) for a given block number
@@ -560,7 +1632,10 @@ impl RpcDataFetcher {
             .await
             .expect("Failed to establish connection to Avail WS.");
 
-        let block_hash = self.get_block_hash(block_number).await;
+        let block_hash = self
+            .get_block_hash(block_number)
+            .await
+            .expect("Failed to get the block hash from Avail.");
 
         let grandpa_authorities_bytes = self
             .client
@@ -612,6 +1687,30 @@ impl RpcDataFetcher {
         authorities
     }
 
+    /// Gets the number of authorities in the authority set that validates the block after the given
+    /// block number.
+    pub async fn get_authority_set_size(&mut self, block_number: u32) -> usize {
+        self.get_authorities(block_number).await.len()
+    }
+
+    /// Finds the first block in [start_block_number, end_block_number] whose encoded header is
+    /// larger than MAX_HEADER_SIZE, the largest header the circuits can process. Returns the block
+    /// and the size of its header.
+    pub async fn find_oversized_header(
+        &mut self,
+        start_block_number: u32,
+        end_block_number: u32,
+    ) -> Option<(u32, usize)> {
+        let headers = self
+            .get_block_headers_range(start_block_number, end_block_number)
+            .await
+            .expect("Failed to get headers from Avail.");
+        headers
+            .iter()
+            .map(|header| (header.number, header.encode().len()))
+            .find(|(_, header_size)| *header_size > MAX_HEADER_SIZE)
+    }
+
     // Computes the authority_set_hash for a given block number. Note: This is the authority set hash
     // that validates the next block after the given block number.
     pub async fn compute_authority_set_hash(&mut self, block_number: u32) -> H256 {
@@ -639,8 +1738,8 @@ impl RpcDataFetcher {
         // Note: grandpa_proveFinality will serve the proof for the last justified block in an epoch.
         // get_simple_justification should fail for any block that is not the last justified block
         // in an epoch.
-        let curr_authority_set_id = self.get_authority_set_id(block_number).await;
-        let prev_authority_set_id = self.get_authority_set_id(block_number - 1).await;
+        let curr_authority_set_id = self.get_authority_set_id(block_number).await?;
+        let prev_authority_set_id = self.get_authority_set_id(block_number - 1).await?;
 
         // If epoch end block, use grandpa_proveFinality to get the justification.
         if curr_authority_set_id == prev_authority_set_id + 1 {
@@ -660,7 +1759,7 @@ impl RpcDataFetcher {
                 Decode::decode(&mut finality_proof.justification.as_slice()).unwrap();
 
             // The authority set id for the current block is defined in the previous block.
-            let authority_set_id = self.get_authority_set_id(block_number - 1).await;
+            let authority_set_id = self.get_authority_set_id(block_number - 1).await?;
 
             // The authorities for the current block are defined in the previous block.
             let authorities_pubkey_bytes = self.get_authorities(block_number - 1).await;
@@ -768,7 +1867,7 @@ impl RpcDataFetcher {
             .get_justification_data::<VALIDATOR_SET_SIZE_MAX>(block_number)
             .await?;
 
-        let current_authority_set_id = self.get_authority_set_id(block_number - 1).await;
+        let current_authority_set_id = self.get_authority_set_id(block_number - 1).await?;
         let current_authority_set_hash = compute_authority_set_hash(&data.pubkeys);
 
         if data.voting_weight * 3 < data.num_authorities * 2 {
@@ -814,11 +1913,20 @@ impl RpcDataFetcher {
         epoch_end_block: u32,
     ) -> HeaderRotateData {
         // Assert epoch_end_block is a valid epoch end block.
-        let epoch_end_block_authority_set_id = self.get_authority_set_id(epoch_end_block).await;
-        let prev_authority_set_id = self.get_authority_set_id(epoch_end_block - 1).await;
+        let epoch_end_block_authority_set_id = self
+            .get_authority_set_id(epoch_end_block)
+            .await
+            .expect("Failed to get the authority set id from Avail.");
+        let prev_authority_set_id = self
+            .get_authority_set_id(epoch_end_block - 1)
+            .await
+            .expect("Failed to get the authority set id from Avail.");
         assert_eq!(epoch_end_block_authority_set_id - 1, prev_authority_set_id);
 
-        let header = self.get_header(epoch_end_block).await;
+        let header = self
+            .get_header(epoch_end_block)
+            .await
+            .expect("Failed to get header from Avail.");
 
         let mut header_bytes = header.encode();
         let header_size = header_bytes.len();
@@ -938,11 +2046,78 @@ mod tests {
     use super::*;
     use crate::consts::{MAX_AUTHORITY_SET_SIZE, MAX_HEADER_SIZE};
 
+    #[test]
+    fn test_get_merkle_branch() {
+        let leaves = (0..5u8).map(|i| vec![i; 32]).collect::<Vec<_>>();
+        let root = RpcDataFetcher::get_merkle_root(leaves.clone());
+
+        for index in 0..leaves.len() {
+            let branch = RpcDataFetcher::get_merkle_branch(leaves.clone(), index);
+            assert_eq!(branch.len(), 3);
+
+            // Recompute the root from the leaf and its branch.
+            let mut node = leaves[index].clone();
+            let mut position = index;
+            for sibling in branch.iter() {
+                let mut hasher = Sha256::new();
+                if position % 2 == 0 {
+                    hasher.update(&node);
+                    hasher.update(sibling);
+                } else {
+                    hasher.update(sibling);
+                    hasher.update(&node);
+                }
+                node = hasher.finalize().to_vec();
+                position /= 2;
+            }
+            assert_eq!(node, root);
+        }
+    }
+
+    #[test]
+    fn test_split_batch_at_epoch_end() {
+        // The batch ends at the epoch end block of its authority set.
+        let (batch_end, ranges) = split_batch(1000, 1300, 2000);
+        assert_eq!(batch_end, 1300);
+        assert_eq!(ranges, vec![(1000, 1256), (1256, 1300)]);
+
+        // The next batch starts at the epoch end block, and its authority set has not ended.
+        let (batch_end, ranges) = split_batch(1300, 0, 2000);
+        assert_eq!(batch_end, 2000);
+        assert_eq!(ranges.first(), Some(&(1300, 1556)));
+        assert_eq!(ranges.last(), Some(&(1812, 2000)));
+        assert!(ranges
+            .iter()
+            .all(|(start, end)| end - start <= MAX_NUM_HEADERS as u32));
+
+        // An epoch end block after the end block does not split the fill.
+        let (batch_end, ranges) = split_batch(1000, 3000, 1200);
+        assert_eq!(batch_end, 1200);
+        assert_eq!(ranges, vec![(1000, 1200)]);
+    }
+
+    #[test]
+    fn test_timeline_score() {
+        // Entries without a log index are ordered after the logs of their block, and before the
+        // next block.
+        assert!(timeline_score(10, Some(500), None) < timeline_score(10, None, Some(0)));
+        assert!(timeline_score(10, None, Some(1)) < timeline_score(11, Some(0), None));
+
+        // Entries detected at the same block have distinct scores.
+        assert_ne!(
+            timeline_score(10, None, Some(0)),
+            timeline_score(10, None, Some(1))
+        );
+    }
+
     #[tokio::test]
     #[cfg_attr(feature = "ci", ignore)]
     async fn test_get_block_headers_range() {
         let mut fetcher = RpcDataFetcher::new().await;
-        let _ = fetcher.get_block_headers_range(100000, 100256).await;
+        let _ = fetcher
+            .get_block_headers_range(100000, 100256)
+            .await
+            .unwrap();
 
         let (_, data_root_commitment) = fetcher.get_merkle_root_commitments(441000, 441001).await;
 
@@ -959,9 +2134,12 @@ mod tests {
         let mut fetcher = RpcDataFetcher::new().await;
 
         let target_block = 529000;
-        let header = fetcher.get_header(target_block).await;
+        let header = fetcher.get_header(target_block).await.unwrap();
         // println!("header has hash {:?}", header.hash());
-        let authority_set_id = fetcher.get_authority_set_id(target_block - 1).await;
+        let authority_set_id = fetcher
+            .get_authority_set_id(target_block - 1)
+            .await
+            .unwrap();
         let authority_set_hash = fetcher.compute_authority_set_hash(target_block - 1).await;
 
         // let _ = fetcher.get_block_hash(target_block).await;
@@ -983,15 +2161,21 @@ mod tests {
         let mut block: u32 = 215000;
 
         loop {
-            let authority_set_id = fetcher.get_authority_set_id(block).await;
+            let authority_set_id = fetcher.get_authority_set_id(block).await.unwrap();
             println!("authority_set_id {:?}", authority_set_id);
 
-            let prev_epoch_end_block = fetcher.last_justified_block(authority_set_id - 1).await;
+            let prev_epoch_end_block = fetcher
+                .last_justified_block(authority_set_id - 1)
+                .await
+                .unwrap();
             println!("prev end block {:?}", prev_epoch_end_block);
             // The current authorities are defined in the last block of the previous epoch.
             let curr_authorities = fetcher.get_authorities(prev_epoch_end_block).await;
 
-            let epoch_end_block = fetcher.last_justified_block(authority_set_id).await;
+            let epoch_end_block = fetcher
+                .last_justified_block(authority_set_id)
+                .await
+                .unwrap();
             println!("curr end block {:?}", epoch_end_block);
             // The next authority set is defined by the last block of the current epoch.
             let next_authorities = fetcher.get_authorities(epoch_end_block).await;
@@ -1006,7 +2190,7 @@ mod tests {
                     "genesis block (last block justified by genesis id) {:?}",
                     epoch_end_block
                 );
-                let genesis_header = fetcher.get_header(epoch_end_block).await;
+                let genesis_header = fetcher.get_header(epoch_end_block).await.unwrap();
                 println!("genesis header {:?}", hex::encode(genesis_header.hash().0));
 
                 break;
@@ -1023,9 +2207,9 @@ mod tests {
         // This is an block in the middle of an era.
         let block = 645570;
 
-        let authority_set_id = fetcher.get_authority_set_id(block - 1).await;
+        let authority_set_id = fetcher.get_authority_set_id(block - 1).await.unwrap();
         let authority_set_hash = fetcher.compute_authority_set_hash(block - 1).await;
-        let header = fetcher.get_header(block).await;
+        let header = fetcher.get_header(block).await.unwrap();
         let header_hash = header.hash();
 
         println!("authority_set_id {:?}", authority_set_id);
@@ -1047,7 +2231,10 @@ mod tests {
         // target_authority_set_id. This block also specifies the new authority set,
         // target_authority_set_id + 1.
         let target_authority_set_id = 513;
-        let epoch_end_block_number = fetcher.last_justified_block(target_authority_set_id).await;
+        let epoch_end_block_number = fetcher
+            .last_justified_block(target_authority_set_id)
+            .await
+            .unwrap();
 
         // Verify that this is an epoch end block.
         assert_ne!(epoch_end_block_number, 0);
@@ -1055,8 +2242,12 @@ mod tests {
 
         let previous_authority_set_id = fetcher
             .get_authority_set_id(epoch_end_block_number - 1)
-            .await;
-        let authority_set_id = fetcher.get_authority_set_id(epoch_end_block_number).await;
+            .await
+            .unwrap();
+        let authority_set_id = fetcher
+            .get_authority_set_id(epoch_end_block_number)
+            .await
+            .unwrap();
 
         // Verify this is an epoch end block.
         assert_eq!(previous_authority_set_id + 1, authority_set_id);
@@ -1077,16 +2268,25 @@ mod tests {
         let mut fetcher = RpcDataFetcher::new().await;
 
         let block_number = 642000;
-        let authority_set_id = fetcher.get_authority_set_id(block_number - 1).await;
+        let authority_set_id = fetcher
+            .get_authority_set_id(block_number - 1)
+            .await
+            .unwrap();
 
-        let last_justified_block = fetcher.last_justified_block(authority_set_id).await;
+        let last_justified_block = fetcher
+            .last_justified_block(authority_set_id)
+            .await
+            .unwrap();
 
-        let header = fetcher.get_header(last_justified_block).await;
+        let header = fetcher.get_header(last_justified_block).await.unwrap();
         println!("header hash {:?}", hex::encode(header.hash().0));
         let authority_set_hash = fetcher.compute_authority_set_hash(block_number - 1).await;
         println!("authority set hash {:?}", hex::encode(authority_set_hash.0));
 
-        let new_authority_set_id = fetcher.get_authority_set_id(last_justified_block).await;
+        let new_authority_set_id = fetcher
+            .get_authority_set_id(last_justified_block)
+            .await
+            .unwrap();
 
         println!(
             "last justified block from authority set {:?} is: {:?}",
@@ -1110,7 +2310,10 @@ mod tests {
         let justification: GrandpaJustification =
             Decode::decode(&mut finality_proof.justification.as_slice()).unwrap();
 
-        let authority_set_id = fetcher.get_authority_set_id(block_number - 1).await;
+        let authority_set_id = fetcher
+            .get_authority_set_id(block_number - 1)
+            .await
+            .unwrap();
 
         // Form a message which is signed in the justification.
         let signed_message = Encode::encode(&(
@@ -1129,9 +2332,9 @@ mod tests {
     async fn test_query_redis_block_range() {
         let mut data_fetcher = RpcDataFetcher::new().await;
 
-        let prev_last_justified_block = data_fetcher.last_justified_block(615).await;
+        let prev_last_justified_block = data_fetcher.last_justified_block(615).await.unwrap();
         println!("prev_last_justified_block {:?}", prev_last_justified_block);
-        let last_justified_block = data_fetcher.last_justified_block(616).await;
+        let last_justified_block = data_fetcher.last_justified_block(616).await.unwrap();
         println!("last_justified_block {:?}", last_justified_block);
         let blocks = data_fetcher
             .find_justifications_in_range(prev_last_justified_block, last_justified_block)
@@ -1151,7 +2354,10 @@ mod tests {
         // let head = data_fetcher.get_head().await.number;
         let mut start_epoch = 179;
         loop {
-            let epoch_end_block = data_fetcher.last_justified_block(start_epoch).await;
+            let epoch_end_block = data_fetcher
+                .last_justified_block(start_epoch)
+                .await
+                .unwrap();
             if epoch_end_block == 0 {
                 break;
             }
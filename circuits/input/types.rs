@@ -31,6 +31,105 @@ pub struct StoredJustificationData {
     pub num_authorities: usize,
 }
 
+// A single precommit signed by an authority, with the message it signed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredPrecommit {
+    pub target_hash: Vec<u8>,
+    pub target_number: u32,
+    pub signed_message: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+// Two conflicting precommits signed by the same authority in the same round and authority set.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredEquivocation {
+    pub authority_set_id: u64,
+    pub round: u64,
+    pub pubkey: Vec<u8>,
+    pub first: StoredPrecommit,
+    pub second: StoredPrecommit,
+}
+
+// A data commitment range whose on-chain commitments do not match the commitments recomputed from
+// the Avail headers in the range.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredCommitmentMismatch {
+    pub start_block: u32,
+    pub end_block: u32,
+    pub data_commitment: Vec<u8>,
+    pub expected_data_commitment: Vec<u8>,
+    pub state_commitment: Vec<u8>,
+    pub expected_state_commitment: Vec<u8>,
+}
+
+// The Merkle proof of a block's data root against the data commitment of the range it is in.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredDataRootProof {
+    pub block_number: u32,
+    pub range_hash: H256,
+    pub data_commitment: H256,
+    pub merkle_branch: Vec<H256>,
+    pub index: usize,
+    pub total_leaves: usize,
+    pub data_root: H256,
+    pub block_hash: H256,
+}
+
+// An entry in the timeline of a VectorX contract. Either a decoded contract event, or a guardian
+// intervention that emits no event of its own, detected from the call data of its transaction or
+// from the contract state. Entries detected from the contract state have no log index, and are
+// ordered among the entries detected at the same block by their entry index instead.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredContractEvent {
+    pub block_number: u64,
+    pub log_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_index: Option<u64>,
+    pub transaction_hash: Option<H256>,
+    pub name: String,
+    pub data: serde_json::Value,
+}
+
+// The contract state last observed by the events service, used to detect guardian interventions
+// that emit no events.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StoredContractState {
+    pub frozen: bool,
+    pub latest_block: u32,
+}
+
+// A proof request submitted by the operator that has not completed yet.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredProofRequest {
+    pub request_id: String,
+    // Either header_range or rotate.
+    pub request_type: String,
+    // For header ranges, the trusted block and the target block.
+    pub trusted_block: u32,
+    pub target_block: u32,
+    // For header ranges, the authority set id of the range. For rotates, the current authority set
+    // id.
+    pub authority_set_id: u64,
+    // Unix timestamp in seconds.
+    pub submitted_at: u64,
+    // Number of requests submitted with the same parameters, including this one.
+    pub attempt: u32,
+}
+
+// The data posted by the guardian with updateBlockRangeData to fill a block range without proofs.
+pub struct BlockRangeData {
+    pub start_blocks: Vec<u32>,
+    pub end_blocks: Vec<u32>,
+    pub header_hashes: Vec<[u8; 32]>,
+    pub data_root_commitments: Vec<[u8; 32]>,
+    pub state_root_commitments: Vec<[u8; 32]>,
+    pub end_authority_set_id: u64,
+    pub end_authority_set_hash: [u8; 32],
+}
+
 #[derive(Debug)]
 pub struct CircuitJustification {
     pub authority_set_id: u64,
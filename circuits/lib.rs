@@ -1,11 +1,17 @@
 #![allow(clippy::needless_range_loop)]
 #![allow(clippy::too_many_arguments)]
 
+pub mod alert;
+pub mod analytics;
 pub mod builder;
 pub mod consts;
 pub mod dummy_header_range;
 pub mod dummy_rotate;
 pub mod header_range;
 pub mod input;
+pub mod metrics;
+pub mod monitor;
+pub mod prover;
+pub mod relay;
 pub mod rotate;
 pub mod vars;
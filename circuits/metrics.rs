@@ -0,0 +1,255 @@
+use std::env;
+use std::net::SocketAddr;
+
+use axum::routing::get;
+use axum::Router;
+use log::{error, info};
+use prometheus::{CounterVec, Encoder, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+
+use crate::alert::Alerter;
+
+// Labels identifying the VectorX deployment of a metric.
+const DEPLOYMENT_LABELS: [&str; 2] = ["chain_id", "contract"];
+
+/// Prometheus metrics of the operator. Every metric is labeled with the chain id and address of the
+/// VectorX contract, so one registry can hold the metrics of several deployments.
+#[derive(Clone)]
+pub struct OperatorMetrics {
+    registry: Registry,
+    /// Number of blocks the contract's latestBlock is behind the Avail head.
+    pub blocks_behind_head: IntGaugeVec,
+    /// Estimated number of blocks until the epoch end block of the head's authority set.
+    pub blocks_to_epoch_end: IntGaugeVec,
+    /// Number of pending requests, by request type.
+    pub pending_requests: IntGaugeVec,
+    /// Number of failed requests, by request type and reason.
+    pub request_failures: IntCounterVec,
+    /// Number of requests deferred by a budget, by request type and reason.
+    pub deferred_requests: IntCounterVec,
+    /// Seconds since the operator last saw latestBlock advance.
+    pub seconds_since_last_commit: IntGaugeVec,
+    /// Number of failed RPC calls, by endpoint (ethereum, avail, redis or prover).
+    pub rpc_errors: IntCounterVec,
+    /// Gas fees paid by the relayed transactions, in gwei, by request type.
+    pub relay_spend_gwei: CounterVec,
+}
+
+fn labels(extra: &[&'static str]) -> Vec<&'static str> {
+    DEPLOYMENT_LABELS
+        .iter()
+        .chain(extra.iter())
+        .copied()
+        .collect()
+}
+
+impl OperatorMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let blocks_behind_head = IntGaugeVec::new(
+            Opts::new(
+                "vectorx_blocks_behind_head",
+                "Blocks between the contract's latestBlock and the Avail head.",
+            ),
+            &labels(&[]),
+        )
+        .unwrap();
+        let blocks_to_epoch_end = IntGaugeVec::new(
+            Opts::new(
+                "vectorx_blocks_to_epoch_end",
+                "Estimated blocks until the epoch end block of the head's authority set.",
+            ),
+            &labels(&[]),
+        )
+        .unwrap();
+        let pending_requests = IntGaugeVec::new(
+            Opts::new("vectorx_pending_requests", "Pending proof requests."),
+            &labels(&["request_type"]),
+        )
+        .unwrap();
+        let request_failures = IntCounterVec::new(
+            Opts::new(
+                "vectorx_request_failures_total",
+                "Failed proof requests, by reason.",
+            ),
+            &labels(&["request_type", "reason"]),
+        )
+        .unwrap();
+        let deferred_requests = IntCounterVec::new(
+            Opts::new(
+                "vectorx_deferred_requests_total",
+                "Requests deferred by a budget, by reason.",
+            ),
+            &labels(&["request_type", "reason"]),
+        )
+        .unwrap();
+        let seconds_since_last_commit = IntGaugeVec::new(
+            Opts::new(
+                "vectorx_seconds_since_last_commit",
+                "Seconds since latestBlock last advanced.",
+            ),
+            &labels(&[]),
+        )
+        .unwrap();
+        let rpc_errors = IntCounterVec::new(
+            Opts::new("vectorx_rpc_errors_total", "Failed RPC calls, by endpoint."),
+            &labels(&["endpoint"]),
+        )
+        .unwrap();
+        let relay_spend_gwei = CounterVec::new(
+            Opts::new(
+                "vectorx_relay_spend_gwei_total",
+                "Gas fees paid by relayed transactions, in gwei.",
+            ),
+            &labels(&["request_type"]),
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(blocks_behind_head.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(blocks_to_epoch_end.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(pending_requests.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(request_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(deferred_requests.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(seconds_since_last_commit.clone()))
+            .unwrap();
+        registry.register(Box::new(rpc_errors.clone())).unwrap();
+        registry
+            .register(Box::new(relay_spend_gwei.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            blocks_behind_head,
+            blocks_to_epoch_end,
+            pending_requests,
+            request_failures,
+            deferred_requests,
+            seconds_since_last_commit,
+            rpc_errors,
+            relay_spend_gwei,
+        }
+    }
+
+    /// Encodes the metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// Serves the metrics on `/metrics` at the port.
+    pub async fn serve(self, port: u16) {
+        let app = Router::new().route(
+            "/metrics",
+            get(move || {
+                let metrics = self.clone();
+                async move { metrics.encode() }
+            }),
+        );
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        info!("Serving metrics on {}.", addr);
+        if let Err(e) = axum::Server::bind(&addr)
+            .serve(app.into_make_service())
+            .await
+        {
+            error!("Metrics server failed: {}", e);
+        }
+    }
+}
+
+impl Default for OperatorMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Alerts once when a value goes above its threshold, and again only after it has recovered and
+/// crossed the threshold again.
+#[derive(Clone, Debug)]
+pub struct ThresholdAlert {
+    description: String,
+    threshold: Option<i64>,
+    firing: bool,
+}
+
+impl ThresholdAlert {
+    /// Reads the threshold from the environment variable. Without it, the alert never fires.
+    pub fn from_env(description: &str, env_var: &str) -> Self {
+        let mut threshold = None;
+        if let Ok(threshold_env) = env::var(env_var) {
+            threshold = Some(
+                threshold_env
+                    .parse::<i64>()
+                    .unwrap_or_else(|_| panic!("invalid {}", env_var)),
+            );
+        }
+        Self {
+            description: description.to_string(),
+            threshold,
+            firing: false,
+        }
+    }
+
+    /// Checks the value against the threshold, alerting when it is first exceeded and logging when
+    /// it recovers.
+    pub async fn check(&mut self, alerter: &Alerter, value: i64, context: &str) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+
+        if value > threshold && !self.firing {
+            self.firing = true;
+            alerter
+                .alert(&format!(
+                    "{} is {}, above the threshold of {}, {}.",
+                    self.description, value, threshold, context
+                ))
+                .await;
+        } else if value <= threshold && self.firing {
+            self.firing = false;
+            info!(
+                "{} is {}, back within the threshold of {}, {}.",
+                self.description, value, threshold, context
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_metrics() {
+        let metrics = OperatorMetrics::new();
+        metrics
+            .blocks_behind_head
+            .with_label_values(&["11155111", "0x00"])
+            .set(42);
+        metrics
+            .request_failures
+            .with_label_values(&["11155111", "0x00", "rotate", "timed_out"])
+            .inc();
+
+        let encoded = metrics.encode();
+        assert!(encoded
+            .contains("vectorx_blocks_behind_head{chain_id=\"11155111\",contract=\"0x00\"} 42"));
+        assert!(encoded.contains(
+            "vectorx_request_failures_total{chain_id=\"11155111\",contract=\"0x00\",reason=\"timed_out\",request_type=\"rotate\"} 1"
+        ));
+    }
+}
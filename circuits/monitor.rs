@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::input::types::{StoredEquivocation, StoredPrecommit};
+
+// Number of rounds (within an authority set) for which precommits are kept to detect equivocations.
+const ROUND_WINDOW: u64 = 64;
+
+/// Watches the GRANDPA justifications of an Avail chain for finality stalls and for authorities
+/// that sign two different targets in the same round and authority set.
+pub struct GrandpaMonitor {
+    stall_threshold: Duration,
+    last_finalized_block: u32,
+    last_finalized_at: Instant,
+    stall_alerted: bool,
+    // (authority set id, round, pubkey) -> first precommit seen from that authority.
+    precommits: HashMap<(u64, u64, Vec<u8>), StoredPrecommit>,
+    // (authority set id, round, pubkey) of the equivocations that were already reported.
+    reported: HashSet<(u64, u64, Vec<u8>)>,
+    latest_round: (u64, u64),
+}
+
+impl GrandpaMonitor {
+    pub fn new(stall_threshold: Duration) -> Self {
+        GrandpaMonitor {
+            stall_threshold,
+            last_finalized_block: 0,
+            last_finalized_at: Instant::now(),
+            stall_alerted: false,
+            precommits: HashMap::new(),
+            reported: HashSet::new(),
+            latest_round: (0, 0),
+        }
+    }
+
+    /// Records a finalized block. Returns true if finality resumed after a reported stall.
+    pub fn observe_finalized(&mut self, block_number: u32) -> bool {
+        if block_number <= self.last_finalized_block {
+            return false;
+        }
+        self.last_finalized_block = block_number;
+        self.last_finalized_at = Instant::now();
+
+        let resumed = self.stall_alerted;
+        self.stall_alerted = false;
+        resumed
+    }
+
+    /// Returns the last finalized block and how long it has been finalized for if finality has not
+    /// moved for longer than the stall threshold. A stall is only reported once until finality
+    /// resumes.
+    pub fn check_stall(&mut self) -> Option<(u32, Duration)> {
+        let elapsed = self.last_finalized_at.elapsed();
+        if self.stall_alerted || elapsed < self.stall_threshold {
+            return None;
+        }
+        self.stall_alerted = true;
+        Some((self.last_finalized_block, elapsed))
+    }
+
+    /// Records a precommit with a valid signature. Returns the equivocation if the authority
+    /// already signed a different target in the same round and authority set. Each equivocation
+    /// is only reported once, however many conflicting precommits of the authority are seen.
+    pub fn observe_precommit(
+        &mut self,
+        authority_set_id: u64,
+        round: u64,
+        pubkey: Vec<u8>,
+        precommit: StoredPrecommit,
+    ) -> Option<StoredEquivocation> {
+        if (authority_set_id, round) > self.latest_round {
+            self.latest_round = (authority_set_id, round);
+            self.precommits.retain(|(set_id, r, _), _| {
+                *set_id == authority_set_id && *r + ROUND_WINDOW >= round
+            });
+            self.reported.retain(|(set_id, r, _)| {
+                *set_id == authority_set_id && *r + ROUND_WINDOW >= round
+            });
+        }
+
+        let key = (authority_set_id, round, pubkey.clone());
+        match self.precommits.get(&key) {
+            Some(first) if first.target_hash != precommit.target_hash => {
+                if self.reported.contains(&key) {
+                    return None;
+                }
+                let equivocation = StoredEquivocation {
+                    authority_set_id,
+                    round,
+                    pubkey,
+                    first: first.clone(),
+                    second: precommit,
+                };
+                self.reported.insert(key);
+                Some(equivocation)
+            }
+            Some(_) => None,
+            None => {
+                self.precommits.insert(key, precommit);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn precommit(target: u8) -> StoredPrecommit {
+        StoredPrecommit {
+            target_hash: vec![target; 32],
+            target_number: target as u32,
+            signed_message: vec![target],
+            signature: vec![target; 64],
+        }
+    }
+
+    #[test]
+    fn test_detect_equivocation() {
+        let mut monitor = GrandpaMonitor::new(Duration::from_secs(60));
+        let pubkey = vec![7u8; 32];
+
+        assert!(monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(1))
+            .is_none());
+        // The same vote seen again (e.g. in a later justification) is not an equivocation.
+        assert!(monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(1))
+            .is_none());
+        // A different target in another round or set is not an equivocation.
+        assert!(monitor
+            .observe_precommit(10, 6, pubkey.clone(), precommit(2))
+            .is_none());
+
+        let equivocation = monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(2))
+            .unwrap();
+        assert_eq!(equivocation.authority_set_id, 10);
+        assert_eq!(equivocation.round, 5);
+        assert_eq!(equivocation.pubkey, pubkey);
+        assert_eq!(equivocation.first, precommit(1));
+        assert_eq!(equivocation.second, precommit(2));
+    }
+
+    #[test]
+    fn test_report_equivocation_once() {
+        let mut monitor = GrandpaMonitor::new(Duration::from_secs(60));
+        let pubkey = vec![7u8; 32];
+
+        assert!(monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(1))
+            .is_none());
+        assert!(monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(2))
+            .is_some());
+        // The conflicting precommit seen again in a later justification is not reported again,
+        // nor is a third target from the same authority in the same round.
+        assert!(monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(2))
+            .is_none());
+        assert!(monitor
+            .observe_precommit(10, 5, pubkey.clone(), precommit(3))
+            .is_none());
+
+        // Another authority equivocating in the same round is still reported.
+        monitor.observe_precommit(10, 5, vec![8u8; 32], precommit(1));
+        assert!(monitor
+            .observe_precommit(10, 5, vec![8u8; 32], precommit(2))
+            .is_some());
+    }
+
+    #[test]
+    fn test_finality_stall() {
+        let mut monitor = GrandpaMonitor::new(Duration::ZERO);
+        monitor.observe_finalized(100);
+
+        assert_eq!(monitor.check_stall().map(|(block, _)| block), Some(100));
+        // The stall is only reported once.
+        assert!(monitor.check_stall().is_none());
+        // Seeing the same block again does not resume finality.
+        assert!(!monitor.observe_finalized(100));
+        assert!(monitor.observe_finalized(101));
+    }
+}
@@ -0,0 +1,403 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use ethers::types::{Address, Bytes, H256};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::fs;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+// Distinguishes local requests submitted in the same millisecond, e.g. by several deployments.
+static LOCAL_REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Serialize the builds of each circuit, so requests proven at once, e.g. by several deployments, do
+// not build the same artifact concurrently.
+static HEADER_RANGE_BUILD: Mutex<()> = Mutex::const_new(());
+static ROTATE_BUILD: Mutex<()> = Mutex::const_new(());
+
+/// The circuits the operator requests proofs for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Circuit {
+    HeaderRange,
+    Rotate,
+}
+
+/// Status of a proof request, as reported by the prover backend. A request that succeeded may still
+/// not have been committed on-chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RequestStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+// The response of the Succinct platform API to GET {SUCCINCT_RPC_URL}/request/{request_id}, of
+// which only the status is used.
+#[derive(Deserialize, Debug)]
+struct PlatformRequest {
+    status: PlatformRequestStatus,
+}
+
+// The statuses of a platform request. Any other status fails to parse, instead of being taken for
+// pending.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum PlatformRequestStatus {
+    Pending,
+    Running,
+    Success,
+    Failure,
+}
+
+/// Gets the status of a request from the Succinct platform API. Errors if the response does not
+/// have one of the known statuses.
+pub async fn get_platform_request_status(
+    succinct_rpc_url: &str,
+    succinct_api_key: &str,
+    request_id: &str,
+) -> Result<RequestStatus> {
+    let response: Value = reqwest::Client::new()
+        .get(format!("{}/request/{}", succinct_rpc_url, request_id))
+        .bearer_auth(succinct_api_key)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let request: PlatformRequest = serde_json::from_value(response.clone()).with_context(|| {
+        format!(
+            "Unexpected response for request {}: {}",
+            request_id, response
+        )
+    })?;
+    Ok(match request.status {
+        PlatformRequestStatus::Pending | PlatformRequestStatus::Running => RequestStatus::Pending,
+        PlatformRequestStatus::Success => RequestStatus::Succeeded,
+        PlatformRequestStatus::Failure => RequestStatus::Failed,
+    })
+}
+
+/// A proof generated by the local prover, with everything the gateway needs to fulfill it
+/// on-chain: the proof and output of the function, and the callback into the VectorX contract.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalProof {
+    pub request_id: String,
+    pub function_id: H256,
+    pub input: Bytes,
+    pub output: Bytes,
+    pub proof: Bytes,
+    pub callback_address: Address,
+    pub callback_data: Bytes,
+}
+
+// An entrypoint in succinct.json.
+#[derive(Deserialize, Clone, Debug)]
+struct Entrypoint {
+    name: String,
+    framework: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct SuccinctConfig {
+    entrypoints: Vec<Entrypoint>,
+}
+
+/// Generates proofs with locally built circuit artifacts, instead of the Succinct platform.
+///
+/// Each entrypoint in succinct.json is built into its own directory,
+/// `{LOCAL_ARTIFACTS_DIR}/{entrypoint}`, where its `buildCommand` leaves the binary and the circuit
+/// under `build`. Proofs are generated by its `proveCommand`, run where `./build` is that directory.
+/// plonky2x proofs are wrapped by the gnark wrapper at LOCAL_WRAPPER_PATH into the proof the
+/// gateway verifies, as on the platform, so the proof and output are the same as the platform's.
+#[derive(Clone, Debug)]
+pub struct LocalProver {
+    artifacts_dir: PathBuf,
+    header_range_entrypoint: String,
+    rotate_entrypoint: String,
+    wrapper_path: Option<PathBuf>,
+    entrypoints: Vec<Entrypoint>,
+}
+
+impl Default for LocalProver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalProver {
+    /// Configured with LOCAL_ARTIFACTS_DIR (default build), LOCAL_HEADER_RANGE_ENTRYPOINT and
+    /// LOCAL_ROTATE_ENTRYPOINT (default header_range and rotate, set to the dummy entrypoints for a
+    /// dummy deployment), and LOCAL_WRAPPER_PATH, the build of the gnark wrapper that plonky2x
+    /// proofs are wrapped with. Must run from the root of the repository, like succinct.json.
+    pub fn new() -> Self {
+        let config: SuccinctConfig = serde_json::from_str(
+            &std::fs::read_to_string("succinct.json").expect("Failed to read succinct.json"),
+        )
+        .expect("invalid succinct.json");
+        Self {
+            artifacts_dir: PathBuf::from(
+                env::var("LOCAL_ARTIFACTS_DIR").unwrap_or("build".to_string()),
+            ),
+            header_range_entrypoint: env::var("LOCAL_HEADER_RANGE_ENTRYPOINT")
+                .unwrap_or("header_range".to_string()),
+            rotate_entrypoint: env::var("LOCAL_ROTATE_ENTRYPOINT").unwrap_or("rotate".to_string()),
+            wrapper_path: env::var("LOCAL_WRAPPER_PATH").ok().map(PathBuf::from),
+            entrypoints: config.entrypoints,
+        }
+    }
+
+    fn entrypoint(&self, circuit: Circuit) -> &str {
+        match circuit {
+            Circuit::HeaderRange => &self.header_range_entrypoint,
+            Circuit::Rotate => &self.rotate_entrypoint,
+        }
+    }
+
+    // plonky2x proofs must be wrapped before the gateway can verify them. rustx proofs are verified
+    // as they are.
+    fn needs_wrapper(&self, circuit: Circuit) -> bool {
+        let entrypoint = self.entrypoint(circuit);
+        !self
+            .entrypoints
+            .iter()
+            .any(|e| e.name == entrypoint && e.framework != "plonky2x")
+    }
+
+    /// Checks that the proofs of the circuit can be relayed: plonky2x proofs need LOCAL_WRAPPER_PATH
+    /// to be wrapped into the proof the gateway verifies.
+    pub fn check_relayable(&self, circuit: Circuit) -> Result<()> {
+        ensure!(
+            self.wrapper_path.is_some() || !self.needs_wrapper(circuit),
+            "The {} entrypoint is a plonky2x circuit, whose local proofs cannot be verified by the gateway without LOCAL_WRAPPER_PATH",
+            self.entrypoint(circuit)
+        );
+        Ok(())
+    }
+
+    // The directory the entrypoint is built in. Its buildCommand leaves the binary and the circuit
+    // under build.
+    fn entrypoint_dir(&self, circuit: Circuit) -> PathBuf {
+        self.artifacts_dir.join(self.entrypoint(circuit))
+    }
+
+    fn proofs_dir(&self) -> PathBuf {
+        self.artifacts_dir.join("proofs")
+    }
+
+    /// Loads the artifact of the circuit, building it with the same command as the `buildCommand`
+    /// in succinct.json if it does not exist yet. Each entrypoint is built in its own directory, so
+    /// building one does not overwrite the circuit of another, and one build of each circuit runs
+    /// at a time.
+    pub async fn load_or_build(&self, circuit: Circuit) -> Result<PathBuf> {
+        let _build = match circuit {
+            Circuit::HeaderRange => HEADER_RANGE_BUILD.lock().await,
+            Circuit::Rotate => ROTATE_BUILD.lock().await,
+        };
+        let entrypoint = self.entrypoint(circuit);
+        let build_dir = self.entrypoint_dir(circuit).join("build");
+        let artifact = build_dir.join(entrypoint);
+        if fs::try_exists(&artifact).await? {
+            return Ok(artifact);
+        }
+
+        info!("Building the {} artifact.", entrypoint);
+        fs::create_dir_all(&build_dir).await?;
+        let manifest_path = fs::canonicalize("Cargo.toml").await?;
+        let status = Command::new("cargo")
+            .arg("run")
+            .arg("--manifest-path")
+            .arg(&manifest_path)
+            .args(["--bin", entrypoint, "--release", "build"])
+            .env("RUST_LOG", "debug")
+            .current_dir(self.entrypoint_dir(circuit))
+            .status()
+            .await?;
+        ensure!(
+            status.success(),
+            "Failed to build the {} artifact",
+            entrypoint
+        );
+
+        // The binary is moved last, so an artifact only exists once its circuit is built.
+        let target_dir = env::var("CARGO_TARGET_DIR").unwrap_or("target".to_string());
+        fs::rename(
+            Path::new(&target_dir).join("release").join(entrypoint),
+            &artifact,
+        )
+        .await?;
+        Ok(artifact)
+    }
+
+    /// Starts proving the input in the background and returns the request ID. The artifact of the
+    /// circuit is built first if it does not exist yet. Once proven, the proof is written to
+    /// `{LOCAL_ARTIFACTS_DIR}/proofs/{request_id}.json`, or the error to
+    /// `{LOCAL_ARTIFACTS_DIR}/proofs/{request_id}.failed` if the build or the proof fails.
+    pub async fn submit(
+        &self,
+        circuit: Circuit,
+        function_id: H256,
+        input: Vec<u8>,
+        callback_address: Address,
+        callback_data: Vec<u8>,
+    ) -> Result<String> {
+        self.check_relayable(circuit)?;
+        fs::create_dir_all(self.proofs_dir()).await?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let request_id = format!(
+            "local-{}-{}-{}",
+            self.entrypoint(circuit),
+            timestamp,
+            LOCAL_REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let prover = self.clone();
+        let local_proof = LocalProof {
+            request_id: request_id.clone(),
+            function_id,
+            input: input.into(),
+            output: Bytes::new(),
+            proof: Bytes::new(),
+            callback_address,
+            callback_data: callback_data.into(),
+        };
+        tokio::spawn(async move {
+            let id = local_proof.request_id.clone();
+            let path = prover.proofs_dir().join(format!("{}.json", id));
+            match prover.build_and_prove(circuit, local_proof, &path).await {
+                Ok(()) => info!("Local proof {} written to {}.", id, path.display()),
+                Err(e) => {
+                    // Without the failure file, the request would stay pending until it times out.
+                    error!("Local proof {} failed: {:#}", id, e);
+                    let failure_path = prover.proofs_dir().join(format!("{}.failed", id));
+                    if let Err(write_error) = fs::write(&failure_path, format!("{:#}", e)).await {
+                        error!(
+                            "Failed to write the failure of local proof {} to {}: {}",
+                            id,
+                            failure_path.display(),
+                            write_error
+                        );
+                    }
+                }
+            }
+        });
+
+        Ok(request_id)
+    }
+
+    // Builds the artifact of the circuit if needed, proves the input of the request, and writes the
+    // proof to the path.
+    async fn build_and_prove(
+        &self,
+        circuit: Circuit,
+        mut local_proof: LocalProof,
+        path: &Path,
+    ) -> Result<()> {
+        let artifact = self.load_or_build(circuit).await?;
+        let (output, proof) = self
+            .prove(
+                circuit,
+                &artifact,
+                &local_proof.request_id,
+                &local_proof.input,
+            )
+            .await?;
+        local_proof.output = output.into();
+        local_proof.proof = proof.into();
+        fs::write(path, serde_json::to_string_pretty(&local_proof)?)
+            .await
+            .context("Failed to write the local proof")?;
+        Ok(())
+    }
+
+    // Runs the proveCommand from succinct.json on the input, in a working directory for the
+    // request where ./build links to the entrypoint's build directory. Each request has its own
+    // working directory, so concurrent proofs of the same circuit do not overwrite each other's
+    // output.json. Returns the output and proof of the function.
+    async fn prove(
+        &self,
+        circuit: Circuit,
+        artifact: &Path,
+        request_id: &str,
+        input: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        fs::create_dir_all(self.proofs_dir()).await?;
+        let work_dir = fs::canonicalize(self.proofs_dir()).await?.join(request_id);
+        fs::create_dir_all(&work_dir).await?;
+        let build_dir = fs::canonicalize(artifact.parent().unwrap()).await?;
+        fs::symlink(&build_dir, work_dir.join("build")).await?;
+
+        let request = json!({
+            "type": "req_bytes",
+            "releaseId": "local",
+            "parentId": null,
+            "files": null,
+            "data": { "input": format!("0x{}", hex::encode(input)) },
+        });
+        let input_path = work_dir.join("input.json");
+        fs::write(&input_path, request.to_string()).await?;
+
+        info!("Proving {} with {}.", request_id, artifact.display());
+        let mut command = Command::new(work_dir.join("build").join(self.entrypoint(circuit)));
+        command.arg("prove").arg(&input_path);
+        if let Some(wrapper_path) = &self.wrapper_path {
+            if self.needs_wrapper(circuit) {
+                command
+                    .arg("--wrapper-path")
+                    .arg(fs::canonicalize(wrapper_path).await?);
+            }
+        }
+        let status = command
+            .env("RUST_LOG", "debug")
+            .current_dir(&work_dir)
+            .status()
+            .await?;
+        ensure!(status.success(), "Prover exited with {}", status);
+
+        let result: Value = serde_json::from_str(
+            &fs::read_to_string(work_dir.join("output.json"))
+                .await
+                .context("Prover did not write output.json")?,
+        )?;
+        let read_hex = |field: &str| -> Result<Vec<u8>> {
+            let value = result["data"][field]
+                .as_str()
+                .ok_or_else(|| anyhow!("Missing {} in output.json", field))?;
+            Ok(hex::decode(value.trim_start_matches("0x"))?)
+        };
+
+        Ok((read_hex("output")?, read_hex("proof")?))
+    }
+
+    /// Gets the status of a local request from the files written when proving finishes.
+    pub async fn get_status(&self, request_id: &str) -> RequestStatus {
+        let proofs_dir = self.proofs_dir();
+        if fs::try_exists(proofs_dir.join(format!("{}.json", request_id)))
+            .await
+            .unwrap_or(false)
+        {
+            RequestStatus::Succeeded
+        } else if fs::try_exists(proofs_dir.join(format!("{}.failed", request_id)))
+            .await
+            .unwrap_or(false)
+        {
+            RequestStatus::Failed
+        } else {
+            RequestStatus::Pending
+        }
+    }
+
+    /// Gets the proof of a local request, if it has been proven.
+    pub async fn get_proof(&self, request_id: &str) -> Option<LocalProof> {
+        let path = self.proofs_dir().join(format!("{}.json", request_id));
+        let contents = fs::read_to_string(path).await.ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+}
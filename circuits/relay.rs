@@ -0,0 +1,573 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use ethers::contract::abigen;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{
+    Address, BlockNumber, Bytes, Eip1559TransactionRequest, TransactionReceipt, H256, U256,
+};
+use log::{info, warn};
+
+use crate::prover::LocalProof;
+
+// The gateway entrypoint for fulfilling a request with a proof and calling back the requester.
+abigen!(
+    SuccinctGateway,
+    r#"[
+        function fulfillCall(bytes32 _functionId, bytes _input, bytes _output, bytes _proof, address _callbackAddress, bytes _callbackData) external
+    ]"#,
+);
+
+// Number of times a transaction is replaced with higher fees before giving up.
+const MAX_REPLACEMENTS: u32 = 5;
+
+// How often to check for the receipt of a sent transaction.
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What happened to a transaction sent by the relayer.
+#[derive(Debug)]
+pub enum RelayOutcome {
+    /// Mined, and succeeded.
+    Mined(TransactionReceipt),
+    /// Mined, but reverted.
+    Reverted(TransactionReceipt),
+    /// Its nonce was used by another transaction, so it can no longer be mined.
+    Dropped,
+    /// Not mined after MAX_REPLACEMENTS replacements. The transaction is still watched, and its
+    /// outcome is reported again once it is mined or dropped.
+    Stuck,
+}
+
+/// The outcome of a transaction, for the owner that sent it.
+#[derive(Debug)]
+pub struct RelayUpdate {
+    pub owner: Address,
+    pub nonce: U256,
+    pub outcome: RelayOutcome,
+}
+
+// A transaction that is not mined yet, with the hashes of the replacements sent for it.
+struct InFlightTransaction {
+    owner: Address,
+    tx: TypedTransaction,
+    max_fee_per_gas_cap: Option<U256>,
+    sent_tx_hashes: Vec<H256>,
+    sent_at: Instant,
+    replacements: u32,
+    stuck: bool,
+}
+
+// Bumps a fee for a replacement transaction. Nodes only accept a replacement that raises both fees
+// by at least 10%, so fees are raised by 12.5%, plus one wei so that small fees rise despite the
+// rounding down.
+fn bump_fee(fee: U256) -> U256 {
+    fee * 1125 / 1000 + 1
+}
+
+// What to do with a transaction in flight that has no receipt.
+#[derive(Debug, PartialEq)]
+enum PendingAction {
+    // Its nonce was used by another transaction, so it can no longer be mined.
+    Drop,
+    // Keep waiting for it to be mined.
+    Wait,
+    // It was replaced MAX_REPLACEMENTS times: report it as stuck, and keep watching it.
+    ReportStuck,
+    // Its fees cannot be bumped past the cap: keep waiting for the transaction that was sent.
+    AtCap,
+    // Replace it with the same nonce and the bumped fees.
+    Replace {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+impl InFlightTransaction {
+    // What to do with the transaction, given the nonce of the next transaction of the signer to be
+    // mined, and whether the confirmation timeout passed since the transaction was last sent.
+    fn next_action(&self, nonce: U256, mined_nonce: U256, timed_out: bool) -> PendingAction {
+        if nonce < mined_nonce {
+            return PendingAction::Drop;
+        }
+        if !timed_out {
+            return PendingAction::Wait;
+        }
+        if self.replacements >= MAX_REPLACEMENTS {
+            return if self.stuck {
+                PendingAction::Wait
+            } else {
+                PendingAction::ReportStuck
+            };
+        }
+
+        let TypedTransaction::Eip1559(inner) = &self.tx else {
+            return PendingAction::Wait;
+        };
+        let max_fee_per_gas = bump_fee(inner.max_fee_per_gas.unwrap_or_default());
+        if self
+            .max_fee_per_gas_cap
+            .is_some_and(|cap| max_fee_per_gas > cap)
+        {
+            return PendingAction::AtCap;
+        }
+        PendingAction::Replace {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: bump_fee(inner.max_priority_fee_per_gas.unwrap_or_default()),
+        }
+    }
+}
+
+/// Sends transactions with its own signer, instead of relying on the Succinct platform's relayer.
+///
+/// Nonces are tracked locally, so consecutive transactions do not wait on the node's pending nonce.
+/// Sending does not wait for the transaction to be mined: the transactions in flight are checked
+/// with `poll`, and their outcomes are taken by the owner that sent them with `take_updates`. Fees
+/// are estimated with EIP-1559, and a transaction that is not mined within the confirmation
+/// timeout is replaced with the same nonce and fees bumped by 12.5%, up to an optional cap on the
+/// max fee per gas.
+pub struct Relayer {
+    client: Arc<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    // The nonce of the next transaction, if known.
+    nonce: Option<U256>,
+    // The transactions that are not mined yet, by nonce.
+    in_flight: BTreeMap<U256, InFlightTransaction>,
+    // The outcomes that their owners have not taken yet.
+    updates: Vec<RelayUpdate>,
+    confirmation_timeout: Duration,
+}
+
+impl Relayer {
+    /// Configured with RELAY_CONFIRMATION_TIMEOUT_SECS (default 180), the time to wait for a
+    /// transaction to be mined before replacing it.
+    pub fn new(ethereum_rpc_url: &str, private_key: &str, chain_id: u64) -> Self {
+        let wallet = LocalWallet::from_str(private_key)
+            .expect("invalid private key")
+            .with_chain_id(chain_id);
+        let provider =
+            Provider::<Http>::try_from(ethereum_rpc_url).expect("could not connect to client");
+
+        let mut confirmation_timeout_secs = 180;
+        if let Ok(confirmation_timeout_secs_env) = env::var("RELAY_CONFIRMATION_TIMEOUT_SECS") {
+            confirmation_timeout_secs = confirmation_timeout_secs_env
+                .parse::<u64>()
+                .expect("invalid RELAY_CONFIRMATION_TIMEOUT_SECS");
+        }
+
+        Self {
+            client: Arc::new(SignerMiddleware::new(provider, wallet)),
+            nonce: None,
+            in_flight: BTreeMap::new(),
+            updates: Vec::new(),
+            confirmation_timeout: Duration::from_secs(confirmation_timeout_secs),
+        }
+    }
+
+    /// The address of the relayer's signer.
+    pub fn address(&self) -> Address {
+        self.client.address()
+    }
+
+    /// The max fee per gas a transaction sent now would pay, from the EIP-1559 estimate.
+    pub async fn estimate_max_fee_per_gas(&self) -> Result<U256> {
+        let (max_fee_per_gas, _) = self.client.estimate_eip1559_fees(None).await?;
+        Ok(max_fee_per_gas)
+    }
+
+    async fn next_nonce(&mut self) -> Result<U256> {
+        if let Some(nonce) = self.nonce {
+            return Ok(nonce);
+        }
+        let nonce = self
+            .client
+            .get_transaction_count(self.address(), Some(BlockNumber::Pending.into()))
+            .await?;
+        self.nonce = Some(nonce);
+        Ok(nonce)
+    }
+
+    /// Sends a transaction that fulfills a locally generated proof through the gateway, which
+    /// verifies it and calls back into the contract. Returns its nonce, as `send` does.
+    pub async fn fulfill(
+        &mut self,
+        owner: Address,
+        gateway: Address,
+        proof: &LocalProof,
+        max_fee_per_gas_cap: Option<U256>,
+    ) -> Result<U256> {
+        let fulfill_call = FulfillCallCall {
+            function_id: proof.function_id.0,
+            input: proof.input.clone(),
+            output: proof.output.clone(),
+            proof: proof.proof.clone(),
+            callback_address: proof.callback_address,
+            callback_data: proof.callback_data.clone(),
+        };
+        self.send(
+            owner,
+            gateway,
+            ethers::abi::AbiEncode::encode(fulfill_call).into(),
+            max_fee_per_gas_cap,
+        )
+        .await
+    }
+
+    /// Sends a transaction with the calldata to the address on behalf of the owner, and returns
+    /// its nonce without waiting for it to be mined. The max fee per gas never goes above the cap,
+    /// if any. Fails if the transaction would revert.
+    pub async fn send(
+        &mut self,
+        owner: Address,
+        to: Address,
+        calldata: Bytes,
+        max_fee_per_gas_cap: Option<U256>,
+    ) -> Result<U256> {
+        let nonce = self.next_nonce().await?;
+        let (mut max_fee_per_gas, mut max_priority_fee_per_gas) =
+            self.client.estimate_eip1559_fees(None).await?;
+        if let Some(cap) = max_fee_per_gas_cap {
+            max_fee_per_gas = max_fee_per_gas.min(cap);
+            max_priority_fee_per_gas = max_priority_fee_per_gas.min(cap);
+        }
+
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(self.address())
+            .to(to)
+            .data(calldata)
+            .nonce(nonce)
+            .max_fee_per_gas(max_fee_per_gas)
+            .max_priority_fee_per_gas(max_priority_fee_per_gas)
+            .into();
+        // Estimating the gas also simulates the transaction, so a call that would revert is not
+        // sent.
+        let gas = self.client.estimate_gas(&tx, None).await?;
+        tx.set_gas(gas * 6 / 5);
+
+        match self.client.send_transaction(tx.clone(), None).await {
+            Ok(pending_tx) => {
+                info!(
+                    "Sent transaction {:?} with nonce {} and max fee {} wei.",
+                    pending_tx.tx_hash(),
+                    nonce,
+                    max_fee_per_gas
+                );
+                self.nonce = Some(nonce + 1);
+                self.in_flight.insert(
+                    nonce,
+                    InFlightTransaction {
+                        owner,
+                        sent_tx_hashes: vec![pending_tx.tx_hash()],
+                        tx,
+                        max_fee_per_gas_cap,
+                        sent_at: Instant::now(),
+                        replacements: 0,
+                        stuck: false,
+                    },
+                );
+                Ok(nonce)
+            }
+            Err(e) => {
+                // The nonce may be out of sync with the node, so refetch it next time, unless
+                // transactions before it are still in flight.
+                if self.in_flight.is_empty() {
+                    self.nonce = None;
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Checks the transactions in flight. A transaction is done once it or one of its
+    /// replacements is mined, or its nonce is used by another transaction. A transaction that is
+    /// not mined within the confirmation timeout is replaced with fees bumped by 12.5%, up to
+    /// MAX_REPLACEMENTS times, and is then reported as stuck but still watched, as a replacement
+    /// may still be mined.
+    pub async fn poll(&mut self) -> Result<()> {
+        if self.in_flight.is_empty() {
+            return Ok(());
+        }
+        // Fetched before the receipts, so a nonce below it without a receipt was used by another
+        // transaction.
+        let mined_nonce = self
+            .client
+            .get_transaction_count(self.address(), Some(BlockNumber::Latest.into()))
+            .await?;
+
+        let nonces: Vec<U256> = self.in_flight.keys().copied().collect();
+        for nonce in nonces {
+            let in_flight = self.in_flight.get_mut(&nonce).unwrap();
+            let mut receipt = None;
+            for tx_hash in in_flight.sent_tx_hashes.iter() {
+                receipt = self.client.get_transaction_receipt(*tx_hash).await?;
+                if receipt.is_some() {
+                    break;
+                }
+            }
+
+            if let Some(receipt) = receipt {
+                let owner = in_flight.owner;
+                self.in_flight.remove(&nonce);
+                let outcome = if receipt.status == Some(1.into()) {
+                    info!(
+                        "Transaction {:?} mined in block {:?}.",
+                        receipt.transaction_hash, receipt.block_number
+                    );
+                    RelayOutcome::Mined(receipt)
+                } else {
+                    warn!("Transaction {:?} reverted.", receipt.transaction_hash);
+                    RelayOutcome::Reverted(receipt)
+                };
+                self.updates.push(RelayUpdate {
+                    owner,
+                    nonce,
+                    outcome,
+                });
+                continue;
+            }
+
+            let timed_out = in_flight.sent_at.elapsed() >= self.confirmation_timeout;
+            let (max_fee_per_gas, max_priority_fee_per_gas) = match in_flight.next_action(
+                nonce,
+                mined_nonce,
+                timed_out,
+            ) {
+                PendingAction::Drop => {
+                    warn!(
+                        "Transaction with nonce {} was replaced by another transaction.",
+                        nonce
+                    );
+                    let owner = in_flight.owner;
+                    self.in_flight.remove(&nonce);
+                    self.updates.push(RelayUpdate {
+                        owner,
+                        nonce,
+                        outcome: RelayOutcome::Dropped,
+                    });
+                    continue;
+                }
+                PendingAction::Wait => continue,
+                PendingAction::ReportStuck => {
+                    warn!(
+                        "Transaction with nonce {} not mined after {} replacements.",
+                        nonce, in_flight.replacements
+                    );
+                    in_flight.stuck = true;
+                    self.updates.push(RelayUpdate {
+                        owner: in_flight.owner,
+                        nonce,
+                        outcome: RelayOutcome::Stuck,
+                    });
+                    continue;
+                }
+                // Past the cap, keep waiting for the transaction that was sent instead, which
+                // does not count as a replacement.
+                PendingAction::AtCap => {
+                    in_flight.sent_at = Instant::now();
+                    warn!(
+                            "Transaction with nonce {} not mined after {} seconds, but its max fee cannot be raised past the cap.",
+                            nonce,
+                            self.confirmation_timeout.as_secs()
+                        );
+                    continue;
+                }
+                PendingAction::Replace {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                } => (max_fee_per_gas, max_priority_fee_per_gas),
+            };
+
+            in_flight.sent_at = Instant::now();
+            in_flight.replacements += 1;
+            if let TypedTransaction::Eip1559(inner) = &mut in_flight.tx {
+                inner.max_fee_per_gas = Some(max_fee_per_gas);
+                inner.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            }
+            warn!(
+                "Transaction with nonce {} not mined after {} seconds, replacing it with max fee {} wei.",
+                nonce,
+                self.confirmation_timeout.as_secs(),
+                max_fee_per_gas
+            );
+            match self
+                .client
+                .send_transaction(in_flight.tx.clone(), None)
+                .await
+            {
+                Ok(pending_tx) => {
+                    info!(
+                        "Sent transaction {:?} with nonce {} and max fee {} wei.",
+                        pending_tx.tx_hash(),
+                        nonce,
+                        max_fee_per_gas
+                    );
+                    in_flight.sent_tx_hashes.push(pending_tx.tx_hash());
+                }
+                // A replacement can be rejected if an earlier transaction was mined meanwhile,
+                // which is found on the next poll.
+                Err(e) => warn!("Failed to replace transaction with nonce {}: {}", nonce, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the outcomes of the transactions sent on behalf of the owner.
+    pub fn take_updates(&mut self, owner: Address) -> Vec<RelayUpdate> {
+        let (taken, kept) = std::mem::take(&mut self.updates)
+            .into_iter()
+            .partition(|update| update.owner == owner);
+        self.updates = kept;
+        taken
+    }
+
+    /// Sends a transaction with the calldata to the address, as `send` does, and waits until it is
+    /// mined. Fails if it reverted, was dropped, or is stuck.
+    pub async fn relay(
+        &mut self,
+        to: Address,
+        calldata: Bytes,
+        max_fee_per_gas_cap: Option<U256>,
+    ) -> Result<TransactionReceipt> {
+        let owner = self.address();
+        let nonce = self.send(owner, to, calldata, max_fee_per_gas_cap).await?;
+        loop {
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+            self.poll().await?;
+            let Some(update) = self
+                .take_updates(owner)
+                .into_iter()
+                .find(|update| update.nonce == nonce)
+            else {
+                continue;
+            };
+            return match update.outcome {
+                RelayOutcome::Mined(receipt) => Ok(receipt),
+                RelayOutcome::Reverted(receipt) => Err(anyhow!(
+                    "Transaction {:?} reverted",
+                    receipt.transaction_hash
+                )),
+                RelayOutcome::Dropped => Err(anyhow!(
+                    "Transaction with nonce {} was replaced by another transaction",
+                    nonce
+                )),
+                RelayOutcome::Stuck => Err(anyhow!(
+                    "Transaction with nonce {} not mined after {} replacements",
+                    nonce,
+                    MAX_REPLACEMENTS
+                )),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_flight(
+        max_fee_per_gas: u64,
+        max_priority_fee_per_gas: u64,
+        max_fee_per_gas_cap: Option<u64>,
+    ) -> InFlightTransaction {
+        InFlightTransaction {
+            owner: Address::zero(),
+            tx: Eip1559TransactionRequest::new()
+                .max_fee_per_gas(max_fee_per_gas)
+                .max_priority_fee_per_gas(max_priority_fee_per_gas)
+                .into(),
+            max_fee_per_gas_cap: max_fee_per_gas_cap.map(U256::from),
+            sent_tx_hashes: Vec::new(),
+            sent_at: Instant::now(),
+            replacements: 0,
+            stuck: false,
+        }
+    }
+
+    #[test]
+    fn test_bump_fee() {
+        assert_eq!(bump_fee(U256::zero()), U256::from(1));
+        assert_eq!(bump_fee(U256::from(7)), U256::from(8));
+        assert_eq!(bump_fee(U256::from(1000)), U256::from(1126));
+
+        // Every bump raises the fee by at least the 10% nodes require of replacements.
+        for fee in [0u64, 1, 7, 9, 10, 11, 999, 30_000_000_000] {
+            let fee = U256::from(fee);
+            assert!(bump_fee(fee) * 10 >= fee * 11);
+            assert!(bump_fee(fee) > fee);
+        }
+    }
+
+    #[test]
+    fn test_replacement() {
+        let nonce = U256::from(5);
+
+        // Replaced with both fees bumped once the confirmation timeout passed.
+        let tx = in_flight(1000, 100, None);
+        assert_eq!(tx.next_action(nonce, nonce, false), PendingAction::Wait);
+        assert_eq!(
+            tx.next_action(nonce, nonce, true),
+            PendingAction::Replace {
+                max_fee_per_gas: U256::from(1126),
+                max_priority_fee_per_gas: U256::from(113),
+            }
+        );
+
+        // The bumped max fee may reach the cap, but not go past it.
+        let tx = in_flight(1000, 100, Some(1126));
+        assert!(matches!(
+            tx.next_action(nonce, nonce, true),
+            PendingAction::Replace { .. }
+        ));
+        let tx = in_flight(1000, 100, Some(1125));
+        assert_eq!(tx.next_action(nonce, nonce, true), PendingAction::AtCap);
+    }
+
+    #[test]
+    fn test_replacement_cap() {
+        let nonce = U256::from(5);
+        let mut tx = in_flight(1000, 100, None);
+        for _ in 0..MAX_REPLACEMENTS {
+            let PendingAction::Replace {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } = tx.next_action(nonce, nonce, true)
+            else {
+                panic!("expected a replacement");
+            };
+            tx.tx.as_eip1559_mut().unwrap().max_fee_per_gas = Some(max_fee_per_gas);
+            tx.tx.as_eip1559_mut().unwrap().max_priority_fee_per_gas =
+                Some(max_priority_fee_per_gas);
+            tx.replacements += 1;
+        }
+
+        // After MAX_REPLACEMENTS replacements, the transaction is reported as stuck once.
+        assert_eq!(
+            tx.next_action(nonce, nonce, true),
+            PendingAction::ReportStuck
+        );
+        tx.stuck = true;
+        assert_eq!(tx.next_action(nonce, nonce, true), PendingAction::Wait);
+    }
+
+    #[test]
+    fn test_dropped() {
+        let nonce = U256::from(5);
+        let mut tx = in_flight(1000, 100, None);
+
+        // A nonce below the next nonce to be mined, without a receipt, was used by another
+        // transaction, whatever the state of the transaction.
+        assert_eq!(tx.next_action(nonce, nonce + 1, false), PendingAction::Drop);
+        tx.replacements = MAX_REPLACEMENTS;
+        tx.stuck = true;
+        assert_eq!(tx.next_action(nonce, nonce + 1, true), PendingAction::Drop);
+
+        // The nonce of the next transaction to be mined is not dropped.
+        assert_eq!(tx.next_action(nonce, nonce, false), PendingAction::Wait);
+        assert_eq!(tx.next_action(nonce + 1, nonce, false), PendingAction::Wait);
+    }
+}
@@ -33,7 +33,10 @@ impl<
 
         let mut data_fetcher = RpcDataFetcher::new().await;
 
-        let epoch_end_block_nb = data_fetcher.last_justified_block(authority_set_id).await;
+        let epoch_end_block_nb = data_fetcher
+            .last_justified_block(authority_set_id)
+            .await
+            .expect("Failed to find the last justified block on Avail.");
 
         let rotate_data = data_fetcher
             .get_header_rotate::<HEADER_LENGTH, MAX_AUTHORITY_SET_SIZE>(epoch_end_block_nb)